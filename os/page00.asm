@@ -12,9 +12,34 @@
     trap TRAP_RESET
     rst 00h
 
+.seek $0008
+    ; rst 08h is OP1ToOP2
+    trap _OP1ToOP2
+    ret
+
+.seek $0010
+    ; rst 10h is FindSym
+    trap _FindSym
+    ret
+
+.seek $0018
+    ; rst 18h is PushRealO1
+    trap _PushRealO1
+    ret
+
+.seek $0020
+    ; rst 20h is Mov9ToOP1
+    trap _Mov9ToOP1
+    ret
+
 .seek $0028
     jr bcall_handler
 
+.seek $0030
+    ; rst 30h is FPAdd
+    trap _FPAdd
+    ret
+
 .seek $0038
     di
     trap TRAP_OS_INTERRUPT
@@ -22,13 +47,20 @@
     ; Acknowledge all interrupts by disabling them
     xor a
     out (3), a
-    ; Enable interrupts again
-    ld a, $17
+    ; Enable interrupts again: ON and timer 1, like TI-OS
+    ld a, $0B
     out (3), a
     ex af, af'
     ei
     reti
 
+.seek $0058
+    ; Run BASIC programs a statement at a time. The emulator returns from the
+    ; loop when the program ends, or calls assembly programs that return here.
+basic_loop:
+    trap TRAP_BASIC_STEP
+    jr basic_loop
+
 ; Implement bcalls.
 ;
 ; See https://wikiti.brandonw.net/index.php?title=83Plus:OS:How_BCALLs_work for
@@ -79,3 +79,110 @@ VPutMap: trap _VPutMap \ ret        ; MULTIPAGE:EXPORT:VPutMap
 GrBufCpy: trap _GrBufCpy \ ret      ; MULTIPAGE:EXPORT:GrBufCpy
 MemSet: trap _MemSet \ ret          ; MULTIPAGE:EXPORT:MemSet
 DivHLBy10: trap _DivHLBy10 \ ret    ; MULTIPAGE:EXPORT:DivHLBy10
+ChkFindSym: trap _ChkFindSym \ ret  ; MULTIPAGE:EXPORT:ChkFindSym
+FindSym: trap _FindSym \ ret        ; MULTIPAGE:EXPORT:FindSym
+
+; Floating point
+Trunc: trap _Trunc \ ret            ; MULTIPAGE:EXPORT:Trunc
+Times2: trap _Times2 \ ret          ; MULTIPAGE:EXPORT:Times2
+Plus1: trap _Plus1 \ ret            ; MULTIPAGE:EXPORT:Plus1
+Minus1: trap _Minus1 \ ret          ; MULTIPAGE:EXPORT:Minus1
+FPSub: trap _FPSub \ ret            ; MULTIPAGE:EXPORT:FPSub
+FPAdd: trap _FPAdd \ ret            ; MULTIPAGE:EXPORT:FPAdd
+FPSquare: trap _FPSquare \ ret      ; MULTIPAGE:EXPORT:FPSquare
+FPMult: trap _FPMult \ ret          ; MULTIPAGE:EXPORT:FPMult
+InvOP1S: trap _InvOP1S \ ret        ; MULTIPAGE:EXPORT:InvOP1S
+InvOP2S: trap _InvOP2S \ ret        ; MULTIPAGE:EXPORT:InvOP2S
+Frac: trap _Frac \ ret              ; MULTIPAGE:EXPORT:Frac
+FPRecip: trap _FPRecip \ ret        ; MULTIPAGE:EXPORT:FPRecip
+FPDiv: trap _FPDiv \ ret            ; MULTIPAGE:EXPORT:FPDiv
+Int: trap _Int \ ret                ; MULTIPAGE:EXPORT:Int
+CkOP1Real: trap _CkOP1Real \ ret    ; MULTIPAGE:EXPORT:CkOP1Real
+CpOP1OP2: trap _CpOP1OP2 \ ret      ; MULTIPAGE:EXPORT:CpOP1OP2
+OP3ToOP4: trap _OP3ToOP4 \ ret      ; MULTIPAGE:EXPORT:OP3ToOP4
+OP1ToOP4: trap _OP1ToOP4 \ ret      ; MULTIPAGE:EXPORT:OP1ToOP4
+OP2ToOP4: trap _OP2ToOP4 \ ret      ; MULTIPAGE:EXPORT:OP2ToOP4
+OP4ToOP2: trap _OP4ToOP2 \ ret      ; MULTIPAGE:EXPORT:OP4ToOP2
+OP1ToOP3: trap _OP1ToOP3 \ ret      ; MULTIPAGE:EXPORT:OP1ToOP3
+OP5ToOP2: trap _OP5ToOP2 \ ret      ; MULTIPAGE:EXPORT:OP5ToOP2
+OP5ToOP6: trap _OP5ToOP6 \ ret      ; MULTIPAGE:EXPORT:OP5ToOP6
+OP5ToOP4: trap _OP5ToOP4 \ ret      ; MULTIPAGE:EXPORT:OP5ToOP4
+OP1ToOP2: trap _OP1ToOP2 \ ret      ; MULTIPAGE:EXPORT:OP1ToOP2
+OP6ToOP2: trap _OP6ToOP2 \ ret      ; MULTIPAGE:EXPORT:OP6ToOP2
+OP6ToOP1: trap _OP6ToOP1 \ ret      ; MULTIPAGE:EXPORT:OP6ToOP1
+OP4ToOP1: trap _OP4ToOP1 \ ret      ; MULTIPAGE:EXPORT:OP4ToOP1
+OP5ToOP1: trap _OP5ToOP1 \ ret      ; MULTIPAGE:EXPORT:OP5ToOP1
+OP3ToOP1: trap _OP3ToOP1 \ ret      ; MULTIPAGE:EXPORT:OP3ToOP1
+OP6ToOP5: trap _OP6ToOP5 \ ret      ; MULTIPAGE:EXPORT:OP6ToOP5
+OP4ToOP5: trap _OP4ToOP5 \ ret      ; MULTIPAGE:EXPORT:OP4ToOP5
+OP3ToOP5: trap _OP3ToOP5 \ ret      ; MULTIPAGE:EXPORT:OP3ToOP5
+OP2ToOP5: trap _OP2ToOP5 \ ret      ; MULTIPAGE:EXPORT:OP2ToOP5
+OP2ToOP6: trap _OP2ToOP6 \ ret      ; MULTIPAGE:EXPORT:OP2ToOP6
+OP1ToOP6: trap _OP1ToOP6 \ ret      ; MULTIPAGE:EXPORT:OP1ToOP6
+OP1ToOP5: trap _OP1ToOP5 \ ret      ; MULTIPAGE:EXPORT:OP1ToOP5
+OP2ToOP1: trap _OP2ToOP1 \ ret      ; MULTIPAGE:EXPORT:OP2ToOP1
+Mov11B: trap _Mov11B \ ret          ; MULTIPAGE:EXPORT:Mov11B
+Mov10B: trap _Mov10B \ ret          ; MULTIPAGE:EXPORT:Mov10B
+Mov9B: trap _Mov9B \ ret            ; MULTIPAGE:EXPORT:Mov9B
+OP2ToOP3: trap _OP2ToOP3 \ ret      ; MULTIPAGE:EXPORT:OP2ToOP3
+OP4ToOP3: trap _OP4ToOP3 \ ret      ; MULTIPAGE:EXPORT:OP4ToOP3
+OP5ToOP3: trap _OP5ToOP3 \ ret      ; MULTIPAGE:EXPORT:OP5ToOP3
+OP4ToOP6: trap _OP4ToOP6 \ ret      ; MULTIPAGE:EXPORT:OP4ToOP6
+Mov9ToOP1: trap _Mov9ToOP1 \ ret    ; MULTIPAGE:EXPORT:Mov9ToOP1
+Mov9ToOP2: trap _Mov9ToOP2 \ ret    ; MULTIPAGE:EXPORT:Mov9ToOP2
+MovFrOP1: trap _MovFrOP1 \ ret      ; MULTIPAGE:EXPORT:MovFrOP1
+OP4Set1: trap _OP4Set1 \ ret        ; MULTIPAGE:EXPORT:OP4Set1
+OP3Set1: trap _OP3Set1 \ ret        ; MULTIPAGE:EXPORT:OP3Set1
+OP2Set4: trap _OP2Set4 \ ret        ; MULTIPAGE:EXPORT:OP2Set4
+OP2Set3: trap _OP2Set3 \ ret        ; MULTIPAGE:EXPORT:OP2Set3
+OP1Set1: trap _OP1Set1 \ ret        ; MULTIPAGE:EXPORT:OP1Set1
+OP1Set4: trap _OP1Set4 \ ret        ; MULTIPAGE:EXPORT:OP1Set4
+OP1Set3: trap _OP1Set3 \ ret        ; MULTIPAGE:EXPORT:OP1Set3
+OP3Set2: trap _OP3Set2 \ ret        ; MULTIPAGE:EXPORT:OP3Set2
+OP1Set2: trap _OP1Set2 \ ret        ; MULTIPAGE:EXPORT:OP1Set2
+OP2Set2: trap _OP2Set2 \ ret        ; MULTIPAGE:EXPORT:OP2Set2
+OP2Set1: trap _OP2Set1 \ ret        ; MULTIPAGE:EXPORT:OP2Set1
+OP5Set0: trap _OP5Set0 \ ret        ; MULTIPAGE:EXPORT:OP5Set0
+OP4Set0: trap _OP4Set0 \ ret        ; MULTIPAGE:EXPORT:OP4Set0
+OP3Set0: trap _OP3Set0 \ ret        ; MULTIPAGE:EXPORT:OP3Set0
+OP2Set0: trap _OP2Set0 \ ret        ; MULTIPAGE:EXPORT:OP2Set0
+OP1Set0: trap _OP1Set0 \ ret        ; MULTIPAGE:EXPORT:OP1Set0
+ZeroOP1: trap _ZeroOP1 \ ret        ; MULTIPAGE:EXPORT:ZeroOP1
+ZeroOP2: trap _ZeroOP2 \ ret        ; MULTIPAGE:EXPORT:ZeroOP2
+ZeroOP3: trap _ZeroOP3 \ ret        ; MULTIPAGE:EXPORT:ZeroOP3
+OP2ExOP6: trap _OP2ExOP6 \ ret      ; MULTIPAGE:EXPORT:OP2ExOP6
+OP5ExOP6: trap _OP5ExOP6 \ ret      ; MULTIPAGE:EXPORT:OP5ExOP6
+OP1ExOP5: trap _OP1ExOP5 \ ret      ; MULTIPAGE:EXPORT:OP1ExOP5
+OP1ExOP6: trap _OP1ExOP6 \ ret      ; MULTIPAGE:EXPORT:OP1ExOP6
+OP2ExOP4: trap _OP2ExOP4 \ ret      ; MULTIPAGE:EXPORT:OP2ExOP4
+OP2ExOP5: trap _OP2ExOP5 \ ret      ; MULTIPAGE:EXPORT:OP2ExOP5
+OP1ExOP3: trap _OP1ExOP3 \ ret      ; MULTIPAGE:EXPORT:OP1ExOP3
+OP1ExOP4: trap _OP1ExOP4 \ ret      ; MULTIPAGE:EXPORT:OP1ExOP4
+OP1ExOP2: trap _OP1ExOP2 \ ret      ; MULTIPAGE:EXPORT:OP1ExOP2
+CkOP1FP0: trap _CkOP1FP0 \ ret      ; MULTIPAGE:EXPORT:CkOP1FP0
+CkOP1Pos: trap _CkOP1Pos \ ret      ; MULTIPAGE:EXPORT:CkOP1Pos
+PopOP5: trap _PopOP5 \ ret          ; MULTIPAGE:EXPORT:PopOP5
+PopOP3: trap _PopOP3 \ ret          ; MULTIPAGE:EXPORT:PopOP3
+PopOP1: trap _PopOP1 \ ret          ; MULTIPAGE:EXPORT:PopOP1
+PopRealO6: trap _PopRealO6 \ ret    ; MULTIPAGE:EXPORT:PopRealO6
+PopRealO5: trap _PopRealO5 \ ret    ; MULTIPAGE:EXPORT:PopRealO5
+PopRealO4: trap _PopRealO4 \ ret    ; MULTIPAGE:EXPORT:PopRealO4
+PopRealO3: trap _PopRealO3 \ ret    ; MULTIPAGE:EXPORT:PopRealO3
+PopRealO2: trap _PopRealO2 \ ret    ; MULTIPAGE:EXPORT:PopRealO2
+PopRealO1: trap _PopRealO1 \ ret    ; MULTIPAGE:EXPORT:PopRealO1
+PopReal: trap _PopReal \ ret        ; MULTIPAGE:EXPORT:PopReal
+PushRealO6: trap _PushRealO6 \ ret  ; MULTIPAGE:EXPORT:PushRealO6
+PushRealO5: trap _PushRealO5 \ ret  ; MULTIPAGE:EXPORT:PushRealO5
+PushRealO4: trap _PushRealO4 \ ret  ; MULTIPAGE:EXPORT:PushRealO4
+PushRealO3: trap _PushRealO3 \ ret  ; MULTIPAGE:EXPORT:PushRealO3
+PushRealO2: trap _PushRealO2 \ ret  ; MULTIPAGE:EXPORT:PushRealO2
+PushRealO1: trap _PushRealO1 \ ret  ; MULTIPAGE:EXPORT:PushRealO1
+PushReal: trap _PushReal \ ret      ; MULTIPAGE:EXPORT:PushReal
+PushOP5: trap _PushOP5 \ ret        ; MULTIPAGE:EXPORT:PushOP5
+PushOP3: trap _PushOP3 \ ret        ; MULTIPAGE:EXPORT:PushOP3
+PushOP1: trap _PushOP1 \ ret        ; MULTIPAGE:EXPORT:PushOP1
+SetXXOP1: trap _SetXXOP1 \ ret      ; MULTIPAGE:EXPORT:SetXXOP1
+SetXXOP2: trap _SetXXOP2 \ ret      ; MULTIPAGE:EXPORT:SetXXOP2
+SetXXXXOP2: trap _SetXXXXOP2 \ ret  ; MULTIPAGE:EXPORT:SetXXXXOP2
+Mov18B: trap _Mov18B \ ret          ; MULTIPAGE:EXPORT:Mov18B
+ConvOP1: trap _ConvOP1 \ ret        ; MULTIPAGE:EXPORT:ConvOP1
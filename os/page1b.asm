@@ -17,6 +17,113 @@ VECTOR(_MemSet, MemSet_PAGE, MemSet)            ; MULTIPAGE:IMPORT:MemSet
 VECTOR(_GetCSC, GetCSC_PAGE, GetCSC)            ; MULTIPAGE:IMPORT:GetCSC
 VECTOR(_DivHLBy10, DivHLBy10_PAGE, DivHLBy10)   ; MULTIPAGE:IMPORT:DivHLBy10
 VECTOR(_GrBufClr, GrBufClr_PAGE, GrBufClr)      ; MULTIPAGE:IMPORT:GrBufClr
+VECTOR(_ChkFindSym, ChkFindSym_PAGE, ChkFindSym); MULTIPAGE:IMPORT:ChkFindSym
+VECTOR(_FindSym, FindSym_PAGE, FindSym)         ; MULTIPAGE:IMPORT:FindSym
+
+; Floating point
+VECTOR(_Trunc, Trunc_PAGE, Trunc)               ; MULTIPAGE:IMPORT:Trunc
+VECTOR(_Times2, Times2_PAGE, Times2)            ; MULTIPAGE:IMPORT:Times2
+VECTOR(_Plus1, Plus1_PAGE, Plus1)               ; MULTIPAGE:IMPORT:Plus1
+VECTOR(_Minus1, Minus1_PAGE, Minus1)            ; MULTIPAGE:IMPORT:Minus1
+VECTOR(_FPSub, FPSub_PAGE, FPSub)               ; MULTIPAGE:IMPORT:FPSub
+VECTOR(_FPAdd, FPAdd_PAGE, FPAdd)               ; MULTIPAGE:IMPORT:FPAdd
+VECTOR(_FPSquare, FPSquare_PAGE, FPSquare)      ; MULTIPAGE:IMPORT:FPSquare
+VECTOR(_FPMult, FPMult_PAGE, FPMult)            ; MULTIPAGE:IMPORT:FPMult
+VECTOR(_InvOP1S, InvOP1S_PAGE, InvOP1S)         ; MULTIPAGE:IMPORT:InvOP1S
+VECTOR(_InvOP2S, InvOP2S_PAGE, InvOP2S)         ; MULTIPAGE:IMPORT:InvOP2S
+VECTOR(_Frac, Frac_PAGE, Frac)                  ; MULTIPAGE:IMPORT:Frac
+VECTOR(_FPRecip, FPRecip_PAGE, FPRecip)         ; MULTIPAGE:IMPORT:FPRecip
+VECTOR(_FPDiv, FPDiv_PAGE, FPDiv)               ; MULTIPAGE:IMPORT:FPDiv
+VECTOR(_Int, Int_PAGE, Int)                     ; MULTIPAGE:IMPORT:Int
+VECTOR(_CkOP1Real, CkOP1Real_PAGE, CkOP1Real)   ; MULTIPAGE:IMPORT:CkOP1Real
+VECTOR(_CpOP1OP2, CpOP1OP2_PAGE, CpOP1OP2)      ; MULTIPAGE:IMPORT:CpOP1OP2
+VECTOR(_OP3ToOP4, OP3ToOP4_PAGE, OP3ToOP4)      ; MULTIPAGE:IMPORT:OP3ToOP4
+VECTOR(_OP1ToOP4, OP1ToOP4_PAGE, OP1ToOP4)      ; MULTIPAGE:IMPORT:OP1ToOP4
+VECTOR(_OP2ToOP4, OP2ToOP4_PAGE, OP2ToOP4)      ; MULTIPAGE:IMPORT:OP2ToOP4
+VECTOR(_OP4ToOP2, OP4ToOP2_PAGE, OP4ToOP2)      ; MULTIPAGE:IMPORT:OP4ToOP2
+VECTOR(_OP1ToOP3, OP1ToOP3_PAGE, OP1ToOP3)      ; MULTIPAGE:IMPORT:OP1ToOP3
+VECTOR(_OP5ToOP2, OP5ToOP2_PAGE, OP5ToOP2)      ; MULTIPAGE:IMPORT:OP5ToOP2
+VECTOR(_OP5ToOP6, OP5ToOP6_PAGE, OP5ToOP6)      ; MULTIPAGE:IMPORT:OP5ToOP6
+VECTOR(_OP5ToOP4, OP5ToOP4_PAGE, OP5ToOP4)      ; MULTIPAGE:IMPORT:OP5ToOP4
+VECTOR(_OP1ToOP2, OP1ToOP2_PAGE, OP1ToOP2)      ; MULTIPAGE:IMPORT:OP1ToOP2
+VECTOR(_OP6ToOP2, OP6ToOP2_PAGE, OP6ToOP2)      ; MULTIPAGE:IMPORT:OP6ToOP2
+VECTOR(_OP6ToOP1, OP6ToOP1_PAGE, OP6ToOP1)      ; MULTIPAGE:IMPORT:OP6ToOP1
+VECTOR(_OP4ToOP1, OP4ToOP1_PAGE, OP4ToOP1)      ; MULTIPAGE:IMPORT:OP4ToOP1
+VECTOR(_OP5ToOP1, OP5ToOP1_PAGE, OP5ToOP1)      ; MULTIPAGE:IMPORT:OP5ToOP1
+VECTOR(_OP3ToOP1, OP3ToOP1_PAGE, OP3ToOP1)      ; MULTIPAGE:IMPORT:OP3ToOP1
+VECTOR(_OP6ToOP5, OP6ToOP5_PAGE, OP6ToOP5)      ; MULTIPAGE:IMPORT:OP6ToOP5
+VECTOR(_OP4ToOP5, OP4ToOP5_PAGE, OP4ToOP5)      ; MULTIPAGE:IMPORT:OP4ToOP5
+VECTOR(_OP3ToOP5, OP3ToOP5_PAGE, OP3ToOP5)      ; MULTIPAGE:IMPORT:OP3ToOP5
+VECTOR(_OP2ToOP5, OP2ToOP5_PAGE, OP2ToOP5)      ; MULTIPAGE:IMPORT:OP2ToOP5
+VECTOR(_OP2ToOP6, OP2ToOP6_PAGE, OP2ToOP6)      ; MULTIPAGE:IMPORT:OP2ToOP6
+VECTOR(_OP1ToOP6, OP1ToOP6_PAGE, OP1ToOP6)      ; MULTIPAGE:IMPORT:OP1ToOP6
+VECTOR(_OP1ToOP5, OP1ToOP5_PAGE, OP1ToOP5)      ; MULTIPAGE:IMPORT:OP1ToOP5
+VECTOR(_OP2ToOP1, OP2ToOP1_PAGE, OP2ToOP1)      ; MULTIPAGE:IMPORT:OP2ToOP1
+VECTOR(_Mov11B, Mov11B_PAGE, Mov11B)            ; MULTIPAGE:IMPORT:Mov11B
+VECTOR(_Mov10B, Mov10B_PAGE, Mov10B)            ; MULTIPAGE:IMPORT:Mov10B
+VECTOR(_Mov9B, Mov9B_PAGE, Mov9B)               ; MULTIPAGE:IMPORT:Mov9B
+VECTOR(_OP2ToOP3, OP2ToOP3_PAGE, OP2ToOP3)      ; MULTIPAGE:IMPORT:OP2ToOP3
+VECTOR(_OP4ToOP3, OP4ToOP3_PAGE, OP4ToOP3)      ; MULTIPAGE:IMPORT:OP4ToOP3
+VECTOR(_OP5ToOP3, OP5ToOP3_PAGE, OP5ToOP3)      ; MULTIPAGE:IMPORT:OP5ToOP3
+VECTOR(_OP4ToOP6, OP4ToOP6_PAGE, OP4ToOP6)      ; MULTIPAGE:IMPORT:OP4ToOP6
+VECTOR(_Mov9ToOP1, Mov9ToOP1_PAGE, Mov9ToOP1)   ; MULTIPAGE:IMPORT:Mov9ToOP1
+VECTOR(_Mov9ToOP2, Mov9ToOP2_PAGE, Mov9ToOP2)   ; MULTIPAGE:IMPORT:Mov9ToOP2
+VECTOR(_MovFrOP1, MovFrOP1_PAGE, MovFrOP1)      ; MULTIPAGE:IMPORT:MovFrOP1
+VECTOR(_OP4Set1, OP4Set1_PAGE, OP4Set1)         ; MULTIPAGE:IMPORT:OP4Set1
+VECTOR(_OP3Set1, OP3Set1_PAGE, OP3Set1)         ; MULTIPAGE:IMPORT:OP3Set1
+VECTOR(_OP2Set4, OP2Set4_PAGE, OP2Set4)         ; MULTIPAGE:IMPORT:OP2Set4
+VECTOR(_OP2Set3, OP2Set3_PAGE, OP2Set3)         ; MULTIPAGE:IMPORT:OP2Set3
+VECTOR(_OP1Set1, OP1Set1_PAGE, OP1Set1)         ; MULTIPAGE:IMPORT:OP1Set1
+VECTOR(_OP1Set4, OP1Set4_PAGE, OP1Set4)         ; MULTIPAGE:IMPORT:OP1Set4
+VECTOR(_OP1Set3, OP1Set3_PAGE, OP1Set3)         ; MULTIPAGE:IMPORT:OP1Set3
+VECTOR(_OP3Set2, OP3Set2_PAGE, OP3Set2)         ; MULTIPAGE:IMPORT:OP3Set2
+VECTOR(_OP1Set2, OP1Set2_PAGE, OP1Set2)         ; MULTIPAGE:IMPORT:OP1Set2
+VECTOR(_OP2Set2, OP2Set2_PAGE, OP2Set2)         ; MULTIPAGE:IMPORT:OP2Set2
+VECTOR(_OP2Set1, OP2Set1_PAGE, OP2Set1)         ; MULTIPAGE:IMPORT:OP2Set1
+VECTOR(_OP5Set0, OP5Set0_PAGE, OP5Set0)         ; MULTIPAGE:IMPORT:OP5Set0
+VECTOR(_OP4Set0, OP4Set0_PAGE, OP4Set0)         ; MULTIPAGE:IMPORT:OP4Set0
+VECTOR(_OP3Set0, OP3Set0_PAGE, OP3Set0)         ; MULTIPAGE:IMPORT:OP3Set0
+VECTOR(_OP2Set0, OP2Set0_PAGE, OP2Set0)         ; MULTIPAGE:IMPORT:OP2Set0
+VECTOR(_OP1Set0, OP1Set0_PAGE, OP1Set0)         ; MULTIPAGE:IMPORT:OP1Set0
+VECTOR(_ZeroOP1, ZeroOP1_PAGE, ZeroOP1)         ; MULTIPAGE:IMPORT:ZeroOP1
+VECTOR(_ZeroOP2, ZeroOP2_PAGE, ZeroOP2)         ; MULTIPAGE:IMPORT:ZeroOP2
+VECTOR(_ZeroOP3, ZeroOP3_PAGE, ZeroOP3)         ; MULTIPAGE:IMPORT:ZeroOP3
+VECTOR(_OP2ExOP6, OP2ExOP6_PAGE, OP2ExOP6)      ; MULTIPAGE:IMPORT:OP2ExOP6
+VECTOR(_OP5ExOP6, OP5ExOP6_PAGE, OP5ExOP6)      ; MULTIPAGE:IMPORT:OP5ExOP6
+VECTOR(_OP1ExOP5, OP1ExOP5_PAGE, OP1ExOP5)      ; MULTIPAGE:IMPORT:OP1ExOP5
+VECTOR(_OP1ExOP6, OP1ExOP6_PAGE, OP1ExOP6)      ; MULTIPAGE:IMPORT:OP1ExOP6
+VECTOR(_OP2ExOP4, OP2ExOP4_PAGE, OP2ExOP4)      ; MULTIPAGE:IMPORT:OP2ExOP4
+VECTOR(_OP2ExOP5, OP2ExOP5_PAGE, OP2ExOP5)      ; MULTIPAGE:IMPORT:OP2ExOP5
+VECTOR(_OP1ExOP3, OP1ExOP3_PAGE, OP1ExOP3)      ; MULTIPAGE:IMPORT:OP1ExOP3
+VECTOR(_OP1ExOP4, OP1ExOP4_PAGE, OP1ExOP4)      ; MULTIPAGE:IMPORT:OP1ExOP4
+VECTOR(_OP1ExOP2, OP1ExOP2_PAGE, OP1ExOP2)      ; MULTIPAGE:IMPORT:OP1ExOP2
+VECTOR(_CkOP1FP0, CkOP1FP0_PAGE, CkOP1FP0)      ; MULTIPAGE:IMPORT:CkOP1FP0
+VECTOR(_CkOP1Pos, CkOP1Pos_PAGE, CkOP1Pos)      ; MULTIPAGE:IMPORT:CkOP1Pos
+VECTOR(_PopOP5, PopOP5_PAGE, PopOP5)            ; MULTIPAGE:IMPORT:PopOP5
+VECTOR(_PopOP3, PopOP3_PAGE, PopOP3)            ; MULTIPAGE:IMPORT:PopOP3
+VECTOR(_PopOP1, PopOP1_PAGE, PopOP1)            ; MULTIPAGE:IMPORT:PopOP1
+VECTOR(_PopRealO6, PopRealO6_PAGE, PopRealO6)   ; MULTIPAGE:IMPORT:PopRealO6
+VECTOR(_PopRealO5, PopRealO5_PAGE, PopRealO5)   ; MULTIPAGE:IMPORT:PopRealO5
+VECTOR(_PopRealO4, PopRealO4_PAGE, PopRealO4)   ; MULTIPAGE:IMPORT:PopRealO4
+VECTOR(_PopRealO3, PopRealO3_PAGE, PopRealO3)   ; MULTIPAGE:IMPORT:PopRealO3
+VECTOR(_PopRealO2, PopRealO2_PAGE, PopRealO2)   ; MULTIPAGE:IMPORT:PopRealO2
+VECTOR(_PopRealO1, PopRealO1_PAGE, PopRealO1)   ; MULTIPAGE:IMPORT:PopRealO1
+VECTOR(_PopReal, PopReal_PAGE, PopReal)         ; MULTIPAGE:IMPORT:PopReal
+VECTOR(_PushRealO6, PushRealO6_PAGE, PushRealO6); MULTIPAGE:IMPORT:PushRealO6
+VECTOR(_PushRealO5, PushRealO5_PAGE, PushRealO5); MULTIPAGE:IMPORT:PushRealO5
+VECTOR(_PushRealO4, PushRealO4_PAGE, PushRealO4); MULTIPAGE:IMPORT:PushRealO4
+VECTOR(_PushRealO3, PushRealO3_PAGE, PushRealO3); MULTIPAGE:IMPORT:PushRealO3
+VECTOR(_PushRealO2, PushRealO2_PAGE, PushRealO2); MULTIPAGE:IMPORT:PushRealO2
+VECTOR(_PushRealO1, PushRealO1_PAGE, PushRealO1); MULTIPAGE:IMPORT:PushRealO1
+VECTOR(_PushReal, PushReal_PAGE, PushReal)      ; MULTIPAGE:IMPORT:PushReal
+VECTOR(_PushOP5, PushOP5_PAGE, PushOP5)         ; MULTIPAGE:IMPORT:PushOP5
+VECTOR(_PushOP3, PushOP3_PAGE, PushOP3)         ; MULTIPAGE:IMPORT:PushOP3
+VECTOR(_PushOP1, PushOP1_PAGE, PushOP1)         ; MULTIPAGE:IMPORT:PushOP1
+VECTOR(_SetXXOP1, SetXXOP1_PAGE, SetXXOP1)      ; MULTIPAGE:IMPORT:SetXXOP1
+VECTOR(_SetXXOP2, SetXXOP2_PAGE, SetXXOP2)      ; MULTIPAGE:IMPORT:SetXXOP2
+VECTOR(_SetXXXXOP2, SetXXXXOP2_PAGE, SetXXXXOP2); MULTIPAGE:IMPORT:SetXXXXOP2
+VECTOR(_Mov18B, Mov18B_PAGE, Mov18B)            ; MULTIPAGE:IMPORT:Mov18B
+VECTOR(_ConvOP1, ConvOP1_PAGE, ConvOP1)         ; MULTIPAGE:IMPORT:ConvOP1
 
 ; Ensure vector table isn't truncated
 .seek $4000
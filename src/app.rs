@@ -0,0 +1,302 @@
+//! Flash applications.
+//!
+//! Apps are stored in flash, beginning at a model-dependent page and growing
+//! downward. Each app occupies consecutive pages, where page 0 of the app is its
+//! highest-numbered page and the following pages are at successively lower numbers.
+//! The first page of an app begins with a header describing it, which TI-OS uses
+//! to find installed apps by walking down from the first app page until it finds a
+//! page without a header. There is no other registry of apps, so writing an app
+//! to the next free pages makes it discoverable.
+//!
+//! Apps begin executing in bank A on their first page, immediately after the
+//! header. Multi-page apps call routines on their other pages with bcalls to
+//! addresses below 4000, which refer to a branch table at 4000 plus the bcall
+//! address on the app's first page.
+
+use crate::memory::{Memory, PAGE_SIZE};
+use crate::tifiles::{FlashFile, FlashType};
+use crate::{LoadAppError, Model};
+
+/// An app installed in flash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct App {
+    /// The app's name, without trailing padding.
+    pub name: Box<[u8]>,
+    /// The flash page holding the first page of the app.
+    pub page: u8,
+    /// Number of flash pages occupied by the app.
+    pub pages: u8,
+    /// Address in bank A where execution of the app begins.
+    pub entry: u16,
+}
+
+/// Header field containing the entire header.
+const FIELD_HEADER: u16 = 0x8000;
+const FIELD_NAME: u16 = 0x8040;
+const FIELD_PAGES: u16 = 0x8080;
+/// The last header field, followed by the app's code.
+const FIELD_IMAGE: u16 = 0x8070;
+
+/// Lowest page that apps may be installed to.
+const LAST_APP_PAGE: u8 = 0x08;
+
+/// Get the page that the first installed app begins on.
+fn first_app_page(model: Model) -> u8 {
+    match model {
+        Model::TI83Plus => 0x15,
+        Model::TI84Plus => 0x29,
+        Model::TI83PlusSE | Model::TI84PlusSE => 0x69,
+    }
+}
+
+/// Read a header field from the start of `data`, returning its ID (with the
+/// size bits cleared), the offset of the field's contents and their size.
+///
+/// The low 4 bits of a field ID specify its size directly if less than 0x0D;
+/// otherwise the ID is followed by a 1, 2 or 4 byte big-endian size.
+fn read_field(data: &[u8]) -> Option<(u16, usize, usize)> {
+    let id = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+    let (start, size) = match id & 0xF {
+        n @ 0..=0xC => (2, n as usize),
+        0xD => (3, *data.get(2)? as usize),
+        0xE => (
+            4,
+            u16::from_be_bytes([*data.get(2)?, *data.get(3)?]) as usize,
+        ),
+        _ => {
+            let size = data.get(2..6)?;
+            (
+                6,
+                u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize,
+            )
+        }
+    };
+    Some((id & !0xF, start, size))
+}
+
+impl App {
+    /// Parse the header at the beginning of an app's first page, returning `None`
+    /// if there is not a valid header.
+    pub fn from_header(page: u8, data: &[u8]) -> Option<App> {
+        let (id, mut pos, _) = read_field(data)?;
+        if id != FIELD_HEADER {
+            return None;
+        }
+
+        let mut name = None;
+        let mut pages = None;
+        let entry = loop {
+            let (id, start, size) = read_field(data.get(pos..)?)?;
+            let contents = pos + start;
+            match id {
+                FIELD_IMAGE => break contents,
+                FIELD_NAME => {
+                    let raw = data.get(contents..contents + size)?;
+                    let len = raw.len() - raw.iter().rev().take_while(|&&c| c == b' ').count();
+                    name = Some(raw[..len].into());
+                }
+                FIELD_PAGES => pages = Some(*data.get(contents)?),
+                _ => {}
+            }
+            pos = contents + size;
+        };
+
+        // The image field's size is not meaningful; code begins right after it
+        // and the padding that usually follows executes as nops.
+        Some(App {
+            name: name?,
+            page,
+            pages: pages.filter(|&n| n > 0)?,
+            entry: 0x4000 + entry as u16,
+        })
+    }
+}
+
+/// Find all the apps installed in flash.
+pub fn installed(mem: &Memory, model: Model) -> Vec<App> {
+    let mut apps = vec![];
+    let mut page = first_app_page(model);
+    while page >= LAST_APP_PAGE {
+        let app = match App::from_header(page, mem.flash_page(page)) {
+            None => break,
+            Some(app) => app,
+        };
+        let next = page.checked_sub(app.pages);
+        apps.push(app);
+        page = match next {
+            None => break,
+            Some(next) => next,
+        };
+    }
+    apps
+}
+
+/// Return true if a flash page contains nothing, being either erased or zeroed.
+fn is_free(data: &[u8; PAGE_SIZE]) -> bool {
+    data.iter().all(|&b| b == 0xFF) || data.iter().all(|&b| b == 0)
+}
+
+/// Install an app from a flash file into the next free pages following
+/// installed apps, returning the installed app.
+pub(crate) fn install(
+    mem: &mut Memory,
+    model: Model,
+    file: &FlashFile,
+) -> Result<App, LoadAppError> {
+    if file.ty != FlashType::App {
+        return Err(LoadAppError::UnsupportedType);
+    }
+    let header = file
+        .pages
+        .iter()
+        .find(|p| p.page == 0)
+        .and_then(|p| App::from_header(0, &p.data))
+        .ok_or(LoadAppError::InvalidHeader)?;
+    if file
+        .pages
+        .iter()
+        .any(|p| p.page >= header.pages || p.data.len() > PAGE_SIZE)
+    {
+        return Err(LoadAppError::InvalidHeader);
+    }
+
+    let apps = installed(mem, model);
+    if apps.iter().any(|app| app.name == header.name) {
+        return Err(LoadAppError::AlreadyInstalled);
+    }
+    let base = match apps.last() {
+        None => first_app_page(model),
+        Some(app) => app
+            .page
+            .checked_sub(app.pages)
+            .ok_or(LoadAppError::InsufficientSpace)?,
+    };
+    let lowest = base
+        .checked_sub(header.pages - 1)
+        .filter(|&p| p >= LAST_APP_PAGE)
+        .ok_or(LoadAppError::InsufficientSpace)?;
+    if !(lowest..=base).all(|page| is_free(mem.flash_page(page))) {
+        return Err(LoadAppError::InsufficientSpace);
+    }
+
+    debug!(
+        "Installing app {:?} to flash pages {:02X}-{:02X}",
+        String::from_utf8_lossy(&header.name),
+        lowest,
+        base
+    );
+    for page in lowest..=base {
+        *mem.flash_page_mut(page) = [0xFF; PAGE_SIZE];
+    }
+    for page in &file.pages {
+        mem.flash_page_mut(base - page.page)[..page.data.len()].copy_from_slice(&page.data);
+    }
+
+    Ok(App {
+        page: base,
+        ..header
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{install, App};
+    use crate::memory::PAGE_SIZE;
+    use crate::tifiles::{FlashFile, FlashPage, FlashType};
+    use crate::{Emulator, LoadAppError, Model, Z80};
+
+    /// Build a minimal app header like those made by the usual app templates.
+    fn header(name: &[u8; 8], pages: u8) -> Vec<u8> {
+        let mut data = vec![0x80, 0x0F, 0, 0, 0, 0];
+        data.extend_from_slice(&[0x80, 0x12, 0x01, 0x04]);
+        data.extend_from_slice(&[0x80, 0x21, 0x01, 0x80, 0x31, 0x01]);
+        data.extend_from_slice(&[0x80, 0x48]);
+        data.extend_from_slice(name);
+        data.extend_from_slice(&[0x80, 0x81, pages, 0x80, 0x90]);
+        data.extend_from_slice(&[0x03, 0x26, 0x09, 0x04, 0x04, 0x6F, 0x1B, 0x80]);
+        data.extend_from_slice(&[0x02, 0x0D, 0x40]);
+        data.extend_from_slice(&[0xA1; 0x40]);
+        data.extend_from_slice(&[0x80, 0x7F, 0, 0, 0, 0]);
+        data.resize(0x80, 0);
+        data
+    }
+
+    fn app_file(name: &[u8; 8], pages: u8) -> FlashFile {
+        let mut page0 = header(name, pages);
+        // Branch table entry at 4080 for page 1, 4000
+        page0.extend_from_slice(&[0x00, 0x40, 0x01]);
+        FlashFile {
+            version: (0, 0),
+            flags: 0,
+            object_type: 0,
+            date: [0; 4],
+            name: name[..].into(),
+            device: 0x73,
+            ty: FlashType::App,
+            os_header: None,
+            pages: (0..pages)
+                .map(|page| FlashPage {
+                    page,
+                    data: if page == 0 { page0.clone() } else { vec![0xC9] },
+                })
+                .collect(),
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn parse_header() {
+        assert_eq!(
+            App::from_header(0x15, &header(b"Test    ", 2)),
+            Some(App {
+                name: b"Test"[..].into(),
+                page: 0x15,
+                pages: 2,
+                entry: 0x4070,
+            })
+        );
+        assert_eq!(App::from_header(0x15, &[0xFF; 0x80]), None);
+    }
+
+    #[test]
+    fn install_and_launch() {
+        let mut emu = Emulator::with_model(Model::TI83Plus);
+        let mut cpu = Z80::new();
+        let mut bytes = vec![];
+        app_file(b"First   ", 1).write_to(&mut bytes).unwrap();
+        let first = emu.install_app(&bytes[..]).expect("Install should succeed");
+        assert_eq!(first.page, 0x15);
+
+        let mut bytes = vec![];
+        app_file(b"Second  ", 2).write_to(&mut bytes).unwrap();
+        let second = emu.install_app(&bytes[..]).expect("Install should succeed");
+        assert_eq!(second.page, 0x14);
+        assert_eq!(emu.apps(), vec![first, second.clone()]);
+
+        emu.launch_app(&mut cpu, &second);
+        assert!(emu.is_running());
+        assert_eq!(cpu.regs().pc, 0x4070);
+        assert_eq!(emu.mem.get_bank_a_page(), 0x14);
+
+        // bcall to 0080 goes through the branch table to the app's second page
+        let sp = cpu.regs().sp - 4;
+        cpu.regs_mut().sp = sp;
+        emu.mem.write_u16(sp, 0x8000);
+        emu.mem.write_u16(0x8000, 0x0080);
+        crate::bcalls::bcall_trap(&mut emu, &mut cpu);
+        assert_eq!(emu.mem.get_bank_a_page(), 0x13);
+        assert_eq!(cpu.regs().pc, 0x4000);
+        assert_eq!(emu.mem[0x4000], 0xC9);
+    }
+
+    #[test]
+    fn oversize_page() {
+        let mut emu = Emulator::with_model(Model::TI83Plus);
+        let mut file = app_file(b"Big     ", 2);
+        file.pages[1].data = vec![0xC9; PAGE_SIZE + 1];
+        assert!(matches!(
+            install(&mut emu.mem, Model::TI83Plus, &file),
+            Err(LoadAppError::InvalidHeader)
+        ));
+    }
+}
@@ -0,0 +1,726 @@
+//! Evaluation of expressions.
+//!
+//! Expressions are parsed directly from tokens by recursive descent, with
+//! operators at the same precedence levels as TI-OS: from lowest, `or` and
+//! `xor`; `and`; relations; addition; multiplication including the implied
+//! multiplication of adjacent values; negation; powers; and postfix operators.
+//! As in TI-OS, closing parentheses and quotes may be omitted at the end of a
+//! statement.
+
+use super::vars::{Error, Result, Value, Var};
+use crate::float::Real;
+
+pub const STORE: u16 = 0x04;
+pub const OPEN_BRACE: u16 = 0x08;
+pub const CLOSE_BRACE: u16 = 0x09;
+pub const OPEN_PAREN: u16 = 0x10;
+pub const CLOSE_PAREN: u16 = 0x11;
+pub const QUOTE: u16 = 0x2A;
+pub const COMMA: u16 = 0x2B;
+const RECIPROCAL: u16 = 0x0C;
+const SQUARE: u16 = 0x0D;
+const FACTORIAL: u16 = 0x2D;
+const PERIOD: u16 = 0x3A;
+const EXPONENT: u16 = 0x3B;
+const OR: u16 = 0x3C;
+const XOR: u16 = 0x3D;
+const AND: u16 = 0x40;
+const EQUAL: u16 = 0x6A;
+const LESS: u16 = 0x6B;
+const GREATER: u16 = 0x6C;
+const LESS_EQUAL: u16 = 0x6D;
+const GREATER_EQUAL: u16 = 0x6E;
+const NOT_EQUAL: u16 = 0x6F;
+const PLUS: u16 = 0x70;
+const MINUS: u16 = 0x71;
+const TIMES: u16 = 0x82;
+const DIVIDE: u16 = 0x83;
+const RAND: u16 = 0xAB;
+const PI: u16 = 0xAC;
+const GET_KEY: u16 = 0xAD;
+const NEGATE: u16 = 0xB0;
+const POWER: u16 = 0xF0;
+const E: u16 = 0xBB31;
+
+// Functions, which include their opening parenthesis.
+const ROUND: u16 = 0x12;
+const MAX: u16 = 0x19;
+const MIN: u16 = 0x1A;
+const INT: u16 = 0xB1;
+const ABS: u16 = 0xB2;
+const DIM: u16 = 0xB5;
+const NOT: u16 = 0xB8;
+const IPART: u16 = 0xB9;
+const FPART: u16 = 0xBA;
+const SQRT: u16 = 0xBC;
+const LN: u16 = 0xBE;
+const EXP: u16 = 0xBF;
+const LOG: u16 = 0xC0;
+const TEN_POW: u16 = 0xC1;
+const SIN: u16 = 0xC2;
+const COS: u16 = 0xC4;
+const TAN: u16 = 0xC6;
+const RAND_INT: u16 = 0xBB0A;
+const SUB: u16 = 0xBB0C;
+const IN_STRING: u16 = 0xBB0F;
+const EXPR: u16 = 0xBB2A;
+const LENGTH: u16 = 0xBB2B;
+
+/// Access to the state outside an expression.
+pub trait Environment {
+    fn recall(&mut self, var: Var) -> Result<Value>;
+    /// Get the key code of the last key pressed, or 0 if none.
+    fn get_key(&mut self) -> u8;
+    /// Get a random number between 0 and 1.
+    fn random(&mut self) -> Real;
+}
+
+pub struct Parser<'a, E> {
+    tokens: &'a [u16],
+    pos: usize,
+    env: &'a mut E,
+}
+
+impl<'a, E: Environment> Parser<'a, E> {
+    pub fn new(tokens: &'a [u16], env: &'a mut E) -> Self {
+        Parser {
+            tokens,
+            pos: 0,
+            env,
+        }
+    }
+
+    pub fn peek(&self) -> Option<u16> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    pub fn next_token(&mut self) -> Option<u16> {
+        let token = self.peek();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    /// Consume the given token if it is next, returning whether it was.
+    pub fn accept(&mut self, token: u16) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the given token, which must be next.
+    pub fn expect(&mut self, token: u16) -> Result<()> {
+        if self.accept(token) {
+            Ok(())
+        } else {
+            Err(Error::Syntax)
+        }
+    }
+
+    /// Consume a closing parenthesis, which is optional at the end of a statement
+    /// or before a store.
+    pub fn close(&mut self) -> Result<()> {
+        if self.accept(CLOSE_PAREN) || self.at_end() || self.peek() == Some(STORE) {
+            Ok(())
+        } else {
+            Err(Error::Syntax)
+        }
+    }
+
+    /// Evaluate an expression.
+    pub fn expression(&mut self) -> Result<Value> {
+        let mut value = self.conjunction()?;
+        loop {
+            let f: fn(bool, bool) -> bool = match self.peek() {
+                Some(OR) => |a, b| a || b,
+                Some(XOR) => |a, b| a != b,
+                _ => return Ok(value),
+            };
+            self.pos += 1;
+            let rhs = self.conjunction()?;
+            value = elementwise(value, rhs, |x, y| Ok(truth(f(!x.is_zero(), !y.is_zero()))))?;
+        }
+    }
+
+    /// Evaluate an expression that must be a real number.
+    pub fn real(&mut self) -> Result<Real> {
+        self.expression()?.real()
+    }
+
+    /// Evaluate an expression that must be an integer.
+    pub fn int(&mut self) -> Result<i64> {
+        self.real()?.to_int().map_err(|_| Error::Domain)
+    }
+
+    fn conjunction(&mut self) -> Result<Value> {
+        let mut value = self.relation()?;
+        while self.accept(AND) {
+            let rhs = self.relation()?;
+            value = elementwise(value, rhs, |x, y| Ok(truth(!x.is_zero() && !y.is_zero())))?;
+        }
+        Ok(value)
+    }
+
+    fn relation(&mut self) -> Result<Value> {
+        use std::cmp::Ordering::*;
+
+        let mut value = self.sum()?;
+        loop {
+            let op = match self.peek() {
+                Some(op @ EQUAL..=NOT_EQUAL) => op,
+                _ => return Ok(value),
+            };
+            self.pos += 1;
+            let rhs = self.sum()?;
+            value = match (value, rhs) {
+                (Value::Str(a), Value::Str(b)) if op == EQUAL || op == NOT_EQUAL => {
+                    Value::Real(truth((a == b) == (op == EQUAL)))
+                }
+                (a, b) => elementwise(a, b, |x, y| {
+                    let ordering = x.cmp(&y);
+                    Ok(truth(match op {
+                        EQUAL => ordering == Equal,
+                        LESS => ordering == Less,
+                        GREATER => ordering == Greater,
+                        LESS_EQUAL => ordering != Greater,
+                        GREATER_EQUAL => ordering != Less,
+                        _ => ordering != Equal,
+                    }))
+                })?,
+            };
+        }
+    }
+
+    fn sum(&mut self) -> Result<Value> {
+        let mut value = self.product()?;
+        loop {
+            value = match (self.peek(), value) {
+                (Some(PLUS), Value::Str(mut a)) => {
+                    self.pos += 1;
+                    match self.product()? {
+                        Value::Str(b) => {
+                            a.extend(b);
+                            Value::Str(a)
+                        }
+                        _ => return Err(Error::DataType),
+                    }
+                }
+                (Some(PLUS), a) => {
+                    self.pos += 1;
+                    elementwise(a, self.product()?, |x, y| Ok(x.add(&y)?))?
+                }
+                (Some(MINUS), a) => {
+                    self.pos += 1;
+                    elementwise(a, self.product()?, |x, y| Ok(x.sub(&y)?))?
+                }
+                (_, value) => return Ok(value),
+            };
+        }
+    }
+
+    fn product(&mut self) -> Result<Value> {
+        let mut value = self.negation()?;
+        loop {
+            value = match self.peek() {
+                Some(DIVIDE) => {
+                    self.pos += 1;
+                    elementwise(value, self.negation()?, |x, y| Ok(x.div(&y)?))?
+                }
+                Some(TIMES) => {
+                    self.pos += 1;
+                    elementwise(value, self.negation()?, |x, y| Ok(x.mul(&y)?))?
+                }
+                Some(t) if starts_value(t) => {
+                    elementwise(value, self.negation()?, |x, y| Ok(x.mul(&y)?))?
+                }
+                _ => return Ok(value),
+            };
+        }
+    }
+
+    fn negation(&mut self) -> Result<Value> {
+        if self.accept(NEGATE) {
+            map(self.negation()?, |x| Ok(x.neg()))
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Value> {
+        let mut value = self.postfix()?;
+        while self.accept(POWER) {
+            let exponent = if self.accept(NEGATE) {
+                map(self.postfix()?, |x| Ok(x.neg()))?
+            } else {
+                self.postfix()?
+            };
+            value = elementwise(value, exponent, |x, y| Ok(power(x, y)?))?;
+        }
+        Ok(value)
+    }
+
+    fn postfix(&mut self) -> Result<Value> {
+        let mut value = self.primary()?;
+        loop {
+            value = match self.peek() {
+                Some(SQUARE) => map(value, |x| Ok(x.mul(&x)?))?,
+                Some(RECIPROCAL) => map(value, |x| Ok(Real::from_int(1).div(&x)?))?,
+                Some(FACTORIAL) => map(value, factorial)?,
+                _ => return Ok(value),
+            };
+            self.pos += 1;
+        }
+    }
+
+    fn primary(&mut self) -> Result<Value> {
+        let token = self.peek().ok_or(Error::Syntax)?;
+        if let 0x30..=EXPONENT = token {
+            return self.number().map(Value::Real);
+        }
+        if let Some(var) = Var::from_token(token) {
+            self.pos += 1;
+            let value = self.env.recall(var)?;
+            if let (Var::List(_), true) = (var, self.accept(OPEN_PAREN)) {
+                let index = self.int()?;
+                self.close()?;
+                return list_element(&value, index).map(Value::Real);
+            }
+            return Ok(value);
+        }
+
+        self.pos += 1;
+        let value = match token {
+            OPEN_PAREN => {
+                let value = self.expression()?;
+                self.close()?;
+                value
+            }
+            QUOTE => {
+                let start = self.pos;
+                while !self.at_end() && self.peek() != Some(QUOTE) && self.peek() != Some(STORE) {
+                    self.pos += 1;
+                }
+                let s = self.tokens[start..self.pos].to_vec();
+                self.accept(QUOTE);
+                Value::Str(s)
+            }
+            OPEN_BRACE => {
+                let mut elements = vec![];
+                loop {
+                    elements.push(self.real()?);
+                    if !self.accept(COMMA) {
+                        break;
+                    }
+                }
+                if !self.accept(CLOSE_BRACE) && !self.at_end() && self.peek() != Some(STORE) {
+                    return Err(Error::Syntax);
+                }
+                Value::List(elements)
+            }
+            PI => Value::Real("3.1415926535898".parse().unwrap()),
+            E => Value::Real("2.718281828459".parse().unwrap()),
+            RAND => Value::Real(self.env.random()),
+            GET_KEY => Value::Real(Real::from_int(self.env.get_key() as i64)),
+            function => self.function(function)?,
+        };
+        Ok(value)
+    }
+
+    /// Parse a number such as `1.5ᴇ⁻3`.
+    fn number(&mut self) -> Result<Real> {
+        let mut text = String::new();
+        while let Some(t @ 0x30..=PERIOD) = self.peek() {
+            text.push(if t == PERIOD { '.' } else { t as u8 as char });
+            self.pos += 1;
+        }
+        if self.accept(EXPONENT) {
+            if text.is_empty() {
+                text.push('1');
+            }
+            text.push('e');
+            if self.accept(NEGATE) {
+                text.push('-');
+            }
+            while let Some(t @ 0x30..=0x39) = self.peek() {
+                text.push(t as u8 as char);
+                self.pos += 1;
+            }
+        }
+        text.parse().map_err(|_| Error::Syntax)
+    }
+
+    /// Parse the arguments to a function up to its closing parenthesis.
+    fn arguments(&mut self) -> Result<Vec<Value>> {
+        let mut args = vec![self.expression()?];
+        while self.accept(COMMA) {
+            args.push(self.expression()?);
+        }
+        self.close()?;
+        Ok(args)
+    }
+
+    fn function(&mut self, token: u16) -> Result<Value> {
+        let unary: fn(Real) -> crate::float::Result<Real> = match token {
+            INT => |x| Ok(x.floor()),
+            ABS => |x| Ok(x.abs()),
+            IPART => |x| Ok(x.trunc()),
+            FPART => |x| Ok(x.fract()),
+            NOT => |x| Ok(truth(x.is_zero())),
+            SQRT => |x| float_fn(x, f64::sqrt),
+            LN => |x| float_fn(x, f64::ln),
+            LOG => |x| float_fn(x, f64::log10),
+            EXP => |x| float_fn(x, f64::exp),
+            TEN_POW => |x| power(Real::from_int(10), x),
+            SIN => |x| float_fn(x, f64::sin),
+            COS => |x| float_fn(x, f64::cos),
+            TAN => |x| float_fn(x, f64::tan),
+            _ => return self.other_function(token),
+        };
+        let mut args = self.arguments()?;
+        if args.len() != 1 {
+            return Err(Error::Argument);
+        }
+        map(args.remove(0), |x| Ok(unary(x)?))
+    }
+
+    fn other_function(&mut self, token: u16) -> Result<Value> {
+        if !is_function(token) {
+            return Err(Error::Syntax);
+        }
+        let args = self.arguments()?;
+        let int = |i: usize| -> Result<i64> {
+            args.get(i)
+                .ok_or(Error::Argument)?
+                .real()?
+                .to_int()
+                .map_err(|_| Error::Domain)
+        };
+        let string = |i: usize| -> Result<&[u16]> {
+            match args.get(i) {
+                Some(Value::Str(s)) => Ok(s),
+                Some(_) => Err(Error::DataType),
+                None => Err(Error::Argument),
+            }
+        };
+
+        Ok(match (token, args.len()) {
+            (ROUND, 1) | (ROUND, 2) => {
+                let places = if args.len() == 2 { int(1)? } else { 9 };
+                if !(0..=9).contains(&places) {
+                    return Err(Error::Domain);
+                }
+                map(args[0].clone(), |x| Ok(x.round(places as i32)?))?
+            }
+            (MIN, _) | (MAX, _) => {
+                let pick = |x: Real, y: Real| Ok(if (x < y) == (token == MIN) { x } else { y });
+                match &args[..] {
+                    [Value::List(xs)] => {
+                        let (&first, rest) = xs.split_first().ok_or(Error::InvalidDim)?;
+                        Value::Real(
+                            rest.iter()
+                                .copied()
+                                .try_fold(first, pick)
+                                .map_err(|e: Error| e)?,
+                        )
+                    }
+                    [a, b] => elementwise(a.clone(), b.clone(), pick)?,
+                    _ => return Err(Error::Argument),
+                }
+            }
+            (DIM, 1) => match &args[0] {
+                Value::List(xs) => Value::Real(Real::from_int(xs.len() as i64)),
+                _ => return Err(Error::DataType),
+            },
+            (LENGTH, 1) => Value::Real(Real::from_int(string(0)?.len() as i64)),
+            (SUB, 3) => {
+                let s = string(0)?;
+                let (start, len) = (int(1)?, int(2)?);
+                if start < 1 || len < 1 {
+                    return Err(Error::Domain);
+                }
+                let end = (start - 1)
+                    .checked_add(len)
+                    .filter(|&end| end <= s.len() as i64)
+                    .ok_or(Error::Domain)?;
+                Value::Str(s[start as usize - 1..end as usize].to_vec())
+            }
+            (IN_STRING, 2) | (IN_STRING, 3) => {
+                let (s, needle) = (string(0)?, string(1)?);
+                let start = if args.len() == 3 { int(2)? } else { 1 };
+                if start < 1 {
+                    return Err(Error::Domain);
+                }
+                let found = (start as usize - 1..s.len())
+                    .find(|&i| s[i..].starts_with(needle))
+                    .map_or(0, |i| i + 1);
+                Value::Real(Real::from_int(found as i64))
+            }
+            (EXPR, 1) => {
+                let tokens = string(0)?.to_vec();
+                let mut parser = Parser::new(&tokens, &mut *self.env);
+                let value = parser.expression()?;
+                if !parser.at_end() {
+                    return Err(Error::Syntax);
+                }
+                value
+            }
+            (RAND_INT, 2) | (RAND_INT, 3) => {
+                let (low, high) = (int(0)?, int(1)?);
+                let (low, high) = (low.min(high), low.max(high));
+                let range = high
+                    .checked_sub(low)
+                    .and_then(|r| r.checked_add(1))
+                    .ok_or(Error::Domain)?;
+                let mut random_int = || -> Result<Real> {
+                    let range = Real::from_int(range);
+                    let offset = self.env.random().mul(&range)?.floor();
+                    Ok(offset.add(&Real::from_int(low))?)
+                };
+                if args.len() == 3 {
+                    let count = int(2)?;
+                    if !(1..=999).contains(&count) {
+                        return Err(Error::Domain);
+                    }
+                    Value::List((0..count).map(|_| random_int()).collect::<Result<_>>()?)
+                } else {
+                    Value::Real(random_int()?)
+                }
+            }
+            _ => return Err(Error::Argument),
+        })
+    }
+}
+
+/// Return true if a token can begin a value, so it implies multiplication when
+/// following another value.
+fn starts_value(token: u16) -> bool {
+    matches!(
+        token,
+        0x30..=EXPONENT | OPEN_BRACE | OPEN_PAREN | QUOTE | RAND | PI | GET_KEY | NEGATE | E
+    ) || Var::from_token(token).is_some()
+        || is_function(token)
+}
+
+fn is_function(token: u16) -> bool {
+    matches!(
+        token,
+        ROUND
+            | MAX
+            | MIN
+            | INT
+            | ABS
+            | DIM
+            | NOT
+            | IPART
+            | FPART
+            | SQRT
+            | LN
+            | EXP
+            | LOG
+            | TEN_POW
+            | SIN
+            | COS
+            | TAN
+            | RAND_INT
+            | SUB
+            | IN_STRING
+            | EXPR
+            | LENGTH
+    )
+}
+
+fn truth(b: bool) -> Real {
+    Real::from_int(b as i64)
+}
+
+/// Get an element of a list by its 1-based index.
+pub fn list_element(list: &Value, index: i64) -> Result<Real> {
+    match list {
+        Value::List(xs) if index >= 1 && index as usize <= xs.len() => Ok(xs[index as usize - 1]),
+        Value::List(_) => Err(Error::InvalidDim),
+        _ => Err(Error::DataType),
+    }
+}
+
+/// Apply a function to a number or each element of a list.
+fn map(value: Value, f: impl Fn(Real) -> Result<Real>) -> Result<Value> {
+    match value {
+        Value::Real(x) => Ok(Value::Real(f(x)?)),
+        Value::List(xs) => xs
+            .into_iter()
+            .map(f)
+            .collect::<Result<_>>()
+            .map(Value::List),
+        Value::Str(_) => Err(Error::DataType),
+    }
+}
+
+/// Apply a function to numbers, or pairs of elements of lists.
+fn elementwise(a: Value, b: Value, f: impl Fn(Real, Real) -> Result<Real>) -> Result<Value> {
+    match (a, b) {
+        (Value::Real(x), Value::Real(y)) => Ok(Value::Real(f(x, y)?)),
+        (Value::List(xs), Value::Real(y)) => map(Value::List(xs), |x| f(x, y)),
+        (Value::Real(x), Value::List(ys)) => map(Value::List(ys), |y| f(x, y)),
+        (Value::List(xs), Value::List(ys)) if xs.len() == ys.len() => xs
+            .into_iter()
+            .zip(ys)
+            .map(|(x, y)| f(x, y))
+            .collect::<Result<_>>()
+            .map(Value::List),
+        (Value::List(_), Value::List(_)) => Err(Error::DimMismatch),
+        _ => Err(Error::DataType),
+    }
+}
+
+/// Compute a function of a number using floating point.
+fn float_fn(x: Real, f: impl Fn(f64) -> f64) -> crate::float::Result<Real> {
+    Real::from_f64(f(x.to_f64()))
+}
+
+/// Raise `x` to the power `y`, which is exact for integer powers.
+fn power(x: Real, y: Real) -> crate::float::Result<Real> {
+    use crate::float::Error as E;
+
+    let n = match y.to_int() {
+        Ok(n) => n,
+        Err(_) if x.is_negative() => return Err(E::Invalid),
+        Err(_) => return float_fn(x, |x| x.powf(y.to_f64())),
+    };
+    if n == 0 && x.is_zero() {
+        return Err(E::Invalid);
+    }
+    let mut result = Real::from_int(1);
+    let mut base = x;
+    let mut remaining = n.unsigned_abs();
+    while remaining > 0 {
+        if remaining & 1 != 0 {
+            result = result.mul(&base)?;
+        }
+        remaining >>= 1;
+        if remaining > 0 {
+            base = base.mul(&base)?;
+        }
+    }
+    if n < 0 {
+        Real::from_int(1).div(&result)
+    } else {
+        Ok(result)
+    }
+}
+
+fn factorial(x: Real) -> Result<Real> {
+    match x.to_int() {
+        Ok(n @ 0..=69) => {
+            (1..=n).try_fold(Real::from_int(1), |acc, i| Ok(acc.mul(&Real::from_int(i))?))
+        }
+        _ => Err(Error::Domain),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct Env {
+        vars: HashMap<Var, Value>,
+    }
+
+    impl Environment for Env {
+        fn recall(&mut self, var: Var) -> Result<Value> {
+            match (self.vars.get(&var), var) {
+                (Some(value), _) => Ok(value.clone()),
+                (None, Var::Real(_)) => Ok(Value::Real(Real::ZERO)),
+                (None, _) => Err(Error::Undefined),
+            }
+        }
+
+        fn get_key(&mut self) -> u8 {
+            105
+        }
+
+        fn random(&mut self) -> Real {
+            "0.5".parse().unwrap()
+        }
+    }
+
+    fn eval_with(env: &mut Env, text: &str) -> Result<Value> {
+        let bytes = super::super::tokens::tokenize(text).unwrap();
+        let tokens = super::super::tokens::split_tokens(&bytes).unwrap();
+        let mut parser = Parser::new(&tokens, env);
+        let value = parser.expression()?;
+        assert!(parser.at_end(), "{:?} was not fully parsed", text);
+        Ok(value)
+    }
+
+    fn eval(text: &str) -> Result<Value> {
+        eval_with(&mut Env::default(), text)
+    }
+
+    fn real(s: &str) -> Value {
+        Value::Real(s.parse().unwrap())
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("1+2*3"), Ok(real("7")));
+        assert_eq!(eval("(1+2)*3"), Ok(real("9")));
+        assert_eq!(eval("2(3+4"), Ok(real("14")));
+        assert_eq!(eval("⁻2²"), Ok(real("-4")));
+        assert_eq!(eval("2^3^2"), Ok(real("64")));
+        assert_eq!(eval("2^⁻1"), Ok(real("0.5")));
+        assert_eq!(eval("1.5ᴇ3/3"), Ok(real("500")));
+        assert_eq!(eval("4!+3⁻¹*3"), Ok(real("25")));
+        assert_eq!(eval("int(⁻2.5)+iPart(⁻2.5)"), Ok(real("-5")));
+        assert_eq!(eval("round(2/3,2)"), Ok(real("0.67")));
+        assert_eq!(eval("√(16)+getKey"), Ok(real("109")));
+        assert_eq!(eval("randInt(1,10)"), Ok(real("6")));
+        assert_eq!(eval("randInt(⁻9ᴇ18,9ᴇ18)"), Err(Error::Domain));
+        assert_eq!(eval("1/0"), Err(Error::DivideByZero));
+        assert_eq!(eval("1+"), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn logic() {
+        assert_eq!(eval("1<2 and 2≤2"), Ok(real("1")));
+        assert_eq!(eval("1=2 or 3≠3"), Ok(real("0")));
+        assert_eq!(eval("1 xor 0"), Ok(real("1")));
+        assert_eq!(eval("not(5)"), Ok(real("0")));
+        assert_eq!(eval("\"AB\"=\"AB\""), Ok(real("1")));
+    }
+
+    #[test]
+    fn strings_and_lists() {
+        let mut env = Env::default();
+        env.vars.insert(Var::Real(b'A'), real("3"));
+        env.vars
+            .insert(Var::List(0), Value::List(vec![Real::from_int(5); 4]));
+
+        assert_eq!(eval_with(&mut env, "2A"), Ok(real("6")));
+        assert_eq!(eval_with(&mut env, "L₁(2)+dim(L₁)"), Ok(real("9")));
+        assert_eq!(eval_with(&mut env, "L₁(5)"), Err(Error::InvalidDim));
+        assert_eq!(eval_with(&mut env, "Str1"), Err(Error::Undefined));
+        assert_eq!(
+            eval("{1,2}+{3,4"),
+            Ok(Value::List(vec![Real::from_int(4), Real::from_int(6)]))
+        );
+        assert_eq!(eval("max({3,1,2})"), Ok(real("3")));
+        assert_eq!(eval("{1,2}+{3}"), Err(Error::DimMismatch));
+        assert_eq!(
+            eval("sub(\"HELLO\",2,3)+\"!"),
+            Ok(Value::Str(vec![0x45, 0x4C, 0x4C, 0x2D]))
+        );
+        assert_eq!(eval("sub(\"HELLO\",9ᴇ18,9ᴇ18)"), Err(Error::Domain));
+        assert_eq!(eval("inString(\"HELLO\",\"L\")"), Ok(real("3")));
+        assert_eq!(eval("length(\"HI\")+expr(\"2*3\")"), Ok(real("8")));
+    }
+}
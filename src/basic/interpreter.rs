@@ -0,0 +1,1120 @@
+//! Execution of BASIC programs.
+//!
+//! The interpreter runs one statement each time the OS's BASIC loop traps into
+//! it, so interrupts, the keyboard and the display keep working between
+//! statements and assembly programs called with `Asm(` simply return to the
+//! loop. Output goes to the home screen through the same routines as the
+//! display bcalls.
+
+use super::expr::{Environment, Parser, CLOSE_PAREN, COMMA, OPEN_PAREN, QUOTE, STORE};
+use super::tokens::token_text;
+use super::vars::{self, Error, Result, Value, Var};
+use crate::bcalls::display::{put_char, ClrLCDFull, HomeUp};
+use crate::bcalls::{reset_flag, test_flag};
+use crate::display::ScrollDirection;
+use crate::float::Real;
+use crate::include::tios;
+use crate::keyboard::Key;
+use crate::tifiles::ASM_PROGRAM_SIGNATURE;
+use crate::{Emulator, Z80};
+use num_traits::FromPrimitive;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Address of the OS loop that runs BASIC programs, which must match page00.asm.
+pub const BASIC_LOOP: u16 = 0x0058;
+
+const NEWLINE: u16 = 0x3F;
+const COLON: u16 = 0x3E;
+const PRGM: u16 = 0x5F;
+const IF: u16 = 0xCE;
+const THEN: u16 = 0xCF;
+const ELSE: u16 = 0xD0;
+const WHILE: u16 = 0xD1;
+const REPEAT: u16 = 0xD2;
+const FOR: u16 = 0xD3;
+const END: u16 = 0xD4;
+const RETURN: u16 = 0xD5;
+const LBL: u16 = 0xD6;
+const GOTO: u16 = 0xD7;
+const PAUSE: u16 = 0xD8;
+const STOP: u16 = 0xD9;
+const INPUT: u16 = 0xDC;
+const PROMPT: u16 = 0xDD;
+const DISP: u16 = 0xDE;
+const OUTPUT: u16 = 0xE0;
+const CLR_HOME: u16 = 0xE1;
+const DEL_VAR: u16 = 0xBB54;
+const ASM: u16 = 0xBB6A;
+
+/// Approximate cycle count to run a statement.
+const STATEMENT_TIME: usize = 5000;
+/// Approximate cycle count to check for a key while waiting for one.
+const WAIT_TIME: usize = 200;
+
+/// State of a running BASIC program.
+#[derive(Debug)]
+pub struct Interpreter {
+    /// The running program followed by any it called with `prgm`.
+    frames: Vec<Frame>,
+    state: State,
+    /// Variables remaining to be read by `Prompt`.
+    prompts: VecDeque<Var>,
+    rng: u64,
+}
+
+#[derive(Debug)]
+struct Frame {
+    tokens: Arc<[u16]>,
+    /// Index of the next statement.
+    pc: usize,
+    /// Blocks that have been entered and not yet ended.
+    blocks: Vec<Block>,
+}
+
+#[derive(Debug)]
+enum Block {
+    If,
+    /// A `While` loop, with the index of its statement.
+    While(usize),
+    /// A `Repeat` loop, with the index of its statement.
+    Repeat(usize),
+    For {
+        var: Var,
+        end: Real,
+        step: Real,
+        /// Index of the first statement of the loop body.
+        body: usize,
+    },
+}
+
+#[derive(Debug)]
+enum State {
+    Running,
+    /// Waiting for Enter to be pressed.
+    Pause,
+    /// Reading a value typed by the user into a variable.
+    Input {
+        var: Var,
+        entry: Vec<u16>,
+        alpha: bool,
+    },
+}
+
+enum Status {
+    Running,
+    Finished,
+}
+
+impl Interpreter {
+    /// Prepare to run a program with the given tokens.
+    pub fn new(tokens: Vec<u16>) -> Interpreter {
+        Interpreter {
+            frames: vec![Frame::new(tokens)],
+            state: State::Running,
+            prompts: VecDeque::new(),
+            rng: 0x2545_F491_4F6C_DD1D,
+        }
+    }
+
+    fn step(&mut self, emu: &mut Emulator, core: &mut Z80) -> Result<Status> {
+        if test_flag(emu, core, tios::onFlags, tios::onInterrupt) {
+            reset_flag(emu, core, tios::onFlags, tios::onInterrupt);
+            return Err(Error::Break);
+        }
+
+        match self.state {
+            State::Running => self.execute_next(emu, core),
+            State::Pause => {
+                if read_key(emu, core) == Some(Key::Enter) {
+                    self.state = State::Running;
+                }
+                Ok(Status::Running)
+            }
+            State::Input { .. } => {
+                if let Some(key) = read_key(emu, core) {
+                    self.input_key(emu, core, key)?;
+                }
+                Ok(Status::Running)
+            }
+        }
+    }
+
+    /// Execute the next statement of the current program.
+    fn execute_next(&mut self, emu: &mut Emulator, core: &mut Z80) -> Result<Status> {
+        let frame = match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => return Ok(Status::Finished),
+        };
+        let tokens = Arc::clone(&frame.tokens);
+        if frame.pc >= tokens.len() {
+            self.frames.pop();
+            return Ok(if self.frames.is_empty() {
+                Status::Finished
+            } else {
+                Status::Running
+            });
+        }
+
+        let start = frame.pc;
+        let end = statement_end(&tokens, start);
+        frame.pc = end + 1;
+        if start == end {
+            return Ok(Status::Running);
+        }
+        self.execute(emu, core, &tokens, start, end)
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("No program is running")
+    }
+
+    /// Execute the statement in `tokens[start..end]`.
+    fn execute(
+        &mut self,
+        emu: &mut Emulator,
+        core: &mut Z80,
+        tokens: &[u16],
+        start: usize,
+        end: usize,
+    ) -> Result<Status> {
+        let statement = &tokens[start..end];
+        let args = &statement[1..];
+        match statement[0] {
+            IF => {
+                let condition = self.condition(emu, core, args)?;
+                let next = self.frame().pc.min(tokens.len());
+                let next_end = statement_end(tokens, next);
+                if tokens[next..next_end] == [THEN] {
+                    self.frame().pc = next_end + 1;
+                    // A false condition runs the Else part if there is one
+                    if condition || self.skip_block(true) == Some(ELSE) {
+                        self.frame().blocks.push(Block::If);
+                    }
+                } else if !condition {
+                    self.frame().pc = next_end + 1;
+                }
+            }
+            ELSE => {
+                // Reached the end of a true If: skip the Else part
+                if let Some(Block::If) = self.frame().blocks.pop() {
+                    self.skip_block(false);
+                } else {
+                    return Err(Error::Syntax);
+                }
+            }
+            WHILE => {
+                if self.condition(emu, core, args)? {
+                    self.frame().blocks.push(Block::While(start));
+                } else {
+                    self.skip_block(false);
+                }
+            }
+            REPEAT => self.frame().blocks.push(Block::Repeat(start)),
+            FOR => {
+                let mut env = self.env(emu, core);
+                let mut parser = Parser::new(args, &mut env);
+                let var = match parser.next_token().and_then(Var::from_token) {
+                    Some(var @ Var::Real(_)) => var,
+                    _ => return Err(Error::Syntax),
+                };
+                parser.expect(COMMA)?;
+                let begin = parser.real()?;
+                parser.expect(COMMA)?;
+                let end = parser.real()?;
+                let step = if parser.accept(COMMA) {
+                    parser.real()?
+                } else {
+                    Real::from_int(1)
+                };
+                parser.close()?;
+                finish(&parser)?;
+
+                vars::store(&mut emu.mem, var, &Value::Real(begin))?;
+                if in_range(begin, end, step) {
+                    let body = self.frame().pc;
+                    self.frame().blocks.push(Block::For {
+                        var,
+                        end,
+                        step,
+                        body,
+                    });
+                } else {
+                    self.skip_block(false);
+                }
+            }
+            END => match self.frame().blocks.pop() {
+                None => return Err(Error::Syntax),
+                Some(Block::If) => {}
+                Some(Block::While(at)) => self.frame().pc = at,
+                Some(Block::Repeat(at)) => {
+                    let condition_end = statement_end(tokens, at);
+                    if !self.condition(emu, core, &tokens[at + 1..condition_end])? {
+                        self.frame().blocks.push(Block::Repeat(at));
+                        self.frame().pc = condition_end + 1;
+                    }
+                }
+                Some(Block::For {
+                    var,
+                    end,
+                    step,
+                    body,
+                }) => {
+                    let value = vars::recall(&emu.mem, var)?.real()?.add(&step)?;
+                    vars::store(&mut emu.mem, var, &Value::Real(value))?;
+                    if in_range(value, end, step) {
+                        self.frame().blocks.push(Block::For {
+                            var,
+                            end,
+                            step,
+                            body,
+                        });
+                        self.frame().pc = body;
+                    }
+                }
+            },
+            LBL => {}
+            GOTO => {
+                let target = find_label(tokens, args).ok_or(Error::Label)?;
+                self.frame().pc = target;
+            }
+            RETURN => {
+                self.frames.pop();
+                if self.frames.is_empty() {
+                    return Ok(Status::Finished);
+                }
+            }
+            STOP => {
+                self.frames.clear();
+                return Ok(Status::Finished);
+            }
+            DISP => {
+                if !args.is_empty() {
+                    for value in self.arguments(emu, core, args)? {
+                        disp(emu, &value);
+                    }
+                }
+            }
+            OUTPUT => {
+                let mut env = self.env(emu, core);
+                let mut parser = Parser::new(args, &mut env);
+                let row = parser.int()?;
+                parser.expect(COMMA)?;
+                let col = parser.int()?;
+                parser.expect(COMMA)?;
+                let value = parser.expression()?;
+                parser.close()?;
+                finish(&parser)?;
+                if !(1..=8).contains(&row) || !(1..=16).contains(&col) {
+                    return Err(Error::Domain);
+                }
+                output(emu, row as u8 - 1, col as u8 - 1, &value_chars(&value));
+            }
+            CLR_HOME => {
+                ClrLCDFull(emu);
+                HomeUp(emu);
+            }
+            PAUSE => {
+                if !args.is_empty() {
+                    let value = self.evaluate(emu, core, args)?;
+                    disp(emu, &value);
+                }
+                self.state = State::Pause;
+            }
+            INPUT => {
+                let (prompt, var) = match args {
+                    [QUOTE, ..] => {
+                        let comma = args.iter().rposition(|&t| t == COMMA);
+                        let comma = comma.ok_or(Error::Syntax)?;
+                        let prompt = self.evaluate(emu, core, &args[..comma])?;
+                        (value_chars(&prompt), &args[comma + 1..])
+                    }
+                    [] => return Err(Error::Unsupported),
+                    _ => (text_chars("?"), args),
+                };
+                let var = match var {
+                    &[token] => Var::from_token(token).ok_or(Error::Syntax)?,
+                    _ => return Err(Error::Syntax),
+                };
+                put_chars(emu, &prompt);
+                self.begin_input(var);
+            }
+            PROMPT => {
+                for var in args.split(|&t| t == COMMA) {
+                    match var {
+                        &[token] => self
+                            .prompts
+                            .push_back(Var::from_token(token).ok_or(Error::Syntax)?),
+                        _ => return Err(Error::Syntax),
+                    }
+                }
+                self.next_prompt(emu);
+            }
+            PRGM => {
+                let var = emu
+                    .find_program(&program_name(args)?)
+                    .map_err(|_| Error::Undefined)?;
+                if var.calc_data().starts_with(ASM_PROGRAM_SIGNATURE) {
+                    return Err(Error::Syntax);
+                }
+                let program =
+                    super::tokens::split_tokens(var.calc_data()).map_err(|_| Error::Syntax)?;
+                self.frames.push(Frame::new(program));
+            }
+            ASM => {
+                let name = match args {
+                    [PRGM, name @ ..] => {
+                        program_name(name.strip_suffix(&[CLOSE_PAREN]).unwrap_or(name))?
+                    }
+                    _ => return Err(Error::Syntax),
+                };
+                let var = emu.find_program(&name).map_err(|_| Error::Undefined)?;
+                emu.start_asm_program(core, var)
+                    .map_err(|_| Error::Syntax)?;
+                // Return to the BASIC loop when the program exits
+                let regs = core.regs_mut();
+                regs.sp -= 2;
+                emu.mem.write_u16(regs.sp, BASIC_LOOP);
+            }
+            DEL_VAR => {
+                let var = args
+                    .first()
+                    .and_then(|&t| Var::from_token(t))
+                    .ok_or(Error::Syntax)?;
+                vars::delete(&mut emu.mem, var);
+                // Another statement may immediately follow
+                if args.len() > 1 {
+                    self.frame().pc = start + 2;
+                }
+            }
+            THEN => return Err(Error::Syntax),
+            _ => self.evaluate_and_store(emu, core, statement)?,
+        }
+        Ok(Status::Running)
+    }
+
+    /// Skip forward to the `End` of the current block, or an `Else` for it if
+    /// `to_else` is set, returning which was found.
+    fn skip_block(&mut self, to_else: bool) -> Option<u16> {
+        let frame = self.frame();
+        let tokens = Arc::clone(&frame.tokens);
+        let mut depth = 0;
+        while frame.pc < tokens.len() {
+            let start = frame.pc;
+            let end = statement_end(&tokens, start);
+            frame.pc = end + 1;
+            match tokens.get(start).copied() {
+                Some(THEN) | Some(WHILE) | Some(REPEAT) | Some(FOR) => depth += 1,
+                Some(END) if depth == 0 => return Some(END),
+                Some(END) => depth -= 1,
+                Some(ELSE) if to_else && depth == 0 => return Some(ELSE),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn env<'a>(&'a mut self, emu: &'a mut Emulator, core: &'a Z80) -> Env<'a> {
+        Env {
+            emu,
+            core,
+            rng: &mut self.rng,
+        }
+    }
+
+    /// Evaluate an expression that makes up all of `tokens`.
+    fn evaluate(&mut self, emu: &mut Emulator, core: &Z80, tokens: &[u16]) -> Result<Value> {
+        let mut env = self.env(emu, core);
+        let mut parser = Parser::new(tokens, &mut env);
+        let value = parser.expression()?;
+        finish(&parser)?;
+        Ok(value)
+    }
+
+    fn condition(&mut self, emu: &mut Emulator, core: &Z80, tokens: &[u16]) -> Result<bool> {
+        Ok(!self.evaluate(emu, core, tokens)?.real()?.is_zero())
+    }
+
+    /// Evaluate comma-separated expressions.
+    fn arguments(&mut self, emu: &mut Emulator, core: &Z80, tokens: &[u16]) -> Result<Vec<Value>> {
+        let mut env = self.env(emu, core);
+        let mut parser = Parser::new(tokens, &mut env);
+        let mut values = vec![parser.expression()?];
+        while parser.accept(COMMA) {
+            values.push(parser.expression()?);
+        }
+        finish(&parser)?;
+        Ok(values)
+    }
+
+    /// Evaluate an expression statement, storing its value to Ans and the
+    /// variable it is stored to if any.
+    fn evaluate_and_store(&mut self, emu: &mut Emulator, core: &Z80, tokens: &[u16]) -> Result<()> {
+        let mut env = self.env(emu, core);
+        let mut parser = Parser::new(tokens, &mut env);
+        let value = parser.expression()?;
+        if !parser.accept(STORE) {
+            finish(&parser)?;
+            return vars::store(&mut emu.mem, Var::Real(vars::ANS), &value);
+        }
+
+        let var = parser
+            .next_token()
+            .and_then(Var::from_token)
+            .ok_or(Error::Syntax)?;
+        let index = if let (Var::List(_), true) = (var, parser.accept(OPEN_PAREN)) {
+            let index = parser.int()?;
+            parser.close()?;
+            Some(index)
+        } else {
+            None
+        };
+        finish(&parser)?;
+
+        match index {
+            None => vars::store(&mut emu.mem, var, &value)?,
+            Some(index) => {
+                let element = value.real()?;
+                let mut list = match vars::recall(&emu.mem, var) {
+                    Ok(Value::List(list)) => list,
+                    Err(Error::Undefined) => vec![],
+                    _ => return Err(Error::DataType),
+                };
+                // Storing just past the end extends the list
+                if index >= 1 && index as usize <= list.len() {
+                    list[index as usize - 1] = element;
+                } else if index as usize == list.len() + 1 {
+                    list.push(element);
+                } else {
+                    return Err(Error::InvalidDim);
+                }
+                vars::store(&mut emu.mem, var, &Value::List(list))?;
+            }
+        }
+        vars::store(&mut emu.mem, Var::Real(vars::ANS), &value)
+    }
+
+    fn begin_input(&mut self, var: Var) {
+        self.state = State::Input {
+            var,
+            entry: vec![],
+            alpha: false,
+        };
+    }
+
+    /// Prompt for the next variable requested by `Prompt`, if any.
+    fn next_prompt(&mut self, emu: &mut Emulator) {
+        match self.prompts.pop_front() {
+            Some(var) => {
+                let mut prompt = token_chars(var_token(var));
+                prompt.extend(text_chars("=?"));
+                put_chars(emu, &prompt);
+                self.begin_input(var);
+            }
+            None => self.state = State::Running,
+        }
+    }
+
+    /// Handle a key pressed while reading input.
+    fn input_key(&mut self, emu: &mut Emulator, core: &Z80, key: Key) -> Result<()> {
+        let (var, entry, alpha) = match &mut self.state {
+            State::Input { var, entry, alpha } => (*var, entry, alpha),
+            _ => unreachable!("Not reading input"),
+        };
+
+        match key {
+            Key::Enter => {
+                let entry = std::mem::take(entry);
+                new_line(emu);
+                let value = match var {
+                    Var::Str(_) => Value::Str(entry),
+                    _ => self.evaluate(emu, core, &entry)?,
+                };
+                vars::store(&mut emu.mem, var, &value)?;
+                self.next_prompt(emu);
+            }
+            Key::Alpha => *alpha = !*alpha,
+            Key::Del => {
+                if entry.pop().is_some() {
+                    backspace(emu);
+                }
+            }
+            key => {
+                let token = if *alpha {
+                    alpha_token(key)
+                } else {
+                    entry_token(key)
+                };
+                if let Some(token) = token {
+                    *alpha = false;
+                    entry.push(token);
+                    put_chars(emu, &token_chars(token));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Frame {
+    fn new(tokens: Vec<u16>) -> Frame {
+        Frame {
+            tokens: tokens.into(),
+            pc: 0,
+            blocks: vec![],
+        }
+    }
+}
+
+/// Access to variables and the keyboard for evaluating expressions.
+struct Env<'a> {
+    emu: &'a mut Emulator,
+    core: &'a Z80,
+    rng: &'a mut u64,
+}
+
+impl Environment for Env<'_> {
+    fn recall(&mut self, var: Var) -> Result<Value> {
+        vars::recall(&self.emu.mem, var)
+    }
+
+    fn get_key(&mut self) -> u8 {
+        read_key(self.emu, self.core).map_or(0, Key::key_code)
+    }
+
+    fn random(&mut self) -> Real {
+        // A 64-bit LCG, taking the high bits which are the most random.
+        *self.rng = self
+            .rng
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let fraction = (*self.rng >> 11) as f64 / (1u64 << 53) as f64;
+        Real::from_f64(fraction).unwrap_or(Real::ZERO)
+    }
+}
+
+/// Take the key last pressed, as recorded by the OS interrupt.
+fn read_key(emu: &mut Emulator, core: &Z80) -> Option<Key> {
+    if !test_flag(emu, core, tios::kbdFlags, tios::kbdSCR) {
+        return None;
+    }
+    reset_flag(emu, core, tios::kbdFlags, tios::kbdSCR);
+    let scan_code = std::mem::replace(&mut emu.mem[tios::kbdScanCode], 0);
+    Key::from_u8(scan_code)
+}
+
+/// Find the index of the separator that ends the statement beginning at `start`.
+///
+/// Statements are separated by colons or newlines, but colons in strings do not
+/// end the statement.
+fn statement_end(tokens: &[u16], start: usize) -> usize {
+    let mut in_string = false;
+    for (i, &token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            NEWLINE => return i,
+            COLON if !in_string => return i,
+            QUOTE => in_string = !in_string,
+            STORE => in_string = false,
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// Find the index of the statement following `Lbl name`.
+fn find_label(tokens: &[u16], name: &[u16]) -> Option<usize> {
+    let mut start = 0;
+    while start < tokens.len() {
+        let end = statement_end(tokens, start);
+        if tokens[start] == LBL && &tokens[start + 1..end] == name {
+            return Some(end + 1);
+        }
+        start = end + 1;
+    }
+    None
+}
+
+/// Get a program name from the tokens of its letters and digits.
+fn program_name(tokens: &[u16]) -> Result<Vec<u8>> {
+    if tokens.is_empty() || tokens.len() > 8 {
+        return Err(Error::Syntax);
+    }
+    tokens
+        .iter()
+        .map(|&t| match t {
+            0x30..=0x39 | 0x41..=0x5B => Ok(t as u8),
+            _ => Err(Error::Syntax),
+        })
+        .collect()
+}
+
+fn finish<E: Environment>(parser: &Parser<E>) -> Result<()> {
+    if parser.at_end() {
+        Ok(())
+    } else {
+        Err(Error::Syntax)
+    }
+}
+
+/// Return true if a `For(` loop with the given end and step continues to `value`.
+fn in_range(value: Real, end: Real, step: Real) -> bool {
+    if step.is_negative() {
+        value >= end
+    } else {
+        value <= end
+    }
+}
+
+fn var_token(var: Var) -> u16 {
+    match var {
+        Var::Real(t) => t as u16,
+        Var::Str(n) => 0xAA00 | n as u16,
+        Var::List(n) => 0x5D00 | n as u16,
+    }
+}
+
+/// Get the token typed by a key in normal entry mode.
+fn entry_token(key: Key) -> Option<u16> {
+    use Key::*;
+
+    Some(match key {
+        Zero => 0x30,
+        One => 0x31,
+        Two => 0x32,
+        Three => 0x33,
+        Four => 0x34,
+        Five => 0x35,
+        Six => 0x36,
+        Seven => 0x37,
+        Eight => 0x38,
+        Nine => 0x39,
+        Period => 0x3A,
+        Negate => 0xB0,
+        Comma => COMMA,
+        Plus => 0x70,
+        Minus => 0x71,
+        Multiply => 0x82,
+        Divide => 0x83,
+        Caret => 0xF0,
+        Square => 0x0D,
+        Reciprocal => 0x0C,
+        OpenParen => OPEN_PAREN,
+        CloseParen => CLOSE_PAREN,
+        _ => return None,
+    })
+}
+
+/// Get the token typed by a key in alpha mode.
+fn alpha_token(key: Key) -> Option<u16> {
+    use Key::*;
+
+    const LETTERS: &[Key] = &[
+        Math, Apps, Program, Reciprocal, Sine, Cosine, Tangent, Caret, Square, Comma, OpenParen,
+        CloseParen, Divide, Log, Seven, Eight, Nine, Multiply, NaturalLog, Four, Five, Six, Minus,
+        Store, One, Two, Three,
+    ];
+    if let Some(i) = LETTERS.iter().position(|&k| k == key) {
+        // A-Z, then θ
+        return Some(0x41 + i as u16);
+    }
+    Some(match key {
+        Zero => 0x29,
+        Period => COLON,
+        Negate => 0xAF,
+        Plus => QUOTE,
+        _ => return None,
+    })
+}
+
+/// Get the font character for a character of token text, as on the calculator.
+fn font_char(c: char) -> u8 {
+    match c {
+        '[' => 0xC1,
+        ' '..='~' => c as u8,
+        'θ' => 0x5B,
+        '√' => 0x10,
+        '⁻' => 0x1A,
+        'ᴇ' => 0x1B,
+        'ℯ' => b'e',
+        '→' => 0x1C,
+        '²' => 0x12,
+        '°' => 0x14,
+        '≤' => 0x17,
+        '≠' => 0x18,
+        '≥' => 0x19,
+        '₀'..='₉' => 0x80 + (c as u32 - '₀' as u32) as u8,
+        '𝑖' => 0xD7,
+        'ʟ' => 0xDC,
+        'π' => 0xC4,
+        _ => b'?',
+    }
+}
+
+fn text_chars(text: &str) -> Vec<u8> {
+    text.chars().map(font_char).collect()
+}
+
+fn token_chars(token: u16) -> Vec<u8> {
+    match token {
+        // Some tokens are drawn with special characters
+        0x0C => vec![0x11],
+        0xC1 => vec![0x1D, b'^', b'('],
+        _ => {
+            let text = token_text(token).unwrap_or("?");
+            // The | only distinguishes tokens that display the same when written
+            // as text.
+            text_chars(
+                text.strip_prefix('|')
+                    .filter(|t| !t.is_empty())
+                    .unwrap_or(text),
+            )
+        }
+    }
+}
+
+/// Format a number as the home screen displays it, with up to 10 significant
+/// digits.
+fn format_real(x: Real) -> String {
+    let x = x.round(9 - x.exponent()).unwrap_or(x);
+    let s = x.to_string().replace('-', "⁻").replace('E', "ᴇ");
+    match s.strip_prefix("0.") {
+        Some(fraction) => format!(".{}", fraction),
+        None => s.replacen("⁻0.", "⁻.", 1),
+    }
+}
+
+/// Get the characters that display a value.
+fn value_chars(value: &Value) -> Vec<u8> {
+    match value {
+        Value::Real(x) => text_chars(&format_real(*x)),
+        Value::Str(s) => s.iter().flat_map(|&t| token_chars(t)).collect(),
+        Value::List(xs) => {
+            let elements: Vec<String> = xs.iter().map(|&x| format_real(x)).collect();
+            text_chars(&format!("{{{}}}", elements.join(" ")))
+        }
+    }
+}
+
+/// Move the cursor to the beginning of the next line, scrolling if it is on the
+/// last line.
+fn new_line(emu: &mut Emulator) {
+    emu.mem[tios::curCol] = 0;
+    if emu.mem[tios::curRow] < 7 {
+        emu.mem[tios::curRow] += 1;
+    } else {
+        emu.mem[tios::curRow] = 7;
+        emu.display.scroll(ScrollDirection::Up, 8);
+    }
+}
+
+/// Get the row and column of the cursor.
+///
+/// Programs can move the cursor off the screen, so like TI-OS a cursor past the
+/// last line is moved to the last line and one past the end of a line wraps to
+/// the next.
+fn cursor(emu: &mut Emulator) -> (u8, u8) {
+    if emu.mem[tios::curRow] > 7 {
+        emu.mem[tios::curRow] = 7;
+    }
+    if emu.mem[tios::curCol] > 15 {
+        new_line(emu);
+    }
+    (emu.mem[tios::curRow], emu.mem[tios::curCol])
+}
+
+/// Display characters at the cursor, wrapping to following lines.
+fn put_chars(emu: &mut Emulator, chars: &[u8]) {
+    for &c in chars {
+        let (row, col) = cursor(emu);
+        put_char(emu, c, col, row);
+        emu.mem[tios::curCol] = col + 1;
+        if col == 15 {
+            new_line(emu);
+        }
+    }
+}
+
+/// Erase the character before the cursor and move back to it.
+fn backspace(emu: &mut Emulator) {
+    let (mut row, mut col) = cursor(emu);
+    if col > 0 {
+        col -= 1;
+    } else if row > 0 {
+        row -= 1;
+        col = 15;
+    }
+    put_char(emu, b' ', col, row);
+    emu.mem[tios::curRow] = row;
+    emu.mem[tios::curCol] = col;
+}
+
+/// Display a value on its own line as `Disp` does: strings on the left and
+/// other values on the right.
+fn disp(emu: &mut Emulator, value: &Value) {
+    let chars = value_chars(value);
+    emu.mem[tios::curCol] = match value {
+        Value::Str(_) => 0,
+        _ => 16usize.saturating_sub(chars.len()) as u8,
+    };
+    put_chars(emu, &chars);
+    if chars.is_empty() || emu.mem[tios::curCol] != 0 {
+        new_line(emu);
+    }
+}
+
+/// Display characters at a position without moving the cursor, wrapping to
+/// following lines and stopping at the bottom of the screen.
+fn output(emu: &mut Emulator, row: u8, col: u8, chars: &[u8]) {
+    let start = row as usize * 16 + col as usize;
+    for (position, &c) in (start..16 * 8).zip(chars) {
+        put_char(emu, c, position as u8 % 16, position as u8 / 16);
+    }
+}
+
+/// Run the next statement of the running BASIC program, handling the trap from
+/// the OS's BASIC loop.
+///
+/// When the program finishes, "Done" is displayed and the loop returns to its
+/// caller. Errors are displayed on the home screen and end the program.
+pub fn step(emu: &mut Emulator, core: &mut Z80) -> usize {
+    // Clean up after any assembly program run with Asm(, which has returned.
+    let asm_size = emu.mem.read_u16(tios::asm_prgm_size);
+    if asm_size != 0 {
+        crate::vat::delete_mem(&mut emu.mem, tios::userMem, asm_size);
+        emu.mem.write_u16(tios::asm_prgm_size, 0);
+    }
+
+    let mut interpreter = match emu.basic.take() {
+        Some(interpreter) => interpreter,
+        None => {
+            error!("BASIC loop is running without a program");
+            return_from_loop(emu, core);
+            return WAIT_TIME;
+        }
+    };
+    let waiting = !matches!(interpreter.state, State::Running);
+    match interpreter.step(emu, core) {
+        Ok(Status::Running) => {
+            emu.basic = Some(interpreter);
+            if waiting {
+                return WAIT_TIME;
+            }
+        }
+        Ok(Status::Finished) => {
+            info!("BASIC program finished");
+            // Done goes on the right of a line of its own
+            if cursor(emu).1 != 0 {
+                new_line(emu);
+            }
+            emu.mem[tios::curCol] = 12;
+            put_chars(emu, &text_chars("Done"));
+            return_from_loop(emu, core);
+        }
+        Err(e) => {
+            info!("BASIC program stopped with error {:?}", e);
+            ClrLCDFull(emu);
+            HomeUp(emu);
+            put_chars(emu, &text_chars(&format!("ERR:{}", e.name())));
+            return_from_loop(emu, core);
+        }
+    }
+    STATEMENT_TIME
+}
+
+/// Leave the BASIC loop as if it returned.
+fn return_from_loop(emu: &mut Emulator, core: &mut Z80) {
+    let regs = core.regs_mut();
+    regs.pc = emu.mem.read_u16(regs.sp);
+    regs.sp = regs.sp.wrapping_add(2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::tokens;
+    use crate::tests::emulator;
+    use std::time::Duration;
+
+    fn run(emu: &mut Emulator, cpu: &mut Z80, duration: Duration) {
+        let mut elapsed = Duration::from_secs(0);
+        while emu.is_running() && elapsed < duration {
+            elapsed += emu.run(cpu, Duration::from_millis(10));
+        }
+    }
+
+    fn run_text(emu: &mut Emulator, cpu: &mut Z80, text: &str) {
+        let var = tokens::program_from_text(b"TEST", text, false).unwrap();
+        emu.store_variable(&var).unwrap();
+        emu.run_program(cpu, b"TEST").unwrap();
+        run(emu, cpu, Duration::from_secs(5));
+    }
+
+    fn real(emu: &Emulator, name: u8) -> Value {
+        vars::recall(&emu.mem, Var::Real(name)).unwrap()
+    }
+
+    /// Return true if anything is drawn in columns of a line of text.
+    fn is_drawn(emu: &Emulator, row: u8, cols: std::ops::Range<u8>) -> bool {
+        (row * 8..row * 8 + 8)
+            .any(|y| (cols.start * 6..cols.end * 6).any(|x| emu.display.get_pixel(x, y) != 0))
+    }
+
+    #[test]
+    fn control_flow() {
+        let (mut emu, mut cpu) = emulator();
+        run_text(
+            &mut emu,
+            &mut cpu,
+            "0→S\nFor(I,1,10\nS+I→S\nEnd\nIf S=55:Then\n\"YES\"→Str1\nElse\n\"NO\"→Str1\nEnd\n\
+             While N<3:N+1→N:End\nRepeat N=0:N-1→N:End\nIf 0:5→N\nGoto A\n5→N\nLbl A\n\
+             Disp S,Str1",
+        );
+        assert!(!emu.is_running());
+        assert_eq!(real(&emu, b'S'), Value::Real(Real::from_int(55)));
+        assert_eq!(real(&emu, b'N'), Value::Real(Real::ZERO));
+        assert_eq!(
+            vars::recall(&emu.mem, Var::Str(0)),
+            Ok(Value::Str(vec![0x59, 0x45, 0x53]))
+        );
+        // Two lines from Disp and one with Done
+        assert!(is_drawn(&emu, 2, 12..16));
+        assert!(!is_drawn(&emu, 2, 0..12));
+        assert_eq!(emu.mem[tios::curRow], 3);
+    }
+
+    #[test]
+    fn cursor_positions() {
+        // An assembly program that moves the cursor to a row and column
+        let move_cursor = |emu: &mut Emulator, row: u8, col: u8| {
+            let [row_lo, row_hi] = tios::curRow.to_le_bytes();
+            let [col_lo, col_hi] = tios::curCol.to_le_bytes();
+            let code = [
+                0xBB, 0x6D, 0x3E, row, 0x32, row_lo, row_hi, 0x3E, col, 0x32, col_lo, col_hi, 0xC9,
+            ];
+            let mut data = (code.len() as u16).to_le_bytes().to_vec();
+            data.extend_from_slice(&code);
+            crate::vat::insert(
+                &mut emu.mem,
+                crate::tifiles::VariableType::Program,
+                b"CURSOR",
+                &data,
+            )
+            .unwrap();
+        };
+
+        // Done goes on a new line after output that doesn't end one
+        let (mut emu, mut cpu) = emulator();
+        move_cursor(&mut emu, 0, 5);
+        run_text(&mut emu, &mut cpu, "Asm(prgmCURSOR");
+        assert!(!emu.is_running());
+        assert!(is_drawn(&emu, 1, 12..16));
+        assert_eq!(emu.mem[tios::curRow], 2);
+
+        // A cursor moved off the screen is brought back to the last line, which
+        // scrolls up after each line is finished
+        let (mut emu, mut cpu) = emulator();
+        move_cursor(&mut emu, 200, 200);
+        run_text(&mut emu, &mut cpu, "Asm(prgmCURSOR\nDisp 1");
+        assert!(!emu.is_running());
+        assert!(is_drawn(&emu, 5, 15..16));
+        assert!(is_drawn(&emu, 6, 12..16));
+        assert!(!is_drawn(&emu, 7, 0..16));
+        assert_eq!(emu.mem[tios::curRow], 7);
+    }
+
+    #[test]
+    fn errors_stop_the_program() {
+        let (mut emu, mut cpu) = emulator();
+        run_text(&mut emu, &mut cpu, "1→A:1/0:2→A");
+        assert!(!emu.is_running());
+        assert_eq!(real(&emu, b'A'), Value::Real(Real::from_int(1)));
+    }
+
+    #[test]
+    fn empty_list() {
+        use crate::tifiles::{File, Variable, VariableType};
+
+        let (mut emu, mut cpu) = emulator();
+        let var = Variable {
+            name: b"\x5D\x00"[..].into(),
+            ty: VariableType::List,
+            version: None,
+            flags: Some(0),
+            data: vec![0, 0],
+        };
+        let mut file = vec![];
+        File::new(var).write_to(&mut file).unwrap();
+        emu.load_variable(&file[..]).unwrap();
+
+        run_text(&mut emu, &mut cpu, "1→A:min(L₁)→A:2→A");
+        assert!(!emu.is_running());
+        assert_eq!(real(&emu, b'A'), Value::Real(Real::from_int(1)));
+    }
+
+    #[test]
+    fn call_assembly() {
+        let (mut emu, mut cpu) = emulator();
+        // ld a,42 \ ld (8000h),a \ ret
+        let code = [0xBB, 0x6D, 0x3E, 42, 0x32, 0x00, 0x80, 0xC9];
+        let mut data = (code.len() as u16).to_le_bytes().to_vec();
+        data.extend_from_slice(&code);
+        crate::vat::insert(
+            &mut emu.mem,
+            crate::tifiles::VariableType::Program,
+            b"ASM",
+            &data,
+        )
+        .unwrap();
+
+        run_text(&mut emu, &mut cpu, "Asm(prgmASM)\n1→A");
+        assert!(!emu.is_running());
+        assert_eq!(emu.mem[0x8000], 42);
+        assert_eq!(emu.mem.read_u16(tios::asm_prgm_size), 0);
+        assert_eq!(real(&emu, b'A'), Value::Real(Real::from_int(1)));
+
+        // Unsquished programs run the same way
+        let hex = tokens::program_from_text(b"HEX", "AsmPrgm3E07\n320080C9\nEnd", false).unwrap();
+        emu.store_variable(&hex).unwrap();
+        run_text(&mut emu, &mut cpu, "Asm(prgmHEX");
+        assert_eq!(emu.mem[0x8000], 7);
+    }
+
+    #[test]
+    fn input() {
+        let (mut emu, mut cpu) = emulator();
+        let var = tokens::program_from_text(b"TEST", "Input A:Disp A+1", false).unwrap();
+        emu.store_variable(&var).unwrap();
+        emu.run_program(&mut cpu, b"TEST").unwrap();
+        for &key in &[Key::Four, Key::Two, Key::Enter] {
+            emu.key_down(key);
+            run(&mut emu, &mut cpu, Duration::from_millis(50));
+            emu.key_up(key);
+            run(&mut emu, &mut cpu, Duration::from_millis(50));
+        }
+        run(&mut emu, &mut cpu, Duration::from_secs(1));
+        assert!(!emu.is_running());
+        assert_eq!(real(&emu, b'A'), Value::Real(Real::from_int(42)));
+    }
+
+    #[test]
+    fn statements() {
+        let tokens = super::super::tokens::tokenize("Disp \"A:B\":1→A\nLbl X:A").unwrap();
+        let tokens = super::super::tokens::split_tokens(&tokens).unwrap();
+        let end = statement_end(&tokens, 0);
+        assert_eq!(tokens[end], COLON);
+        assert_eq!(statement_end(&tokens, end + 1), end + 4);
+        assert_eq!(find_label(&tokens, &[0x58]), Some(tokens.len() - 1));
+        assert_eq!(find_label(&tokens, &[0x59]), None);
+    }
+
+    #[test]
+    fn number_format() {
+        let real = |s: &str| s.parse::<Real>().unwrap();
+        assert_eq!(format_real(real("-0.5")), "⁻.5");
+        assert_eq!(format_real(real("2")), "2");
+        assert_eq!(format_real(real("0.66666666666667")), ".6666666667");
+        assert_eq!(format_real(real("123456789012")), "1.23456789ᴇ11");
+        assert_eq!(format_real(real("0.00001")), "1ᴇ⁻5");
+        assert_eq!(text_chars("⁻1"), vec![0x1A, b'1']);
+    }
+}
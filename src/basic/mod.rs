@@ -0,0 +1,8 @@
+//! TI-BASIC programs.
+
+mod expr;
+mod interpreter;
+pub mod tokens;
+mod vars;
+
+pub(crate) use interpreter::{step, Interpreter, BASIC_LOOP};
@@ -0,0 +1,384 @@
+//! Conversion between TI-BASIC programs and text.
+//!
+//! Programs are stored as a sequence of tokens, each of which is one or two bytes
+//! and displays as a string of one or more characters. Two-byte tokens have a
+//! first byte that identifies them as such (like `t2ByteTok`, BB), which is never
+//! itself a complete token.
+//!
+//! Text uses the characters the calculator displays for each token, so most
+//! tokens are written as they appear on the calculator: `→` for store,
+//! `L₁` for list 1 and `ᴇ` for the exponent marker. A few ASCII spellings are
+//! also accepted when tokenizing, like `->` for `→` and `<=` for `≤`. Tokens
+//! that display the same as another have a distinct spelling, so text can be
+//! converted back to the same tokens.
+//! Tokenizing is greedy, always taking the longest matching token, so keywords
+//! in strings become the keyword's token like on the calculator.
+
+use crate::tifiles::{Variable, VariableType, ASM_PROGRAM_SIGNATURE};
+
+/// Every token and its text, where two-byte tokens have their first byte in the
+/// high byte.
+///
+/// Every token has distinct text so programs are unchanged by converting them to
+/// text and back. Where tokens display the same, the one less likely to be meant
+/// is written with a `|` before its text, like `|u` for the sequence variable u
+/// and `|a` for the regression coefficient a, and the constant e is `ℯ`.
+#[rustfmt::skip]
+const TOKENS: &[(u16, &str)] = &[
+    // One-byte tokens
+    (0x01, "►DMS"), (0x02, "►Dec"), (0x03, "►Frac"), (0x04, "→"), (0x05, "Boxplot"), (0x06, "["),
+    (0x07, "]"), (0x08, "{"), (0x09, "}"), (0x0A, "ʳ"), (0x0B, "°"), (0x0C, "⁻¹"), (0x0D, "²"),
+    (0x0E, "ᵀ"), (0x0F, "³"), (0x10, "("), (0x11, ")"), (0x12, "round("), (0x13, "pxl-Test("),
+    (0x14, "augment("), (0x15, "rowSwap("), (0x16, "row+("), (0x17, "*row("), (0x18, "*row+("),
+    (0x19, "max("), (0x1A, "min("), (0x1B, "R►Pr("), (0x1C, "R►Pθ("), (0x1D, "P►Rx("),
+    (0x1E, "P►Ry("), (0x1F, "median("), (0x20, "randM("), (0x21, "mean("), (0x22, "solve("),
+    (0x23, "seq("), (0x24, "fnInt("), (0x25, "nDeriv("), (0x27, "fMin("), (0x28, "fMax("),
+    (0x29, " "), (0x2A, "\""), (0x2B, ","), (0x2C, "𝑖"), (0x2D, "!"), (0x2E, "CubicReg "),
+    (0x2F, "QuartReg "), (0x30, "0"), (0x31, "1"), (0x32, "2"), (0x33, "3"), (0x34, "4"),
+    (0x35, "5"), (0x36, "6"), (0x37, "7"), (0x38, "8"), (0x39, "9"), (0x3A, "."), (0x3B, "ᴇ"),
+    (0x3C, " or "), (0x3D, " xor "), (0x3E, ":"), (0x3F, "\n"), (0x40, " and "), (0x41, "A"),
+    (0x42, "B"), (0x43, "C"), (0x44, "D"), (0x45, "E"), (0x46, "F"), (0x47, "G"), (0x48, "H"),
+    (0x49, "I"), (0x4A, "J"), (0x4B, "K"), (0x4C, "L"), (0x4D, "M"), (0x4E, "N"), (0x4F, "O"),
+    (0x50, "P"), (0x51, "Q"), (0x52, "R"), (0x53, "S"), (0x54, "T"), (0x55, "U"), (0x56, "V"),
+    (0x57, "W"), (0x58, "X"), (0x59, "Y"), (0x5A, "Z"), (0x5B, "θ"), (0x5F, "prgm"),
+    (0x64, "Radian"), (0x65, "Degree"), (0x66, "Normal"), (0x67, "Sci"), (0x68, "Eng"),
+    (0x69, "Float"), (0x6A, "="), (0x6B, "<"), (0x6C, ">"), (0x6D, "≤"), (0x6E, "≥"), (0x6F, "≠"),
+    (0x70, "+"), (0x71, "-"), (0x72, "Ans"), (0x73, "Fix "), (0x74, "Horiz"), (0x75, "Full"),
+    (0x76, "Func"), (0x77, "Param"), (0x78, "Polar"), (0x79, "Seq"), (0x7A, "IndpntAuto"),
+    (0x7B, "IndpntAsk"), (0x7C, "DependAuto"), (0x7D, "DependAsk"), (0x7F, "□"), (0x80, "﹢"),
+    (0x81, "·"), (0x82, "*"), (0x83, "/"), (0x84, "Trace"), (0x85, "ClrDraw"), (0x86, "ZStandard"),
+    (0x87, "ZTrig"), (0x88, "ZBox"), (0x89, "Zoom In"), (0x8A, "Zoom Out"), (0x8B, "ZSquare"),
+    (0x8C, "ZInteger"), (0x8D, "ZPrevious"), (0x8E, "ZDecimal"), (0x8F, "ZoomStat"),
+    (0x90, "ZoomRcl"), (0x91, "PrintScreen"), (0x92, "ZoomSto"), (0x93, "Text("), (0x94, " nPr "),
+    (0x95, " nCr "), (0x96, "FnOn "), (0x97, "FnOff "), (0x98, "StorePic "), (0x99, "RecallPic "),
+    (0x9A, "StoreGDB "), (0x9B, "RecallGDB "), (0x9C, "Line("), (0x9D, "Vertical "),
+    (0x9E, "Pt-On("), (0x9F, "Pt-Off("), (0xA0, "Pt-Change("), (0xA1, "Pxl-On("),
+    (0xA2, "Pxl-Off("), (0xA3, "Pxl-Change("), (0xA4, "Shade("), (0xA5, "Circle("),
+    (0xA6, "Horizontal "), (0xA7, "Tangent("), (0xA8, "DrawInv "), (0xA9, "DrawF "),
+    (0xAB, "rand"), (0xAC, "π"), (0xAD, "getKey"), (0xAE, "'"), (0xAF, "?"), (0xB0, "⁻"),
+    (0xB1, "int("), (0xB2, "abs("), (0xB3, "det("), (0xB4, "identity("), (0xB5, "dim("),
+    (0xB6, "sum("), (0xB7, "prod("), (0xB8, "not("), (0xB9, "iPart("), (0xBA, "fPart("),
+    (0xBC, "√("), (0xBD, "³√("), (0xBE, "ln("), (0xBF, "e^("), (0xC0, "log("), (0xC1, "₁₀^("),
+    (0xC2, "sin("), (0xC3, "sin⁻¹("), (0xC4, "cos("), (0xC5, "cos⁻¹("), (0xC6, "tan("),
+    (0xC7, "tan⁻¹("), (0xC8, "sinh("), (0xC9, "sinh⁻¹("), (0xCA, "cosh("), (0xCB, "cosh⁻¹("),
+    (0xCC, "tanh("), (0xCD, "tanh⁻¹("), (0xCE, "If "), (0xCF, "Then"), (0xD0, "Else"),
+    (0xD1, "While "), (0xD2, "Repeat "), (0xD3, "For("), (0xD4, "End"), (0xD5, "Return"),
+    (0xD6, "Lbl "), (0xD7, "Goto "), (0xD8, "Pause "), (0xD9, "Stop"), (0xDA, "IS>("),
+    (0xDB, "DS<("), (0xDC, "Input "), (0xDD, "Prompt "), (0xDE, "Disp "), (0xDF, "DispGraph"),
+    (0xE0, "Output("), (0xE1, "ClrHome"), (0xE2, "Fill("), (0xE3, "SortA("), (0xE4, "SortD("),
+    (0xE5, "DispTable"), (0xE6, "Menu("), (0xE7, "Send("), (0xE8, "Get("), (0xE9, "PlotsOn "),
+    (0xEA, "PlotsOff "), (0xEB, "ʟ"), (0xEC, "Plot1("), (0xED, "Plot2("), (0xEE, "Plot3("),
+    (0xF0, "^"), (0xF1, "ˣ√"), (0xF2, "1-Var Stats "), (0xF3, "2-Var Stats "),
+    (0xF4, "LinReg(a+bx) "), (0xF5, "ExpReg "), (0xF6, "LnReg "), (0xF7, "PwrReg "),
+    (0xF8, "Med-Med "), (0xF9, "QuadReg "), (0xFA, "ClrList "), (0xFB, "ClrTable"),
+    (0xFC, "Histogram"), (0xFD, "xyLine"), (0xFE, "Scatter"), (0xFF, "LinReg(ax+b) "),
+    // Two-byte tokens
+    (0xBB00, "npv("), (0xBB01, "irr("), (0xBB02, "bal("), (0xBB03, "ΣPrn("), (0xBB04, "ΣInt("),
+    (0xBB05, "►Nom("), (0xBB06, "►Eff("), (0xBB07, "dbd("), (0xBB08, "lcm("), (0xBB09, "gcd("),
+    (0xBB0A, "randInt("), (0xBB0B, "randBin("), (0xBB0C, "sub("), (0xBB0D, "stdDev("),
+    (0xBB0E, "variance("), (0xBB0F, "inString("), (0xBB10, "normalcdf("), (0xBB11, "invNorm("),
+    (0xBB12, "tcdf("), (0xBB13, "χ²cdf("), (0xBB14, "Fcdf("), (0xBB15, "binompdf("),
+    (0xBB16, "binomcdf("), (0xBB17, "poissonpdf("), (0xBB18, "poissoncdf("),
+    (0xBB19, "geometpdf("), (0xBB1A, "geometcdf("), (0xBB1B, "normalpdf("), (0xBB1C, "tpdf("),
+    (0xBB1D, "χ²pdf("), (0xBB1E, "Fpdf("), (0xBB1F, "randNorm("), (0xBB20, "tvm_Pmt"),
+    (0xBB21, "tvm_I%"), (0xBB22, "tvm_PV"), (0xBB23, "tvm_N"), (0xBB24, "tvm_FV"),
+    (0xBB25, "conj("), (0xBB26, "real("), (0xBB27, "imag("), (0xBB28, "angle("),
+    (0xBB29, "cumSum("), (0xBB2A, "expr("), (0xBB2B, "length("), (0xBB2C, "ΔList("),
+    (0xBB2D, "ref("), (0xBB2E, "rref("), (0xBB2F, "►Rect"), (0xBB30, "►Polar"), (0xBB31, "ℯ"),
+    (0xBB32, "SinReg "), (0xBB33, "Logistic "), (0xBB34, "LinRegTTest "), (0xBB35, "ShadeNorm("),
+    (0xBB36, "Shade_t("), (0xBB37, "Shadeχ²("), (0xBB38, "ShadeF("), (0xBB39, "Matr►list("),
+    (0xBB3A, "List►matr("), (0xBB3B, "Z-Test("), (0xBB3C, "T-Test "), (0xBB3D, "2-SampZTest("),
+    (0xBB3E, "1-PropZTest("), (0xBB3F, "2-PropZTest("), (0xBB40, "χ²-Test("),
+    (0xBB41, "ZInterval "), (0xBB42, "2-SampZInt("), (0xBB43, "1-PropZInt("),
+    (0xBB44, "2-PropZInt("), (0xBB45, "GraphStyle("), (0xBB46, "2-SampTTest "),
+    (0xBB47, "2-SampFTest "), (0xBB48, "TInterval "), (0xBB49, "2-SampTInt "),
+    (0xBB4A, "SetUpEditor "), (0xBB4B, "Pmt_End"), (0xBB4C, "Pmt_Bgn"), (0xBB4D, "Real"),
+    (0xBB4E, "re^θ𝑖"), (0xBB4F, "a+b𝑖"), (0xBB50, "ExprOn"), (0xBB51, "ExprOff"),
+    (0xBB52, "ClrAllLists"), (0xBB53, "GetCalc("), (0xBB54, "DelVar "), (0xBB55, "Equ►String("),
+    (0xBB56, "String►Equ("), (0xBB57, "Clear Entries"), (0xBB58, "Select("), (0xBB59, "ANOVA("),
+    (0xBB5A, "ModBoxplot"), (0xBB5B, "NormProbPlot"), (0xBB64, "G-T"), (0xBB65, "ZoomFit"),
+    (0xBB66, "DiagnosticOn"), (0xBB67, "DiagnosticOff"), (0xBB68, "Archive "),
+    (0xBB69, "UnArchive "), (0xBB6A, "Asm("), (0xBB6B, "AsmComp("), (0xBB6C, "AsmPrgm"),
+    (0xBB6E, "Á"), (0xBB6F, "À"), (0xBB70, "Â"), (0xBB71, "Ä"), (0xBB72, "á"), (0xBB73, "à"),
+    (0xBB74, "â"), (0xBB75, "ä"), (0xBB76, "É"), (0xBB77, "È"), (0xBB78, "Ê"), (0xBB79, "Ë"),
+    (0xBB7A, "é"), (0xBB7B, "è"), (0xBB7C, "ê"), (0xBB7D, "ë"), (0xBB7E, "Í"), (0xBB7F, "Ì"),
+    (0xBB80, "Î"), (0xBB81, "Ï"), (0xBB82, "í"), (0xBB83, "ì"), (0xBB84, "î"), (0xBB85, "ï"),
+    (0xBB86, "Ó"), (0xBB87, "Ò"), (0xBB88, "Ô"), (0xBB89, "Ö"), (0xBB8A, "ó"), (0xBB8B, "ò"),
+    (0xBB8C, "ô"), (0xBB8D, "ö"), (0xBB8E, "Ú"), (0xBB8F, "Ù"), (0xBB90, "Û"), (0xBB91, "Ü"),
+    (0xBB92, "ú"), (0xBB93, "ù"), (0xBB94, "û"), (0xBB95, "ü"), (0xBB96, "Ç"), (0xBB97, "ç"),
+    (0xBB98, "Ñ"), (0xBB99, "ñ"), (0xBB9A, "´"), (0xBB9B, "`"), (0xBB9C, "¨"), (0xBB9D, "¿"),
+    (0xBB9E, "¡"), (0xBB9F, "α"), (0xBBA0, "β"), (0xBBA1, "γ"), (0xBBA2, "Δ"), (0xBBA3, "δ"),
+    (0xBBA4, "ε"), (0xBBA5, "λ"), (0xBBA6, "μ"), (0xBBA7, "|π"), (0xBBA8, "ρ"), (0xBBA9, "Σ"),
+    (0xBBAB, "φ"), (0xBBAC, "Ω"), (0xBBAD, "p̂"), (0xBBAE, "χ"), (0xBBAF, "𝐅"), (0xBBB0, "a"),
+    (0xBBB1, "b"), (0xBBB2, "c"), (0xBBB3, "d"), (0xBBB4, "e"), (0xBBB5, "f"), (0xBBB6, "g"),
+    (0xBBB7, "h"), (0xBBB8, "i"), (0xBBB9, "j"), (0xBBBA, "k"), (0xBBBC, "l"), (0xBBBD, "m"),
+    (0xBBBE, "n"), (0xBBBF, "o"), (0xBBC0, "p"), (0xBBC1, "q"), (0xBBC2, "r"), (0xBBC3, "s"),
+    (0xBBC4, "t"), (0xBBC5, "u"), (0xBBC6, "v"), (0xBBC7, "w"), (0xBBC8, "x"), (0xBBC9, "y"),
+    (0xBBCA, "z"), (0xBBCB, "σ"), (0xBBCC, "τ"), (0xBBCD, "|Í"), (0xBBCE, "GarbageCollect"),
+    (0xBBCF, "~"), (0xBBD1, "@"), (0xBBD2, "#"), (0xBBD3, "$"), (0xBBD4, "&"), (0xBBD5, "|`"),
+    (0xBBD6, ";"), (0xBBD7, "\\"), (0xBBD8, "|"), (0xBBD9, "_"), (0xBBDA, "%"), (0xBBDB, "…"),
+    (0xBBDC, "∠"), (0xBBDD, "ß"), (0xBBDE, "ˣ"), (0xBBDF, "ᴛ"), (0xBBE0, "₀"), (0xBBE1, "₁"),
+    (0xBBE2, "₂"), (0xBBE3, "₃"), (0xBBE4, "₄"), (0xBBE5, "₅"), (0xBBE6, "₆"), (0xBBE7, "₇"),
+    (0xBBE8, "₈"), (0xBBE9, "₉"), (0xBBEA, "₁₀"), (0xBBEB, "◄"), (0xBBEC, "►"), (0xBBED, "↑"),
+    (0xBBEE, "↓"),
+    // TI-84 Plus tokens
+    (0xEF00, "setDate("), (0xEF01, "setTime("), (0xEF02, "checkTmr("), (0xEF03, "setDtFmt("),
+    (0xEF04, "setTmFmt("), (0xEF05, "timeCnv("), (0xEF06, "dayOfWk("), (0xEF07, "getDtStr("),
+    (0xEF08, "getTmStr("), (0xEF09, "getDate"), (0xEF0A, "getTime"), (0xEF0B, "startTmr"),
+    (0xEF0C, "getDtFmt"), (0xEF0D, "getTmFmt"), (0xEF0E, "isClockOn"), (0xEF0F, "ClockOff"),
+    (0xEF10, "ClockOn"), (0xEF11, "OpenLib("), (0xEF12, "ExecLib"), (0xEF13, "invT("),
+    (0xEF14, "χ²GOF-Test("), (0xEF15, "LinRegTInt "), (0xEF16, "Manual-Fit "),
+    // Matrices
+    (0x5C00, "[A]"), (0x5C01, "[B]"), (0x5C02, "[C]"), (0x5C03, "[D]"), (0x5C04, "[E]"),
+    (0x5C05, "[F]"), (0x5C06, "[G]"), (0x5C07, "[H]"), (0x5C08, "[I]"), (0x5C09, "[J]"),
+    // Lists
+    (0x5D00, "L₁"), (0x5D01, "L₂"), (0x5D02, "L₃"), (0x5D03, "L₄"), (0x5D04, "L₅"), (0x5D05, "L₆"),
+    // Equations
+    (0x5E10, "Y₁"), (0x5E11, "Y₂"), (0x5E12, "Y₃"), (0x5E13, "Y₄"), (0x5E14, "Y₅"), (0x5E15, "Y₆"),
+    (0x5E16, "Y₇"), (0x5E17, "Y₈"), (0x5E18, "Y₉"), (0x5E19, "Y₀"), (0x5E20, "X₁ᴛ"),
+    (0x5E21, "Y₁ᴛ"), (0x5E22, "X₂ᴛ"), (0x5E23, "Y₂ᴛ"), (0x5E24, "X₃ᴛ"), (0x5E25, "Y₃ᴛ"),
+    (0x5E26, "X₄ᴛ"), (0x5E27, "Y₄ᴛ"), (0x5E28, "X₅ᴛ"), (0x5E29, "Y₅ᴛ"), (0x5E2A, "X₆ᴛ"),
+    (0x5E2B, "Y₆ᴛ"), (0x5E40, "r₁"), (0x5E41, "r₂"), (0x5E42, "r₃"), (0x5E43, "r₄"),
+    (0x5E44, "r₅"), (0x5E45, "r₆"), (0x5E80, "|u"), (0x5E81, "|v"), (0x5E82, "|w"),
+    // Pictures
+    (0x6000, "Pic1"), (0x6001, "Pic2"), (0x6002, "Pic3"), (0x6003, "Pic4"), (0x6004, "Pic5"),
+    (0x6005, "Pic6"), (0x6006, "Pic7"), (0x6007, "Pic8"), (0x6008, "Pic9"), (0x6009, "Pic0"),
+    // Graph databases
+    (0x6100, "GDB1"), (0x6101, "GDB2"), (0x6102, "GDB3"), (0x6103, "GDB4"), (0x6104, "GDB5"),
+    (0x6105, "GDB6"), (0x6106, "GDB7"), (0x6107, "GDB8"), (0x6108, "GDB9"), (0x6109, "GDB0"),
+    // Statistics results
+    (0x6201, "RegEQ"), (0x6202, "|n"), (0x6203, "x̄"), (0x6204, "Σx"), (0x6205, "Σx²"),
+    (0x6206, "Sx"), (0x6207, "σx"), (0x6208, "minX"), (0x6209, "maxX"), (0x620A, "minY"),
+    (0x620B, "maxY"), (0x620C, "ȳ"), (0x620D, "Σy"), (0x620E, "Σy²"), (0x620F, "Sy"),
+    (0x6210, "σy"), (0x6211, "Σxy"), (0x6212, "|r"), (0x6213, "Med"), (0x6214, "Q₁"),
+    (0x6215, "Q₃"), (0x6216, "|a"), (0x6217, "|b"), (0x6218, "|c"), (0x6219, "|d"), (0x621A, "|e"),
+    (0x621B, "x₁"), (0x621C, "x₂"), (0x621D, "x₃"), (0x621E, "y₁"), (0x621F, "y₂"), (0x6220, "y₃"),
+    (0x6221, "𝗻"), (0x6222, "|p"), (0x6223, "|z"), (0x6224, "|t"), (0x6225, "χ²"), (0x6226, "|𝐅"),
+    (0x6227, "df"), (0x6228, "|p̂"), (0x6229, "p̂₁"), (0x622A, "p̂₂"), (0x622B, "x̄₁"),
+    (0x622C, "Sx₁"), (0x622D, "n₁"), (0x622E, "x̄₂"), (0x622F, "Sx₂"), (0x6230, "n₂"),
+    (0x6231, "Sxp"), (0x6232, "lower"), (0x6233, "upper"), (0x6234, "|s"), (0x6235, "r²"),
+    (0x6236, "R²"), (0x6237, "Factor df"), (0x6238, "Factor SS"), (0x6239, "Factor MS"),
+    (0x623A, "Error df"), (0x623B, "Error SS"), (0x623C, "Error MS"),
+    // Window and finance variables
+    (0x6300, "ZXscl"), (0x6301, "ZYscl"), (0x6302, "Xscl"), (0x6303, "Yscl"), (0x6304, "u(nMin)"),
+    (0x6305, "v(nMin)"), (0x6306, "u(n-1)"), (0x6307, "v(n-1)"), (0x6308, "Zu(nMin)"),
+    (0x6309, "Zv(nMin)"), (0x630A, "Xmin"), (0x630B, "Xmax"), (0x630C, "Ymin"), (0x630D, "Ymax"),
+    (0x630E, "Tmin"), (0x630F, "Tmax"), (0x6310, "θmin"), (0x6311, "θmax"), (0x6312, "ZXmin"),
+    (0x6313, "ZXmax"), (0x6314, "ZYmin"), (0x6315, "ZYmax"), (0x6316, "Zθmin"), (0x6317, "Zθmax"),
+    (0x6318, "ZTmin"), (0x6319, "ZTmax"), (0x631A, "TblStart"), (0x631B, "PlotStart"),
+    (0x631C, "ZPlotStart"), (0x631D, "nMax"), (0x631E, "ZnMax"), (0x631F, "nMin"),
+    (0x6320, "ZnMin"), (0x6321, "ΔTbl"), (0x6322, "Tstep"), (0x6323, "θstep"), (0x6324, "ZTstep"),
+    (0x6325, "Zθstep"), (0x6326, "ΔX"), (0x6327, "ΔY"), (0x6328, "XFact"), (0x6329, "YFact"),
+    (0x632A, "TblInput"), (0x632B, "𝗡"), (0x632C, "I%"), (0x632D, "PV"), (0x632E, "PMT"),
+    (0x632F, "FV"), (0x6330, "P/Y"), (0x6331, "C/Y"), (0x6332, "w(nMin)"), (0x6333, "Zw(nMin)"),
+    (0x6334, "PlotStep"), (0x6335, "ZPlotStep"), (0x6336, "Xres"), (0x6337, "ZXres"),
+    // Graph format
+    (0x7E00, "Sequential"), (0x7E01, "Simul"), (0x7E02, "PolarGC"), (0x7E03, "RectGC"),
+    (0x7E04, "CoordOn"), (0x7E05, "CoordOff"), (0x7E06, "Connected"), (0x7E07, "Dot"),
+    (0x7E08, "AxesOn"), (0x7E09, "AxesOff"), (0x7E0A, "GridOn"), (0x7E0B, "GridOff"),
+    (0x7E0C, "LabelOn"), (0x7E0D, "LabelOff"), (0x7E0E, "Web"), (0x7E0F, "Time"),
+    (0x7E10, "uvAxes"), (0x7E11, "vwAxes"), (0x7E12, "uwAxes"),
+    // Strings
+    (0xAA00, "Str1"), (0xAA01, "Str2"), (0xAA02, "Str3"), (0xAA03, "Str4"), (0xAA04, "Str5"),
+    (0xAA05, "Str6"), (0xAA06, "Str7"), (0xAA07, "Str8"), (0xAA08, "Str9"), (0xAA09, "Str0"),
+];
+
+/// Alternate spellings of tokens, accepted only when tokenizing.
+const ALIASES: &[(u16, &str)] = &[
+    (0x04, "->"),
+    (0x6D, "<="),
+    (0x6E, ">="),
+    (0x6F, "!="),
+    (0x3F, "\r\n"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The variable is not a program.
+    NotProgram,
+    /// The program contains assembly code rather than tokens.
+    Assembly,
+    /// There is no token with the given value, at a byte offset in the program.
+    UnknownToken { offset: usize, token: u16 },
+    /// The program ends with the first byte of a two-byte token.
+    Truncated,
+    /// No token matches the text at a 1-based line and column.
+    UnrecognizedText { line: usize, column: usize },
+    /// Program names must be 1 to 8 characters.
+    InvalidName,
+}
+
+/// Return true if a token is the first byte of a two-byte token.
+fn is_prefix(byte: u8) -> bool {
+    matches!(byte, 0x5C..=0x5E | 0x60..=0x63 | 0x7E | 0xAA | 0xBB | 0xEF)
+}
+
+/// Get the text of a token.
+pub fn token_text(token: u16) -> Option<&'static str> {
+    TOKENS
+        .iter()
+        .find(|&&(value, _)| value == token)
+        .map(|&(_, text)| text)
+}
+
+/// Split program data into tokens, where two-byte tokens have their first byte
+/// in the high byte.
+pub fn split_tokens(data: &[u8]) -> Result<Vec<u16>, Error> {
+    let mut tokens = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&b) = bytes.next() {
+        tokens.push(if is_prefix(b) {
+            let &second = bytes.next().ok_or(Error::Truncated)?;
+            (b as u16) << 8 | second as u16
+        } else {
+            b as u16
+        });
+    }
+    Ok(tokens)
+}
+
+/// Convert tokens to text.
+pub fn detokenize(data: &[u8]) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut offset = 0;
+    for token in split_tokens(data)? {
+        out.push_str(token_text(token).ok_or(Error::UnknownToken { offset, token })?);
+        offset += if token > 0xFF { 2 } else { 1 };
+    }
+    Ok(out)
+}
+
+/// Find the longest token matching the beginning of `text`.
+fn match_token(text: &str) -> Option<(u16, usize)> {
+    TOKENS
+        .iter()
+        .chain(ALIASES)
+        .filter(|(_, t)| text.starts_with(t))
+        .fold(None, |best: Option<(u16, usize)>, &(value, t)| match best {
+            Some((_, len)) if len >= t.len() => best,
+            _ => Some((value, t.len())),
+        })
+}
+
+/// Convert text to tokens.
+pub fn tokenize(text: &str) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (token, len) = match_token(rest).ok_or_else(|| {
+            let consumed = &text[..text.len() - rest.len()];
+            let line_start = consumed.rfind('\n').map_or(0, |i| i + 1);
+            Error::UnrecognizedText {
+                line: consumed.matches('\n').count() + 1,
+                column: consumed[line_start..].chars().count() + 1,
+            }
+        })?;
+        if token > 0xFF {
+            out.push((token >> 8) as u8);
+        }
+        out.push(token as u8);
+        rest = &rest[len..];
+    }
+    Ok(out)
+}
+
+/// Get the text of a BASIC program variable.
+pub fn program_text(var: &Variable) -> Result<String, Error> {
+    if var.ty != VariableType::Program && var.ty != VariableType::ProtectedProgram {
+        return Err(Error::NotProgram);
+    }
+    let data = var.calc_data();
+    if data.starts_with(ASM_PROGRAM_SIGNATURE) {
+        return Err(Error::Assembly);
+    }
+    detokenize(data)
+}
+
+/// Tokenize text into a program variable.
+pub fn program_from_text(name: &[u8], text: &str, protected: bool) -> Result<Variable, Error> {
+    if name.is_empty() || name.len() > 8 {
+        return Err(Error::InvalidName);
+    }
+    let tokens = tokenize(text)?;
+    let mut data = (tokens.len() as u16).to_le_bytes().to_vec();
+    data.extend_from_slice(&tokens);
+
+    Ok(Variable {
+        name: name.into(),
+        ty: if protected {
+            VariableType::ProtectedProgram
+        } else {
+            VariableType::Program
+        },
+        version: Some(0),
+        flags: Some(0),
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vars::token_bytes;
+
+    #[test]
+    fn every_token_roundtrips() {
+        let mut texts = std::collections::HashSet::new();
+        for &(token, text) in TOKENS {
+            assert!(
+                texts.insert(text),
+                "{:04X} has the same text as another token",
+                token
+            );
+            let bytes = token_bytes(&[token]);
+            assert_eq!(
+                tokenize(text).as_ref(),
+                Ok(&bytes),
+                "{:04X} ({:?}) doesn't round-trip",
+                token,
+                text
+            );
+            assert_eq!(detokenize(&bytes).as_deref(), Ok(text));
+        }
+    }
+
+    #[test]
+    fn longest_match_and_aliases() {
+        // "sin⁻¹(" is a single token rather than "sin(" and "⁻¹"
+        assert_eq!(tokenize("sin⁻¹(X").unwrap(), vec![0xC3, 0x58]);
+        assert_eq!(tokenize("1->A").unwrap(), tokenize("1→A").unwrap());
+        assert_eq!(tokenize("A<=B").unwrap(), vec![0x41, 0x6D, 0x42]);
+        // Lowercase letters are two-byte tokens
+        assert_eq!(tokenize("ab").unwrap(), vec![0xBB, 0xB0, 0xBB, 0xB1]);
+        // Program names follow prgm as separate tokens
+        assert_eq!(split_tokens(&[0x5F, 0x41]).unwrap(), vec![0x5F, 0x41]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            tokenize("Disp 1\nDisp 2\u{1}"),
+            Err(Error::UnrecognizedText { line: 2, column: 7 })
+        );
+        assert_eq!(
+            detokenize(&[0x41, 0x00]),
+            Err(Error::UnknownToken {
+                offset: 1,
+                token: 0
+            })
+        );
+        assert_eq!(detokenize(&[0xBB]), Err(Error::Truncated));
+
+        let mut asm = program_from_text(b"ASM", "", false).unwrap();
+        asm.data = vec![3, 0, 0xBB, 0x6D, 0xC9];
+        assert_eq!(program_text(&asm), Err(Error::Assembly));
+    }
+
+    #[test]
+    fn program_variable() {
+        let var = program_from_text(b"HELLO", "Disp \"HI", true).unwrap();
+        assert_eq!(var.ty, VariableType::ProtectedProgram);
+        assert_eq!(&var.data[..2], &[4, 0]);
+        assert_eq!(program_text(&var).unwrap(), "Disp \"HI");
+    }
+}
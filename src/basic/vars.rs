@@ -0,0 +1,213 @@
+//! Values and variables of BASIC programs.
+//!
+//! Variables are stored in the VAT in the same formats TI-OS uses, so assembly
+//! programs called from BASIC can read and write them.
+
+use crate::float::Real;
+use crate::tifiles::{self, VariableType};
+use crate::{vat, Memory};
+
+/// Errors raised while running a program, displayed as `ERR:` followed by the
+/// error's name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Syntax,
+    DataType,
+    Argument,
+    DivideByZero,
+    Overflow,
+    Domain,
+    Undefined,
+    InvalidDim,
+    DimMismatch,
+    Label,
+    Memory,
+    Break,
+    /// The program uses a command that is not implemented.
+    Unsupported,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Get the name TI-OS displays for this error.
+    pub fn name(self) -> &'static str {
+        match self {
+            Error::Syntax => "SYNTAX",
+            Error::DataType => "DATA TYPE",
+            Error::Argument => "ARGUMENT",
+            Error::DivideByZero => "DIVIDE BY 0",
+            Error::Overflow => "OVERFLOW",
+            Error::Domain => "DOMAIN",
+            Error::Undefined => "UNDEFINED",
+            Error::InvalidDim => "INVALID DIM",
+            Error::DimMismatch => "DIM MISMATCH",
+            Error::Label => "LABEL",
+            Error::Memory => "MEMORY",
+            Error::Break => "BREAK",
+            Error::Unsupported => "UNSUPPORTED",
+        }
+    }
+}
+
+impl From<crate::float::Error> for Error {
+    fn from(other: crate::float::Error) -> Self {
+        use crate::float::Error as E;
+        match other {
+            E::Overflow => Error::Overflow,
+            E::DivideByZero => Error::DivideByZero,
+            E::Invalid => Error::Domain,
+        }
+    }
+}
+
+impl From<vat::Error> for Error {
+    fn from(_: vat::Error) -> Self {
+        Error::Memory
+    }
+}
+
+/// The value of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Real(Real),
+    /// A string as the tokens it contains.
+    Str(Vec<u16>),
+    List(Vec<Real>),
+}
+
+impl Value {
+    pub fn real(&self) -> Result<Real> {
+        match self {
+            Value::Real(x) => Ok(*x),
+            _ => Err(Error::DataType),
+        }
+    }
+}
+
+/// A variable that can be stored to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Var {
+    /// A real variable named by its token: A-Z, θ or Ans.
+    Real(u8),
+    /// Str1-Str0, numbered 0-9 in token order.
+    Str(u8),
+    /// L₁-L₆, numbered 0-5.
+    List(u8),
+}
+
+/// Token of the Ans variable, which is also its name.
+pub const ANS: u8 = 0x72;
+
+impl Var {
+    /// Get the variable named by a token.
+    pub fn from_token(token: u16) -> Option<Var> {
+        match token {
+            0x41..=0x5B => Some(Var::Real(token as u8)),
+            0x72 => Some(Var::Real(ANS)),
+            0xAA00..=0xAA09 => Some(Var::Str(token as u8)),
+            0x5D00..=0x5D05 => Some(Var::List(token as u8)),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> Vec<u8> {
+        match self {
+            Var::Real(t) => vec![t],
+            Var::Str(n) => vec![0xAA, n],
+            Var::List(n) => vec![0x5D, n],
+        }
+    }
+
+    fn ty(self) -> VariableType {
+        match self {
+            Var::Real(_) => VariableType::Real,
+            Var::Str(_) => VariableType::String,
+            Var::List(_) => VariableType::List,
+        }
+    }
+}
+
+/// Convert tokens to the bytes that represent them.
+pub fn token_bytes(tokens: &[u16]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(tokens.len());
+    for &token in tokens {
+        if token > 0xFF {
+            out.push((token >> 8) as u8);
+        }
+        out.push(token as u8);
+    }
+    out
+}
+
+/// Get the value of a variable.
+///
+/// Real variables that do not exist have the value 0, and Ans may hold a value
+/// of any type.
+pub fn recall(mem: &Memory, var: Var) -> Result<Value> {
+    // The symbol table is searched by name only, so this finds Ans of any type.
+    let entry = match vat::find(mem, var.ty(), &var.name()) {
+        Some(entry) if entry.in_ram() => entry,
+        Some(_) => return Err(Error::Memory),
+        None if matches!(var, Var::Real(_)) => return Ok(Value::Real(Real::ZERO)),
+        None => return Err(Error::Undefined),
+    };
+    let size = vat::data_size(mem, &entry);
+    let data: Vec<u8> = (entry.data..entry.data + size).map(|a| mem[a]).collect();
+
+    let ty = entry.ty().ok_or(Error::DataType)?;
+    if ty == VariableType::String {
+        return super::tokens::split_tokens(&data[2..])
+            .map(Value::Str)
+            .map_err(|_| Error::DataType);
+    }
+    let var = tifiles::Variable {
+        name: entry.name.clone(),
+        ty,
+        version: None,
+        flags: None,
+        data,
+    };
+    match var.value() {
+        Ok(tifiles::Value::Real(x)) => Ok(Value::Real(x)),
+        Ok(tifiles::Value::List(xs)) => Ok(Value::List(xs)),
+        _ => Err(Error::DataType),
+    }
+}
+
+/// Set the value of a variable, creating it if it does not exist.
+pub fn store(mem: &mut Memory, var: Var, value: &Value) -> Result<()> {
+    match (var, value) {
+        (Var::Real(ANS), _)
+        | (Var::Real(_), Value::Real(_))
+        | (Var::Str(_), Value::Str(_))
+        | (Var::List(_), Value::List(_)) => {}
+        _ => return Err(Error::DataType),
+    }
+    let (ty, data) = encode(var, value)?;
+    delete(mem, var);
+    vat::insert(mem, ty, &var.name(), &data)?;
+    Ok(())
+}
+
+fn encode(var: Var, value: &Value) -> Result<(VariableType, Vec<u8>)> {
+    let value = match value {
+        Value::Str(tokens) => {
+            let bytes = token_bytes(tokens);
+            let mut data = (bytes.len() as u16).to_le_bytes().to_vec();
+            data.extend_from_slice(&bytes);
+            return Ok((VariableType::String, data));
+        }
+        Value::Real(x) => tifiles::Value::Real(*x),
+        Value::List(xs) => tifiles::Value::List(xs.clone()),
+    };
+    let var = tifiles::Variable::from_value(&var.name(), &value).map_err(|_| Error::InvalidDim)?;
+    Ok((var.ty, var.data))
+}
+
+/// Delete a variable if it exists.
+pub fn delete(mem: &mut Memory, var: Var) {
+    if let Some(entry) = vat::find(mem, var.ty(), &var.name()) {
+        vat::delete(mem, &entry);
+    }
+}
@@ -69,7 +69,7 @@ fn put_char_scrolling(emu: &mut Emulator, cpu: &Z80, c: u8) {
     emu.mem[tios::curRow] = y;
 }
 
-fn put_char(emu: &mut Emulator, c: u8, col: u8, row: u8) {
+pub fn put_char(emu: &mut Emulator, c: u8, col: u8, row: u8) {
     assert!(
         col < 16 && row < 8,
         "Screen coordinates ({}, {}) are out of bounds",
@@ -0,0 +1,427 @@
+//! Floating point registers, stack and arithmetic.
+//!
+//! The OP registers OP1 through OP6 are 11 bytes each, which holds a 9-byte real
+//! number or variable name with some room for the OS to use extra digits. Complex
+//! numbers occupy two consecutive registers, and the floating point stack (FPS)
+//! grows upward from fpBase in 9-byte entries.
+//!
+//! TI-OS raises an error for invalid operations like division by zero, but we
+//! don't support errors so they are logged and leave the operands unchanged.
+//! Arithmetic operates only on reals; complex operands are treated as their real
+//! parts.
+#![allow(non_snake_case)]
+
+use crate::float::{self, Real, COMPLEX_TYPE, REAL_TYPE};
+use crate::include::tios;
+use crate::{vat, Emulator, Flags, Z80};
+
+/// Size of an OP register.
+const OP_SIZE: u16 = 11;
+/// Size of a real number and FPS entry.
+const REAL_SIZE: u16 = 9;
+
+/// Get the address of register OPn.
+fn op(n: u8) -> u16 {
+    debug_assert!((1..=6).contains(&n));
+    tios::OP1 + (n as u16 - 1) * OP_SIZE
+}
+
+fn read_real(emu: &Emulator, addr: u16) -> Real {
+    let mut bytes = [0; REAL_SIZE as usize];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = emu.mem[addr + i as u16];
+    }
+    Real::from_bytes(&bytes)
+}
+
+fn write_real(emu: &mut Emulator, addr: u16, value: Real) {
+    for (i, &b) in value.to_bytes(REAL_TYPE).iter().enumerate() {
+        emu.mem[addr + i as u16] = b;
+    }
+}
+
+fn is_complex(emu: &Emulator, addr: u16) -> bool {
+    emu.mem[addr] & 0x1F == COMPLEX_TYPE
+}
+
+/// Copy `count` bytes from HL to DE, leaving both pointing past the copied data
+/// like `ldir`.
+fn copy_hl_de(emu: &mut Emulator, core: &mut Z80, count: u16) -> usize {
+    let regs = core.regs_mut();
+    for _ in 0..count {
+        let b = emu.mem[regs.hl];
+        let _ = emu.mem.put(regs.de, b);
+        regs.hl = regs.hl.wrapping_add(1);
+        regs.de = regs.de.wrapping_add(1);
+    }
+    21 * count as usize
+}
+
+pub fn OPxToOPy(emu: &mut Emulator, x: u8, y: u8) -> usize {
+    for i in 0..OP_SIZE {
+        emu.mem[op(y) + i] = emu.mem[op(x) + i];
+    }
+    250
+}
+
+pub fn OPxExOPy(emu: &mut Emulator, x: u8, y: u8) -> usize {
+    for i in 0..OP_SIZE {
+        let tmp = emu.mem[op(x) + i];
+        emu.mem[op(x) + i] = emu.mem[op(y) + i];
+        emu.mem[op(y) + i] = tmp;
+    }
+    450
+}
+
+/// Set OPx to a small integer, as in the `OP1Set0` family.
+pub fn OPxSet(emu: &mut Emulator, x: u8, value: i64) -> usize {
+    write_real(emu, op(x), Real::from_int(value));
+    150
+}
+
+/// Set all the bytes of OPx to zero.
+pub fn ZeroOPx(emu: &mut Emulator, x: u8) -> usize {
+    for i in 0..OP_SIZE {
+        emu.mem[op(x) + i] = 0;
+    }
+    100
+}
+
+/// Copy `count` bytes from HL to DE, as in `Mov9B`.
+pub fn MovNB(emu: &mut Emulator, core: &mut Z80, count: u16) -> usize {
+    copy_hl_de(emu, core, count)
+}
+
+/// Copy 9 bytes from HL to OPx.
+pub fn Mov9ToOPx(emu: &mut Emulator, core: &mut Z80, x: u8) -> usize {
+    core.regs_mut().de = op(x);
+    copy_hl_de(emu, core, REAL_SIZE)
+}
+
+/// Copy 9 bytes from OP1 to DE.
+pub fn MovFrOP1(emu: &mut Emulator, core: &mut Z80) -> usize {
+    core.regs_mut().hl = tios::OP1;
+    copy_hl_de(emu, core, REAL_SIZE)
+}
+
+/// Push 9 bytes from `src` onto the FPS.
+fn push_fps(emu: &mut Emulator, src: u16) {
+    if vat::free_ram(&emu.mem) < REAL_SIZE {
+        error!("Floating point stack overflow pushing from {:04X}", src);
+        return;
+    }
+    let fps = emu.mem.read_u16(tios::FPS);
+    for i in 0..REAL_SIZE {
+        emu.mem[fps + i] = emu.mem[src + i];
+    }
+    emu.mem.write_u16(tios::FPS, fps + REAL_SIZE);
+}
+
+/// Pop 9 bytes from the FPS into `dst`.
+fn pop_fps(emu: &mut Emulator, dst: u16) {
+    let fps = emu.mem.read_u16(tios::FPS);
+    // A program may have moved FPS below fpBase, which leaves the stack empty
+    if fps.saturating_sub(emu.mem.read_u16(tios::fpBase)) < REAL_SIZE {
+        error!("Floating point stack underflow popping to {:04X}", dst);
+        return;
+    }
+    let fps = fps - REAL_SIZE;
+    for i in 0..REAL_SIZE {
+        emu.mem[dst + i] = emu.mem[fps + i];
+    }
+    emu.mem.write_u16(tios::FPS, fps);
+}
+
+/// Push the real number in OPx onto the FPS.
+pub fn PushRealOx(emu: &mut Emulator, x: u8) -> usize {
+    push_fps(emu, op(x));
+    250
+}
+
+/// Push the real number at HL onto the FPS.
+pub fn PushReal(emu: &mut Emulator, core: &mut Z80) -> usize {
+    push_fps(emu, core.regs().hl);
+    250
+}
+
+/// Push the number in OPx onto the FPS, along with the following register if
+/// it is complex.
+pub fn PushOPx(emu: &mut Emulator, x: u8) -> usize {
+    push_fps(emu, op(x));
+    if is_complex(emu, op(x)) {
+        push_fps(emu, op(x + 1));
+        return 500;
+    }
+    250
+}
+
+/// Pop a real number from the FPS into OPx.
+pub fn PopRealOx(emu: &mut Emulator, x: u8) -> usize {
+    pop_fps(emu, op(x));
+    250
+}
+
+/// Pop a real number from the FPS into the memory at DE.
+pub fn PopReal(emu: &mut Emulator, core: &mut Z80) -> usize {
+    pop_fps(emu, core.regs().de);
+    250
+}
+
+/// Pop a number from the FPS into OPx, or into OPx and the following register
+/// if it is complex.
+pub fn PopOPx(emu: &mut Emulator, x: u8) -> usize {
+    let top = emu.mem.read_u16(tios::FPS).wrapping_sub(REAL_SIZE);
+    if is_complex(emu, top) {
+        pop_fps(emu, op(x + 1));
+        pop_fps(emu, op(x));
+        return 500;
+    }
+    pop_fps(emu, op(x));
+    250
+}
+
+/// Store the result of an operation in OPx, or log the error.
+fn store_result(emu: &mut Emulator, x: u8, result: float::Result<Real>) {
+    match result {
+        Ok(value) => write_real(emu, op(x), value),
+        Err(e) => error!("Floating point error: {:?}", e),
+    }
+}
+
+/// Compute OP1 = OP1 <operation> OP2.
+fn binary_op(emu: &mut Emulator, f: fn(&Real, &Real) -> float::Result<Real>) {
+    let a = read_real(emu, tios::OP1);
+    let b = read_real(emu, tios::OP2);
+    store_result(emu, 1, f(&a, &b));
+}
+
+pub fn FPAdd(emu: &mut Emulator) -> usize {
+    binary_op(emu, Real::add);
+    1000
+}
+
+pub fn FPSub(emu: &mut Emulator) -> usize {
+    binary_op(emu, Real::sub);
+    1000
+}
+
+pub fn FPMult(emu: &mut Emulator) -> usize {
+    binary_op(emu, Real::mul);
+    3000
+}
+
+pub fn FPDiv(emu: &mut Emulator) -> usize {
+    binary_op(emu, Real::div);
+    5000
+}
+
+pub fn FPSquare(emu: &mut Emulator) -> usize {
+    let a = read_real(emu, tios::OP1);
+    store_result(emu, 1, a.mul(&a));
+    3000
+}
+
+pub fn FPRecip(emu: &mut Emulator) -> usize {
+    let a = read_real(emu, tios::OP1);
+    store_result(emu, 1, Real::from_int(1).div(&a));
+    5000
+}
+
+/// Add `value` to OP1, as in `Plus1` and `Minus1`.
+pub fn OP1PlusN(emu: &mut Emulator, value: i64) -> usize {
+    let a = read_real(emu, tios::OP1);
+    store_result(emu, 1, a.add(&Real::from_int(value)));
+    1000
+}
+
+pub fn Times2(emu: &mut Emulator) -> usize {
+    let a = read_real(emu, tios::OP1);
+    store_result(emu, 1, a.add(&a));
+    1000
+}
+
+/// Negate OPx, as in `InvOP1S`.
+pub fn InvOPxS(emu: &mut Emulator, x: u8) -> usize {
+    if read_real(emu, op(x)).is_zero() {
+        return 50;
+    }
+    emu.mem[op(x)] ^= 0x80;
+    50
+}
+
+/// Replace OP1 with the greatest integer less than or equal to it.
+pub fn Int(emu: &mut Emulator) -> usize {
+    let a = read_real(emu, tios::OP1);
+    store_result(emu, 1, Ok(a.floor()));
+    300
+}
+
+/// Replace OP1 with its integer part.
+pub fn Trunc(emu: &mut Emulator) -> usize {
+    let a = read_real(emu, tios::OP1);
+    store_result(emu, 1, Ok(a.trunc()));
+    300
+}
+
+/// Replace OP1 with its fractional part.
+pub fn Frac(emu: &mut Emulator) -> usize {
+    let a = read_real(emu, tios::OP1);
+    store_result(emu, 1, Ok(a.fract()));
+    300
+}
+
+/// Compare OP1 to OP2, setting Z if they are equal and C if OP1 is less.
+pub fn CpOP1OP2(emu: &mut Emulator, core: &mut Z80) -> usize {
+    let a = read_real(emu, tios::OP1);
+    let b = read_real(emu, tios::OP2);
+    let mut flags = core.flags() - (Flags::Z | Flags::C);
+    match a.cmp(&b) {
+        std::cmp::Ordering::Equal => flags |= Flags::Z,
+        std::cmp::Ordering::Less => flags |= Flags::C,
+        std::cmp::Ordering::Greater => {}
+    }
+    core.set_flags(flags);
+    300
+}
+
+/// Set or clear the Z flag.
+fn set_z(core: &mut Z80, value: bool) {
+    let mut flags = core.flags();
+    flags.set(Flags::Z, value);
+    core.set_flags(flags);
+}
+
+/// Set Z if OP1 is zero.
+pub fn CkOP1FP0(emu: &mut Emulator, core: &mut Z80) -> usize {
+    set_z(core, read_real(emu, tios::OP1).is_zero());
+    60
+}
+
+/// Set Z if OP1 is positive (or zero).
+pub fn CkOP1Pos(emu: &mut Emulator, core: &mut Z80) -> usize {
+    set_z(core, !read_real(emu, tios::OP1).is_negative());
+    60
+}
+
+/// Set A to the type of OP1 and Z if it is real.
+pub fn CkOP1Real(emu: &mut Emulator, core: &mut Z80) -> usize {
+    let ty = emu.mem[tios::OP1] & 0x1F;
+    core.regs_mut().set_a(ty);
+    set_z(core, ty == REAL_TYPE);
+    40
+}
+
+/// Convert the value in A to a number in OPx, as in `SetXXOP1`.
+pub fn SetXXOPx(emu: &mut Emulator, core: &mut Z80, x: u8) -> usize {
+    let value = core.regs().get_a();
+    write_real(emu, op(x), Real::from_int(value as i64));
+    400
+}
+
+/// Convert the value in HL to a number in OP2.
+pub fn SetXXXXOP2(emu: &mut Emulator, core: &mut Z80) -> usize {
+    let value = core.regs().hl;
+    write_real(emu, op(2), Real::from_int(value as i64));
+    600
+}
+
+/// Convert the integer part of OP1 to an integer in DE, which must be less
+/// than 10000. A is also set to the low byte of the result.
+pub fn ConvOP1(emu: &mut Emulator, core: &mut Z80) -> usize {
+    let value = read_real(emu, tios::OP1).trunc().abs();
+    match value.to_int() {
+        Ok(n @ 0..=9999) => {
+            let regs = core.regs_mut();
+            regs.de = n as u16;
+            regs.set_a(n as u8);
+        }
+        _ => error!("ConvOP1 of out-of-range value {}", value),
+    }
+    600
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_op(emu: &mut Emulator, x: u8, s: &str) {
+        write_real(emu, op(x), s.parse().unwrap());
+    }
+
+    fn get_op(emu: &Emulator, x: u8) -> String {
+        read_real(emu, op(x)).to_string()
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut emu = Emulator::new();
+        set_op(&mut emu, 1, "1.5");
+        set_op(&mut emu, 2, "-4");
+        FPMult(&mut emu);
+        assert_eq!(get_op(&emu, 1), "-6");
+        FPDiv(&mut emu);
+        assert_eq!(get_op(&emu, 1), "1.5");
+        FPAdd(&mut emu);
+        assert_eq!(get_op(&emu, 1), "-2.5");
+        Int(&mut emu);
+        assert_eq!(get_op(&emu, 1), "-3");
+
+        // Division by zero leaves OP1 unchanged
+        OPxSet(&mut emu, 2, 0);
+        FPDiv(&mut emu);
+        assert_eq!(get_op(&emu, 1), "-3");
+    }
+
+    #[test]
+    fn moves_and_compare() {
+        let mut emu = Emulator::new();
+        let mut cpu = Z80::new();
+        set_op(&mut emu, 1, "2");
+        OPxToOPy(&mut emu, 1, 5);
+        set_op(&mut emu, 1, "3");
+        OPxExOPy(&mut emu, 1, 5);
+        assert_eq!(get_op(&emu, 1), "2");
+        assert_eq!(get_op(&emu, 5), "3");
+
+        OPxToOPy(&mut emu, 5, 2);
+        CpOP1OP2(&mut emu, &mut cpu);
+        assert!(cpu.flags().contains(Flags::C) && !cpu.flags().contains(Flags::Z));
+
+        cpu.regs_mut().hl = 1234;
+        SetXXXXOP2(&mut emu, &mut cpu);
+        OPxToOPy(&mut emu, 2, 1);
+        ConvOP1(&mut emu, &mut cpu);
+        assert_eq!(cpu.regs().de, 1234);
+    }
+
+    #[test]
+    fn fp_stack() {
+        let mut emu = Emulator::new();
+        let base = emu.mem.read_u16(tios::FPS);
+        set_op(&mut emu, 1, "7");
+        PushRealOx(&mut emu, 1);
+        assert_eq!(emu.mem.read_u16(tios::FPS), base + 9);
+
+        // Complex numbers push both parts
+        emu.mem[op(3)] = COMPLEX_TYPE;
+        emu.mem[op(4)] = COMPLEX_TYPE | 0x80;
+        PushOPx(&mut emu, 3);
+        assert_eq!(emu.mem.read_u16(tios::FPS), base + 27);
+        ZeroOPx(&mut emu, 3);
+        ZeroOPx(&mut emu, 4);
+        PopOPx(&mut emu, 5);
+        assert_eq!(emu.mem[op(5)], COMPLEX_TYPE);
+        assert_eq!(emu.mem[op(6)], COMPLEX_TYPE | 0x80);
+
+        PopRealOx(&mut emu, 2);
+        assert_eq!(get_op(&emu, 2), "7");
+        assert_eq!(emu.mem.read_u16(tios::FPS), base);
+
+        // Popping an empty stack does nothing, even if FPS is below fpBase
+        PopRealOx(&mut emu, 2);
+        emu.mem.write_u16(tios::FPS, base - 1);
+        PopRealOx(&mut emu, 2);
+        assert_eq!(emu.mem.read_u16(tios::FPS), base - 1);
+        assert_eq!(get_op(&emu, 2), "7");
+    }
+}
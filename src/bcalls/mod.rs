@@ -1,7 +1,9 @@
 use crate::{Emulator, Z80};
 
 pub mod display;
+pub mod float;
 pub mod memory;
+pub mod symbols;
 pub mod util;
 
 const VECTOR_TABLE_PAGE: u8 = 0x1B;
@@ -14,13 +16,26 @@ pub fn bcall_trap(emu: &mut Emulator, core: &mut Z80) -> usize {
     // Get vector table location
     let bcall_addr = emu.mem.read_u16(ret_addr);
 
-    // Read target from vector table
-    assert!(
-        bcall_addr >= 0x4000 && bcall_addr < 0x8000,
-        "Non-OS bcalls are not currently supported"
-    );
-    let target_page = emu.mem.read_paged(VECTOR_TABLE_PAGE, bcall_addr);
-    let target_addr = emu.mem.read_u16_paged(VECTOR_TABLE_PAGE, bcall_addr + 1);
+    // Read target from the vector table, or the running app's branch table for
+    // bcalls below 4000. Branch table entries are an address followed by a page
+    // number counting down from the app's first page.
+    let (target_page, target_addr) = match (bcall_addr, emu.app_page) {
+        (0x4000..=0x7FFF, _) => (
+            emu.mem.read_paged(VECTOR_TABLE_PAGE, bcall_addr),
+            emu.mem.read_u16_paged(VECTOR_TABLE_PAGE, bcall_addr + 1),
+        ),
+        (0..=0x3FFF, Some(app_page)) => {
+            let entry = 0x4000 + bcall_addr;
+            (
+                app_page.wrapping_sub(emu.mem.read_paged(app_page, entry + 2)),
+                emu.mem.read_u16_paged(app_page, entry),
+            )
+        }
+        _ => panic!(
+            "bcall {:04X} is not to the OS or a running app, which is not supported",
+            bcall_addr
+        ),
+    };
 
     if target_page == 0 && target_addr == 0 {
         if cfg!(debug_assertions) {
@@ -80,3 +95,7 @@ pub fn test_flag(emu: &Emulator, core: &Z80, byte: u8, bit: u8) -> bool {
 pub fn set_flag(emu: &mut Emulator, core: &Z80, byte: u8, bit: u8) {
     emu.mem[core.regs().iy.wrapping_add(byte as u16)] |= 1 << bit;
 }
+
+pub fn reset_flag(emu: &mut Emulator, core: &Z80, byte: u8, bit: u8) {
+    emu.mem[core.regs().iy.wrapping_add(byte as u16)] &= !(1 << bit);
+}
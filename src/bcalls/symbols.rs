@@ -0,0 +1,108 @@
+#![allow(non_snake_case)]
+
+use crate::include::tios;
+use crate::tifiles::VariableType;
+use crate::vat;
+use crate::{Emulator, Flags, Z80};
+use num_traits::FromPrimitive;
+
+/// Read the type and name of the variable named in OP1.
+fn read_op1(emu: &Emulator) -> (u8, Vec<u8>) {
+    let ty = emu.mem[tios::OP1] & 0x1F;
+    let name = (1..9)
+        .map(|i| emu.mem[tios::OP1 + i])
+        .take_while(|&c| c != 0)
+        .collect();
+    (ty, name)
+}
+
+/// Set registers with the result of a symbol search.
+///
+/// When found, HL points to the symbol entry, DE to the data, A is the variable
+/// type and B the flash page if archived. Carry is set if not found.
+fn return_symbol(core: &mut Z80, entry: Option<vat::Entry>) {
+    match entry {
+        None => core.set_flags(core.flags() | Flags::C),
+        Some(entry) => {
+            trace!("Found symbol {:?}", entry);
+            let regs = core.regs_mut();
+            regs.hl = entry.address;
+            regs.de = entry.data;
+            regs.set_a(entry.type_byte & 0x1F);
+            regs.bc = (regs.bc & 0xFF) | (entry.page as u16) << 8;
+            core.set_flags(core.flags() - Flags::C);
+        }
+    }
+}
+
+/// Search the symbol table for the variable named in OP1, which is identified only
+/// by its name.
+pub fn FindSym(emu: &mut Emulator, core: &mut Z80) -> usize {
+    let (_, name) = read_op1(emu);
+    return_symbol(core, vat::find(&emu.mem, VariableType::Real, &name));
+    300
+}
+
+/// Search for the variable named in OP1, in the program table if the type in
+/// OP1 is a program, appvar or group and the symbol table otherwise.
+///
+/// A type that isn't a valid variable type names no variable, so is not found.
+pub fn ChkFindSym(emu: &mut Emulator, core: &mut Z80) -> usize {
+    let (ty, name) = read_op1(emu);
+    let entry = VariableType::from_u8(ty).and_then(|ty| vat::find(&emu.mem, ty, &name));
+    return_symbol(core, entry);
+    300
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChkFindSym, FindSym};
+    use crate::include::tios;
+    use crate::tifiles::VariableType;
+    use crate::{vat, Emulator, Flags, Z80};
+
+    #[test]
+    fn chkfindsym() {
+        let mut emu = Emulator::new();
+        let mut cpu = Z80::new();
+        vat::clear(&mut emu.mem);
+        let entry = vat::insert(&mut emu.mem, VariableType::AppVar, b"SAVE", &[0, 0]).unwrap();
+
+        emu.mem[tios::OP1..tios::OP1 + 6].copy_from_slice(b"\x15SAVE\0");
+        ChkFindSym(&mut emu, &mut cpu);
+        assert!(!cpu.flags().contains(Flags::C));
+        assert_eq!(cpu.regs().hl, entry.address);
+        assert_eq!(cpu.regs().de, tios::userMem);
+        assert_eq!(cpu.regs().get_a(), 0x15);
+        assert_eq!(cpu.regs().bc >> 8, 0);
+
+        // Programs aren't appvars
+        emu.mem[tios::OP1] = VariableType::Program as u8;
+        ChkFindSym(&mut emu, &mut cpu);
+        assert!(cpu.flags().contains(Flags::C));
+
+        // Nor is a type that doesn't exist
+        emu.mem[tios::OP1] = 0x1F;
+        ChkFindSym(&mut emu, &mut cpu);
+        assert!(cpu.flags().contains(Flags::C));
+    }
+
+    #[test]
+    fn findsym() {
+        let mut emu = Emulator::new();
+        let mut cpu = Z80::new();
+        vat::clear(&mut emu.mem);
+        let entry = vat::insert(&mut emu.mem, VariableType::List, b"\x5D\x01", &[0, 0]).unwrap();
+
+        // The type in OP1 is ignored
+        emu.mem[tios::OP1..tios::OP1 + 4].copy_from_slice(b"\x00\x5D\x01\0");
+        FindSym(&mut emu, &mut cpu);
+        assert!(!cpu.flags().contains(Flags::C));
+        assert_eq!(cpu.regs().hl, entry.address);
+        assert_eq!(cpu.regs().get_a(), VariableType::List as u8);
+
+        emu.mem[tios::OP1 + 2] = 2;
+        FindSym(&mut emu, &mut cpu);
+        assert!(cpu.flags().contains(Flags::C));
+    }
+}
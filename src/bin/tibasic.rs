@@ -0,0 +1,82 @@
+//! Convert TI-BASIC programs to and from text.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::exit;
+use tihle::basic::tokens;
+use tihle::tifiles;
+
+const USAGE: &str = "\
+Usage:
+    tibasic detokenize PROGRAM.8xp [OUTPUT.txt]
+    tibasic tokenize [--protected] NAME INPUT.txt OUTPUT.8xp
+
+Files named - are standard input or output.";
+
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    exit(1)
+}
+
+fn read_input(path: &str) -> Vec<u8> {
+    let mut data = vec![];
+    let result = if path == "-" {
+        std::io::stdin().read_to_end(&mut data)
+    } else {
+        File::open(path).and_then(|mut f| f.read_to_end(&mut data))
+    };
+    if let Err(e) = result {
+        fail(format!("Failed to read {}: {}", path, e));
+    }
+    data
+}
+
+fn write_output(path: &str, data: &[u8]) {
+    let result = if path == "-" {
+        std::io::stdout().write_all(data)
+    } else {
+        File::create(path).and_then(|mut f| f.write_all(data))
+    };
+    if let Err(e) = result {
+        fail(format!("Failed to write {}: {}", path, e));
+    }
+}
+
+fn detokenize(input: &str, output: &str) {
+    let file = tifiles::File::read_from(&read_input(input)[..])
+        .unwrap_or_else(|e| fail(format!("Failed to read program: {:?}", e)));
+    let text = tokens::program_text(&file.var)
+        .unwrap_or_else(|e| fail(format!("Failed to detokenize program: {:?}", e)));
+    write_output(output, text.as_bytes());
+}
+
+fn tokenize(name: &str, input: &str, output: &str, protected: bool) {
+    let text = String::from_utf8(read_input(input))
+        .unwrap_or_else(|_| fail(format!("{} is not valid UTF-8", input)));
+    let var = tokens::program_from_text(name.as_bytes(), &text, protected)
+        .unwrap_or_else(|e| fail(format!("Failed to tokenize program: {:?}", e)));
+    let mut data = vec![];
+    tifiles::File::new(var)
+        .write_to(&mut data)
+        .expect("Writing to memory should not fail");
+    write_output(output, &data);
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let protected = match args.iter().position(|a| a == "--protected") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["detokenize", input] => detokenize(input, "-"),
+        ["detokenize", input, output] => detokenize(input, output),
+        ["tokenize", name, input, output] => tokenize(name, input, output, protected),
+        _ => fail(USAGE),
+    }
+}
@@ -97,14 +97,23 @@ impl<'a> Video<'a> {
         }
     }
 
-    fn update(&mut self, display: &Display) {
+    fn update(&mut self, display: &mut Display) {
+        let (on_level, off_level) = if display.is_on() {
+            contrast_levels(display.contrast())
+        } else {
+            (0xFF, 0xFF)
+        };
+
         // Simple YV12 conversion: write luminance bytes and leave chroma untouched
-        for (&src, dst) in display.get_buffer().iter().zip(self.texture_buf.iter_mut()) {
-            if src != 0 {
-                *dst = 0;
-            } else {
-                *dst = 0xFF;
-            }
+        // Pixels are blended between the two levels according to how long they've
+        // been lit, which is either always or never when not in grayscale mode.
+        for (&src, dst) in display
+            .grayscale_buffer()
+            .iter()
+            .zip(self.texture_buf.iter_mut())
+        {
+            let (on, off) = (on_level as u32, off_level as u32);
+            *dst = ((on * src as u32 + off * (0xFF - src as u32)) / 0xFF) as u8;
         }
 
         // PixelFormatEnum.byte_size* include all three planes so we don't have a convenient way
@@ -136,6 +145,21 @@ impl<'a> Video<'a> {
     }
 }
 
+/// Get the luminance of lit and unlit pixels for a given display contrast.
+///
+/// Pixels are black at the contrast TI-OS normally uses and lighter below that,
+/// while the background darkens at higher contrasts.
+fn contrast_levels(contrast: u8) -> (u8, u8) {
+    const NORMAL: u32 = 0x30;
+    let contrast = contrast as u32;
+
+    if contrast <= NORMAL {
+        ((0xFF * (NORMAL - contrast) / NORMAL) as u8, 0xFF)
+    } else {
+        (0, (0xFF - (contrast - NORMAL) * 12) as u8)
+    }
+}
+
 pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
@@ -179,12 +203,50 @@ fn main() {
     let mut video = Video::setup(&mut window);
     let mut events = sdl_context.event_pump().unwrap();
 
-    let mut emulator = tihle::Emulator::new();
     let mut cpu = tihle::Z80::new();
+    let mut rom = None;
+    let mut os = None;
+    let mut model = None;
+    let mut run = None;
+    let mut paths = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rom" => rom = args.next(),
+            "--os" => os = args.next(),
+            "--model" => match args.next().map(|m| m.parse()) {
+                Some(Ok(m)) => model = Some(m),
+                _ => {
+                    error!("--model must be one of 83p, 83pse, 84p or 84pse");
+                    std::process::exit(1);
+                }
+            },
+            "--run" => run = args.next(),
+            _ => paths.push(arg),
+        }
+    }
 
-    if let Some(path) = std::env::args().skip(1).next() {
-        load_program(&mut emulator, &mut cpu, &path);
+    let user_os = rom.is_some() || os.is_some();
+    if user_os && !paths.is_empty() {
+        warn!(
+            "Files can't be loaded with a user-supplied OS; ignoring {:?}",
+            paths
+        );
     }
+    let emulator = match (rom, os) {
+        (Some(path), _) => boot_rom(&path, model),
+        (None, Some(path)) => boot_os(&path, model),
+        (None, None) => {
+            let mut emulator = tihle::Emulator::with_model(model.unwrap_or_default());
+            let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+            load_files(&mut emulator, &mut cpu, &paths, run.as_deref());
+            Some(emulator)
+        }
+    };
+    let mut emulator = match emulator {
+        Some(emulator) => emulator,
+        None => std::process::exit(1),
+    };
 
     let target_frame_time = Duration::from_secs(1) / 60;
     loop {
@@ -334,7 +396,7 @@ fn main() {
     };
 
     if let Some(path) = std::env::args().skip(1).next() {
-        load_program(emulator, cpu, &path);
+        load_files(emulator, cpu, &[&path], None);
     }
 
     extern "C" fn wrap_iterate(millis: f64, _: *mut emscripten::c_void) -> emscripten::EM_BOOL {
@@ -375,17 +437,123 @@ fn main() {
     }
 }
 
-fn load_program(emulator: &mut Emulator, mut cpu: &mut Z80, path: &str) {
-    match File::open(path) {
-        Ok(f) => {
-            if let Err(e) = emulator.load_program(&mut cpu, f) {
-                error!("Failed to load program from {:?}: {:?}", path, e);
+/// Load files into the emulator, then run a program.
+///
+/// Flash apps (8xk files) are installed and launched, and anything else is
+/// loaded as a variable. Then the program named `run` is run if given, or
+/// otherwise the first program that was loaded.
+fn load_files(emulator: &mut Emulator, cpu: &mut Z80, paths: &[&str], run: Option<&str>) {
+    use tihle::tifiles::VariableType;
+
+    let mut first_program = None;
+    for path in paths {
+        let path = std::path::Path::new(path);
+        let f = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Unable to open {:?} to load: {}", path, e);
+                continue;
+            }
+        };
+
+        let is_app = path
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("8xk"));
+        if is_app {
+            match emulator.install_app(f) {
+                Ok(app) => emulator.launch_app(cpu, &app),
+                Err(e) => error!("Failed to install app from {:?}: {:?}", path, e),
             }
+            continue;
         }
-        Err(e) => {
-            error!("Unable to open {:?} to load: {}", path, e);
+
+        match emulator.load_variable(f) {
+            Ok(var) => {
+                info!(
+                    "Loaded {:?} {:?} from {:?}",
+                    var.ty,
+                    String::from_utf8_lossy(&var.name),
+                    path
+                );
+                let is_program =
+                    var.ty == VariableType::Program || var.ty == VariableType::ProtectedProgram;
+                if is_program && first_program.is_none() {
+                    first_program = Some(var.name);
+                }
+            }
+            Err(e) => error!("Failed to load variable from {:?}: {:?}", path, e),
         }
     }
+
+    let name = match run {
+        Some(name) => name.as_bytes(),
+        None => match first_program {
+            Some(ref name) => name,
+            None => return,
+        },
+    };
+    if let Err(e) = emulator.run_program(cpu, name) {
+        error!(
+            "Failed to run program {:?}: {:?}",
+            String::from_utf8_lossy(name),
+            e
+        );
+    }
+}
+
+/// Construct an emulator to run the ROM dump at the given path, choosing the model
+/// according to its size if not specified.
+fn boot_rom(path: &str, model: Option<tihle::Model>) -> Option<Emulator> {
+    use tihle::Model;
+
+    let rom = match std::fs::read(path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            error!("Unable to read ROM from {:?}: {}", path, e);
+            return None;
+        }
+    };
+    // The 83+ SE and 84+ SE have the same amount of flash, so the 84+ SE is
+    // assumed unless chosen otherwise.
+    let model = model.or_else(|| {
+        [Model::TI83Plus, Model::TI84Plus, Model::TI84PlusSE]
+            .iter()
+            .copied()
+            .find(|m| m.flash_pages() as usize * 0x4000 == rom.len())
+    });
+    let model = match model {
+        Some(m) => m,
+        None => {
+            error!("ROM size {} is not that of any supported model", rom.len());
+            return None;
+        }
+    };
+
+    info!("Booting {:?} ROM from {:?}", model, path);
+    Emulator::with_rom(model, &rom)
+        .map_err(|e| error!("Failed to load ROM: {:?}", e))
+        .ok()
+}
+
+/// Construct an emulator to run the 8xu OS upgrade at the given path.
+fn boot_os(path: &str, model: Option<tihle::Model>) -> Option<Emulator> {
+    let os = match File::open(path).map(tihle::tifiles::FlashFile::read_from) {
+        Ok(Ok(os)) => os,
+        Ok(Err(e)) => {
+            error!("Unable to read OS from {:?}: {:?}", path, e);
+            return None;
+        }
+        Err(e) => {
+            error!("Unable to open OS {:?}: {}", path, e);
+            return None;
+        }
+    };
+    let model = model.unwrap_or_default();
+
+    info!("Booting {:?} OS from {:?}", model, path);
+    Emulator::with_os(os, model)
+        .map_err(|e| error!("Failed to load OS: {:?}", e))
+        .ok()
 }
 
 /// Run a single iteration of emulation, until the emulated CPU has run for `frame_time`.
@@ -404,12 +572,20 @@ fn iterate_main(
         use std::convert::TryInto;
 
         match event {
+            Event::KeyDown {
+                keycode: Some(Keycode::F9),
+                ..
+            } => {
+                let grayscale = !emu.display.is_grayscale();
+                emu.display.set_grayscale(grayscale);
+                video.show_status(if grayscale { "grayscale" } else { "monochrome" });
+            }
             Event::KeyDown {
                 keycode: Some(k), ..
             } => {
                 if let Ok(k) = k.try_into() {
                     debug!("Key down: {:?}", k);
-                    emu.keyboard.key_down(k);
+                    emu.key_down(k);
                 } else {
                     debug!("Ignoring unhandled key {:?}", k);
                 }
@@ -419,21 +595,12 @@ fn iterate_main(
             } => {
                 if let Ok(k) = k.try_into() {
                     debug!("Key up: {:?}", k);
-                    emu.keyboard.key_up(k);
+                    emu.key_up(k);
                 }
             }
             Event::DropFile { filename, .. } => {
-                emu.reset();
-                match File::open(filename) {
-                    Ok(f) => {
-                        if let Err(e) = emu.load_program(cpu, f) {
-                            error!("Failed to load program: {:?}", e);
-                        }
-                    }
-                    Err(e) => {
-                        error!("Unable to open file to load: {}", e);
-                    }
-                }
+                // Dropped programs run, while other variables are only loaded
+                load_files(emu, cpu, &[&filename], None);
             }
             Event::Quit { .. } => {
                 return true;
@@ -455,6 +622,6 @@ fn iterate_main(
     }
 
     debug!("CPU run complete; swap display");
-    video.update(&emu.display);
+    video.update(&mut emu.display);
     false
 }
@@ -0,0 +1,331 @@
+//! Crystal timers on the 83+ SE and 84+.
+
+use crate::scheduler::{Event, Scheduler};
+
+/// The three crystal timers, controlled by ports 0x30-0x38.
+///
+/// Each timer uses three consecutive ports, starting at 0x30 for the first,
+/// 0x33 for the second and 0x36 for the third:
+///
+///  * The first port selects the clock source. Zero stops the timer, values
+///    0x40-0x47 select a division of the 32768 Hz crystal and values 0x80-0x87
+///    divide the CPU clock by a power of two.
+///  * The second port controls behavior on expiry. When written, bit 0 makes the
+///    timer restart counting when it expires and bit 1 causes an interrupt when it
+///    expires. Writing this port also acknowledges expiry. When read, bit 0 is set
+///    if the timer has expired and bit 1 if it expired again before being
+///    acknowledged.
+///  * The third port is the counter. Writing it starts the timer counting down
+///    from the written value, and reading returns the current count.
+///
+/// See [WikiTI](https://wikiti.brandonw.net/index.php?title=83Plus:Ports:30) for
+/// further details.
+#[derive(Debug)]
+pub struct CrystalTimers {
+    timers: [CrystalTimer; 3],
+    /// Used to compute tick rates in CPU cycles.
+    cpu_clock_rate: u32,
+}
+
+/// Frequency of the crystal oscillator, in Hz.
+const CRYSTAL_FREQUENCY: u32 = 32768;
+/// Divisors of the crystal frequency selected by values 0x40-0x47.
+const CRYSTAL_DIVIDERS: [u32; 8] = [3, 12, 48, 192, 1, 16, 256, 4096];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClockSource {
+    Off,
+    /// Crystal frequency divided by the given value.
+    Crystal(u32),
+    /// CPU clock divided by the given value.
+    Cpu(u32),
+}
+
+impl ClockSource {
+    fn from_port(value: u8) -> Self {
+        match value & 0xC0 {
+            0x40 => ClockSource::Crystal(CRYSTAL_DIVIDERS[value as usize & 7]),
+            0x80 => ClockSource::Cpu(1 << (value & 7)),
+            _ => ClockSource::Off,
+        }
+    }
+
+    fn to_port(self) -> u8 {
+        match self {
+            ClockSource::Off => 0,
+            ClockSource::Crystal(div) => {
+                0x40 | CRYSTAL_DIVIDERS.iter().position(|&d| d == div).unwrap() as u8
+            }
+            ClockSource::Cpu(div) => 0x80 | div.trailing_zeros() as u8,
+        }
+    }
+
+    /// Get the length of a tick in CPU cycles, as a numerator and denominator.
+    fn tick_cycles(self, cpu_clock_rate: u32) -> Option<(u64, u64)> {
+        match self {
+            ClockSource::Off => None,
+            ClockSource::Crystal(div) => {
+                Some((cpu_clock_rate as u64 * div as u64, CRYSTAL_FREQUENCY as u64))
+            }
+            ClockSource::Cpu(div) => Some((div as u64, 1)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CrystalTimer {
+    source: ClockSource,
+    /// Restart from `reload` on expiry.
+    loop_mode: bool,
+    interrupt_enabled: bool,
+    /// Value last written to the counter, reloaded in loop mode.
+    reload: u8,
+    /// Count at cycle `start`, counting down to 0 from there if running.
+    count: u8,
+    start: u64,
+    running: bool,
+    expired: bool,
+    /// Expired more than once without being acknowledged.
+    overflowed: bool,
+}
+
+impl CrystalTimer {
+    fn new() -> Self {
+        CrystalTimer {
+            source: ClockSource::Off,
+            loop_mode: false,
+            interrupt_enabled: false,
+            reload: 0,
+            count: 0,
+            start: 0,
+            running: false,
+            expired: false,
+            overflowed: false,
+        }
+    }
+
+    /// Get the current count.
+    fn counter(&self, now: u64, cpu_clock_rate: u32) -> u8 {
+        match self.source.tick_cycles(cpu_clock_rate) {
+            Some((num, den)) if self.running => {
+                let ticks = (now - self.start) * den / num;
+                (self.count as u64).saturating_sub(ticks) as u8
+            }
+            _ => self.count,
+        }
+    }
+
+    /// Get the cycle at which this timer next expires, if it is running.
+    fn expiry(&self, cpu_clock_rate: u32) -> Option<u64> {
+        match self.source.tick_cycles(cpu_clock_rate) {
+            Some((num, den)) if self.running => {
+                // Round up, so the timer has expired by the returned cycle
+                let ticks = self.count as u64 * num;
+                Some(self.start + ticks / den + (ticks % den).min(1))
+            }
+            _ => None,
+        }
+    }
+
+    /// Record the current count so timing can change from `now`.
+    fn freeze(&mut self, now: u64, cpu_clock_rate: u32) {
+        self.count = self.counter(now, cpu_clock_rate);
+        self.start = now;
+    }
+
+    fn expire(&mut self, at: u64) {
+        self.overflowed |= self.expired;
+        self.expired = true;
+        trace!("Crystal timer expired: {:?}", self);
+
+        if self.loop_mode {
+            self.count = self.reload;
+            self.start = at;
+        } else {
+            self.running = false;
+            self.count = 0;
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        self.expired && self.interrupt_enabled
+    }
+
+    fn write_source(&mut self, value: u8, now: u64, cpu_clock_rate: u32) {
+        self.freeze(now, cpu_clock_rate);
+        self.source = ClockSource::from_port(value);
+        if self.source == ClockSource::Off {
+            self.running = false;
+        }
+    }
+
+    fn write_control(&mut self, value: u8) {
+        self.loop_mode = value & 1 != 0;
+        self.interrupt_enabled = value & 2 != 0;
+        self.expired = false;
+        self.overflowed = false;
+    }
+
+    fn read_control(&self) -> u8 {
+        (self.expired as u8) | ((self.overflowed as u8) << 1)
+    }
+
+    fn write_counter(&mut self, value: u8, now: u64) {
+        self.count = value;
+        self.reload = value;
+        self.start = now;
+        self.running = value != 0 && self.source != ClockSource::Off;
+    }
+}
+
+impl CrystalTimers {
+    pub fn new() -> Self {
+        CrystalTimers {
+            timers: [
+                CrystalTimer::new(),
+                CrystalTimer::new(),
+                CrystalTimer::new(),
+            ],
+            cpu_clock_rate: 6_000_000,
+        }
+    }
+
+    /// Update the scheduled expiry of a timer.
+    fn reschedule(&self, index: usize, scheduler: &mut Scheduler) {
+        let event = Event::CrystalTimer(index as u8);
+        match self.timers[index].expiry(self.cpu_clock_rate) {
+            Some(at) => scheduler.schedule(event, at),
+            None => scheduler.cancel(event),
+        }
+    }
+
+    /// Set the CPU clock frequency.
+    ///
+    /// Timers clocked from the crystal continue to run at the same rate, but it
+    /// corresponds to a different number of CPU cycles.
+    pub fn set_cpu_clock_rate(&mut self, hz: u32, now: u64, scheduler: &mut Scheduler) {
+        for timer in self.timers.iter_mut() {
+            timer.freeze(now, self.cpu_clock_rate);
+        }
+        self.cpu_clock_rate = hz;
+        for i in 0..self.timers.len() {
+            self.reschedule(i, scheduler);
+        }
+    }
+
+    /// Handle expiry of a timer, at the cycle it was scheduled to expire.
+    pub fn expire(&mut self, index: u8, at: u64, scheduler: &mut Scheduler) {
+        self.timers[index as usize].expire(at);
+        self.reschedule(index as usize, scheduler);
+    }
+
+    /// Get a bitmask of timers with pending interrupts, where bit 0 is the
+    /// first timer.
+    pub fn pending(&self) -> u8 {
+        self.timers
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.is_pending())
+            .fold(0, |acc, (i, _)| acc | (1 << i))
+    }
+
+    /// Read one of the ports 0x30-0x38.
+    pub fn read_port(&self, port: u8, now: u64) -> u8 {
+        let timer = &self.timers[(port - 0x30) as usize / 3];
+        match (port - 0x30) % 3 {
+            0 => timer.source.to_port(),
+            1 => timer.read_control(),
+            _ => timer.counter(now, self.cpu_clock_rate),
+        }
+    }
+
+    /// Write one of the ports 0x30-0x38.
+    pub fn write_port(&mut self, port: u8, value: u8, now: u64, scheduler: &mut Scheduler) {
+        let index = (port - 0x30) as usize / 3;
+        let timer = &mut self.timers[index];
+        match (port - 0x30) % 3 {
+            0 => timer.write_source(value, now, self.cpu_clock_rate),
+            1 => timer.write_control(value),
+            _ => timer.write_counter(value, now),
+        }
+        trace!(
+            "Crystal timer port {:02X} write {:02X}: {:?}",
+            port,
+            value,
+            timer
+        );
+        self.reschedule(index, scheduler);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CrystalTimers;
+    use crate::scheduler::{Event, Scheduler};
+
+    /// Handle timer expiry up to the given cycle, like the emulator does.
+    fn run_until(timers: &mut CrystalTimers, scheduler: &mut Scheduler, now: u64) {
+        while let Some((event, at)) = scheduler.pop_due(now) {
+            match event {
+                Event::CrystalTimer(n) => timers.expire(n, at, scheduler),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn one_shot_expiry() {
+        let mut timers = CrystalTimers::new();
+        let mut scheduler = Scheduler::new();
+        // 32768 Hz, interrupt without looping, count 32 ticks (~977us)
+        timers.write_port(0x30, 0x44, 0, &mut scheduler);
+        timers.write_port(0x31, 0x02, 0, &mut scheduler);
+        timers.write_port(0x32, 32, 0, &mut scheduler);
+        assert_eq!(scheduler.next(), Some(5860));
+
+        run_until(&mut timers, &mut scheduler, 2930);
+        assert_eq!(timers.pending(), 0);
+        assert_eq!(timers.read_port(0x32, 2930), 16);
+
+        run_until(&mut timers, &mut scheduler, 6000);
+        assert_eq!(timers.pending(), 1);
+        assert_eq!(timers.read_port(0x31, 6000), 1);
+        assert_eq!(scheduler.next(), None);
+
+        // Acknowledge
+        timers.write_port(0x31, 0x02, 6000, &mut scheduler);
+        assert_eq!(timers.pending(), 0);
+    }
+
+    #[test]
+    fn looping_overflow() {
+        let mut timers = CrystalTimers::new();
+        let mut scheduler = Scheduler::new();
+        // 8 Hz, looping with no interrupt
+        timers.write_port(0x36, 0x47, 0, &mut scheduler);
+        timers.write_port(0x37, 0x01, 0, &mut scheduler);
+        timers.write_port(0x38, 4, 0, &mut scheduler);
+
+        run_until(&mut timers, &mut scheduler, 3_600_000);
+        assert_eq!(timers.read_port(0x37, 3_600_000), 1);
+        assert_eq!(timers.read_port(0x38, 3_600_000), 4);
+        run_until(&mut timers, &mut scheduler, 9_600_000);
+        assert_eq!(timers.read_port(0x37, 9_600_000), 3);
+        assert_eq!(timers.pending(), 0);
+        assert_eq!(scheduler.next(), Some(12_000_000));
+    }
+
+    #[test]
+    fn crystal_rate_is_independent_of_cpu() {
+        let mut timers = CrystalTimers::new();
+        let mut scheduler = Scheduler::new();
+        // 8 Hz, 4 ticks is half a second
+        timers.write_port(0x30, 0x47, 0, &mut scheduler);
+        timers.write_port(0x32, 4, 0, &mut scheduler);
+        assert_eq!(scheduler.next(), Some(3_000_000));
+
+        // Switch to 15 MHz after a quarter second
+        timers.set_cpu_clock_rate(15_000_000, 1_500_000, &mut scheduler);
+        assert_eq!(scheduler.next(), Some(1_500_000 + 3_750_000));
+    }
+}
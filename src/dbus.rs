@@ -0,0 +1,624 @@
+//! The TI link protocol (DBUS), as spoken by a computer connected to a calculator.
+//!
+//! Bytes are sent one bit at a time, least significant bit first, using both
+//! lines of the link port. To send a 0 the sender pulls the tip line low and to
+//! send a 1 it pulls the ring line low; the receiver acknowledges by pulling the
+//! other line low, then the sender releases its line and finally the receiver
+//! releases its line. Both lines are high between bits.
+//!
+//! Bytes are grouped into packets consisting of a machine ID, command ID and
+//! 16-bit length, followed by that many data bytes and a 16-bit checksum of the
+//! data for commands that carry data. Variables are transferred as so:
+//!
+//! | Sender | Receiver | Meaning
+//! |--------|----------|--------
+//! | RTS    |          | Request to send, with variable header
+//! |        | ACK      |
+//! |        | CTS      | Clear to send
+//! | ACK    |          |
+//! | DATA   |          | Variable data
+//! |        | ACK      |
+//! | EOT    |          | End of transmission
+//! |        | ACK      |
+//!
+//! A calculator sending a variable (with the Send( command, for instance) may
+//! begin with VAR rather than RTS, but the rest of the transfer is the same. A
+//! receiver that doesn't want the variable replies to the request with SKIP
+//! instead of CTS.
+//!
+//! See the [link protocol guide](http://merthsoft.com/linkguide/ti83+/) for more
+//! details.
+
+use crate::link::{CableEnd, RING, TIP};
+use crate::tifiles::{Variable, VariableType};
+use num_traits::FromPrimitive;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Machine ID sent by a computer talking to an 83+ or 84+.
+pub const HOST_MACHINE_ID: u8 = 0x23;
+
+/// How long a transfer may go without the other end responding before it is
+/// abandoned, by default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The other end stopped responding partway through a transfer.
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+enum Command {
+    /// Variable header, sent by a calculator in place of RTS.
+    Var = 0x06,
+    /// Clear to send.
+    Cts = 0x09,
+    Data = 0x15,
+    /// Variable refused, with a reason code.
+    Skip = 0x36,
+    Ack = 0x56,
+    /// Checksum error; resend the last packet.
+    Err = 0x5A,
+    /// Check whether ready.
+    Rdy = 0x68,
+    /// End of transmission.
+    Eot = 0x92,
+    /// Request for a variable.
+    Req = 0xA2,
+    /// Request to send.
+    Rts = 0xC9,
+}
+
+impl Command {
+    /// Return true if packets with this command have data and a checksum.
+    fn has_data(self) -> bool {
+        matches!(
+            self,
+            Command::Var | Command::Data | Command::Skip | Command::Req | Command::Rts
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Packet {
+    machine: u8,
+    command: Command,
+    data: Vec<u8>,
+}
+
+impl Packet {
+    fn checksum(data: &[u8]) -> u16 {
+        data.iter().fold(0u16, |a, &x| a.wrapping_add(x as u16))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.machine, self.command as u8];
+        out.extend_from_slice(&(self.data.len() as u16).to_le_bytes());
+        if self.command.has_data() {
+            out.extend_from_slice(&self.data);
+            out.extend_from_slice(&Self::checksum(&self.data).to_le_bytes());
+        }
+        out
+    }
+
+    /// Decode a packet from the beginning of `buf`.
+    ///
+    /// Returns `Ok(None)` if more bytes are needed to decode a packet, or the
+    /// packet and the number of bytes it occupied.
+    fn decode(buf: &[u8]) -> Result<Option<(Packet, usize)>, &'static str> {
+        if buf.len() < 4 {
+            return Ok(None);
+        }
+        let command = Command::from_u8(buf[1]).ok_or("Unrecognized command")?;
+        if !command.has_data() {
+            // The length of packets without data is meaningless; the calculator
+            // sometimes sends nonzero values.
+            let packet = Packet {
+                machine: buf[0],
+                command,
+                data: vec![],
+            };
+            return Ok(Some((packet, 4)));
+        }
+
+        let len = u16::from_le_bytes([buf[2], buf[3]]) as usize;
+        if buf.len() < 4 + len + 2 {
+            return Ok(None);
+        }
+        let data = &buf[4..4 + len];
+        let sum = u16::from_le_bytes([buf[4 + len], buf[5 + len]]);
+        if sum != Self::checksum(data) {
+            return Err("Incorrect checksum");
+        }
+
+        let packet = Packet {
+            machine: buf[0],
+            command,
+            data: data.to_vec(),
+        };
+        Ok(Some((packet, 4 + len + 2)))
+    }
+}
+
+/// Encode the header sent in RTS and VAR packets for a variable.
+fn encode_header(var: &Variable) -> Vec<u8> {
+    let mut out = Vec::with_capacity(13);
+    out.extend_from_slice(&(var.data.len() as u16).to_le_bytes());
+    out.push(var.ty as u8);
+    let mut name = [0u8; 8];
+    name[..var.name.len()].copy_from_slice(&var.name);
+    out.extend_from_slice(&name);
+    if let Some(version) = var.version {
+        out.push(version);
+        out.push(var.flags.unwrap_or(0));
+    }
+    out
+}
+
+/// Decode a variable header, returning the variable with empty data and the data size.
+fn decode_header(header: &[u8]) -> Result<(Variable, usize), &'static str> {
+    if header.len() < 11 {
+        return Err("Variable header too short");
+    }
+    let size = u16::from_le_bytes([header[0], header[1]]) as usize;
+    let ty = VariableType::from_u8(header[2]).ok_or("Unrecognized variable type")?;
+    let raw_name = &header[3..11];
+    let name_len = raw_name
+        .iter()
+        .position(|&x| x == 0)
+        .unwrap_or(raw_name.len());
+    let (version, flags) = match header.len() {
+        11 => (None, None),
+        _ => (header.get(11).copied(), header.get(12).copied()),
+    };
+
+    let var = Variable {
+        name: raw_name[..name_len].into(),
+        ty,
+        version,
+        flags,
+        data: Vec::with_capacity(size),
+    };
+    Ok((var, size))
+}
+
+/// State of the bit-level transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BitState {
+    /// Both lines released.
+    Idle,
+    /// Sending a bit, waiting for the receiver to acknowledge it.
+    SendWaitAck,
+    /// Sent a bit, waiting for the receiver to release its line.
+    SendWaitRelease,
+    /// Acknowledged a received bit, waiting for the sender to release its line.
+    ReceiveWaitRelease(u8),
+}
+
+/// State of a variable transfer.
+#[derive(Debug)]
+enum Transfer {
+    Idle,
+    /// Sent RTS, waiting for ACK.
+    SendWaitRtsAck(Variable),
+    /// Waiting for CTS.
+    SendWaitCts(Variable),
+    /// Sent DATA, waiting for ACK.
+    SendWaitDataAck,
+    /// Sent EOT, waiting for ACK.
+    SendWaitEotAck,
+    /// Sent CTS, waiting for ACK.
+    ReceiveWaitCtsAck(Variable, usize),
+    ReceiveWaitData(Variable, usize),
+    ReceiveWaitEot(Variable),
+}
+
+/// A computer connected to a calculator with a link cable.
+///
+/// The endpoint sends variables queued with [Endpoint::send] and accepts any
+/// variables sent to it, which can be collected with [Endpoint::take_received].
+/// Transfers only progress when [Endpoint::step] is called, which should be done
+/// frequently while the calculator is running (after every short call to
+/// [Emulator::run](crate::Emulator::run), for instance).
+///
+/// Failed transfers are logged and abandoned, as are transfers where the other end
+/// doesn't respond for longer than the timeout (see [Endpoint::set_timeout]).
+#[derive(Debug)]
+pub struct Endpoint {
+    cable: CableEnd,
+    machine_id: u8,
+    bits: BitState,
+
+    /// Byte currently being sent, and the number of bits of it already sent.
+    tx_byte: Option<(u8, u8)>,
+    tx: VecDeque<u8>,
+    /// Byte being received, and the number of bits of it received.
+    rx_byte: (u8, u8),
+    rx: Vec<u8>,
+
+    transfer: Transfer,
+    outgoing: VecDeque<Variable>,
+    received: Vec<Variable>,
+
+    timeout: Duration,
+    /// Time for which a transfer has been in progress without the lines changing.
+    stalled: Duration,
+    /// Lines driven by each end at the end of the last step.
+    last_driven: (u8, u8),
+}
+
+impl Endpoint {
+    /// Create an endpoint using the given end of a link cable.
+    pub fn new(cable: CableEnd) -> Self {
+        Endpoint {
+            cable,
+            machine_id: HOST_MACHINE_ID,
+            bits: BitState::Idle,
+            tx_byte: None,
+            tx: VecDeque::new(),
+            rx_byte: (0, 0),
+            rx: Vec::new(),
+            transfer: Transfer::Idle,
+            outgoing: VecDeque::new(),
+            received: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            stalled: Duration::from_secs(0),
+            last_driven: (0, 0),
+        }
+    }
+
+    /// Set how long the other end may go without responding before a transfer is
+    /// abandoned.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Create an endpoint connected to the link port of an emulator.
+    pub fn connect(emu: &mut crate::Emulator) -> Self {
+        let (calc, host) = crate::link::cable();
+        emu.link.connect(calc);
+        Self::new(host)
+    }
+
+    /// Queue a variable to be sent.
+    pub fn send(&mut self, var: Variable) {
+        self.outgoing.push_back(var);
+    }
+
+    /// Get variables that have been received since the last call.
+    pub fn take_received(&mut self) -> Vec<Variable> {
+        std::mem::take(&mut self.received)
+    }
+
+    /// Return true if no transfer is in progress and nothing is waiting to be sent.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.transfer, Transfer::Idle)
+            && self.outgoing.is_empty()
+            && self.tx.is_empty()
+            && self.tx_byte.is_none()
+            && self.bits == BitState::Idle
+    }
+
+    /// Respond to the current state of the link lines, sending or receiving as
+    /// much as possible without waiting for the other end.
+    ///
+    /// `elapsed` is the time since the last step, used to detect when the other
+    /// end stops responding. In that case the current transfer is abandoned and
+    /// [Error::Timeout] is returned.
+    pub fn step(&mut self, elapsed: Duration) -> Result<(), Error> {
+        loop {
+            let receiving = !self.rx.is_empty() || self.rx_byte.1 != 0;
+            if matches!(self.transfer, Transfer::Idle) && self.tx.is_empty() && !receiving {
+                self.start_send();
+            }
+            if !self.step_bits() {
+                break;
+            }
+        }
+
+        let driven = (self.cable.driven(), self.cable.remote_driven());
+        if self.is_idle() || driven != self.last_driven {
+            self.stalled = Duration::from_secs(0);
+        } else {
+            self.stalled += elapsed;
+        }
+        self.last_driven = driven;
+
+        if self.stalled > self.timeout {
+            warn!("DBUS transfer timed out in state {:?}", self.transfer);
+            self.abandon();
+            return Err(Error::Timeout);
+        }
+        Ok(())
+    }
+
+    /// Stop the current transfer and release the lines.
+    fn abandon(&mut self) {
+        self.cable.set_driven(0);
+        self.bits = BitState::Idle;
+        self.tx_byte = None;
+        self.tx.clear();
+        self.rx_byte = (0, 0);
+        self.rx.clear();
+        self.transfer = Transfer::Idle;
+        self.stalled = Duration::from_secs(0);
+    }
+
+    /// Make one transition of the bit-level state machine, returning false if
+    /// nothing can be done until the other end does something.
+    fn step_bits(&mut self) -> bool {
+        let remote = self.cable.remote_driven();
+        match self.bits {
+            BitState::Idle if remote == TIP || remote == RING => {
+                // Acknowledge the bit by pulling the other line
+                self.cable.set_driven(remote ^ (TIP | RING));
+                self.bits = BitState::ReceiveWaitRelease((remote == RING) as u8);
+            }
+            BitState::Idle if remote == 0 => {
+                let (byte, bit) = match self.tx_byte.or_else(|| self.tx.pop_front().map(|b| (b, 0)))
+                {
+                    None => return false,
+                    Some(x) => x,
+                };
+                self.tx_byte = Some((byte, bit));
+                self.cable
+                    .set_driven(if byte & (1 << bit) != 0 { RING } else { TIP });
+                self.bits = BitState::SendWaitAck;
+            }
+            BitState::SendWaitAck if remote != 0 && remote != self.cable.driven() => {
+                self.cable.set_driven(0);
+                self.bits = BitState::SendWaitRelease;
+            }
+            BitState::SendWaitRelease if remote == 0 => {
+                let (byte, bit) = self.tx_byte.unwrap();
+                self.tx_byte = if bit == 7 {
+                    None
+                } else {
+                    Some((byte, bit + 1))
+                };
+                self.bits = BitState::Idle;
+            }
+            BitState::ReceiveWaitRelease(value) if remote == 0 => {
+                // The sender must see our line released before anything else is
+                // sent, so stop here until the next step.
+                self.cable.set_driven(0);
+                self.bits = BitState::Idle;
+
+                let (byte, count) = self.rx_byte;
+                let byte = byte | (value << count);
+                if count == 7 {
+                    self.rx_byte = (0, 0);
+                    self.rx.push(byte);
+                    self.receive_packets();
+                } else {
+                    self.rx_byte = (byte, count + 1);
+                }
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn send_packet(&mut self, command: Command, data: Vec<u8>) {
+        let packet = Packet {
+            machine: self.machine_id,
+            command,
+            data,
+        };
+        trace!("DBUS send {:?}", packet);
+        self.tx.extend(packet.encode());
+    }
+
+    /// Begin sending the next outgoing variable, if any.
+    fn start_send(&mut self) {
+        if let Some(var) = self.outgoing.pop_front() {
+            debug!(
+                "DBUS begin sending {:?} variable {:?}",
+                var.ty,
+                String::from_utf8_lossy(&var.name)
+            );
+            self.send_packet(Command::Rts, encode_header(&var));
+            self.transfer = Transfer::SendWaitRtsAck(var);
+        }
+    }
+
+    fn receive_packets(&mut self) {
+        loop {
+            match Packet::decode(&self.rx) {
+                Ok(None) => return,
+                Ok(Some((packet, len))) => {
+                    self.rx.drain(..len);
+                    trace!("DBUS received {:?}", packet);
+                    if let Err(e) = self.handle_packet(packet) {
+                        warn!("DBUS transfer failed: {}", e);
+                        self.transfer = Transfer::Idle;
+                    }
+                }
+                Err(e) => {
+                    warn!("DBUS received invalid packet: {}", e);
+                    self.rx.clear();
+                    self.send_packet(Command::Err, vec![]);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: Packet) -> Result<(), &'static str> {
+        let transfer = std::mem::replace(&mut self.transfer, Transfer::Idle);
+        self.transfer = match (transfer, packet.command) {
+            (Transfer::Idle, Command::Rts) | (Transfer::Idle, Command::Var) => {
+                let (var, size) = decode_header(&packet.data)?;
+                self.send_packet(Command::Ack, vec![]);
+                self.send_packet(Command::Cts, vec![]);
+                Transfer::ReceiveWaitCtsAck(var, size)
+            }
+            (Transfer::Idle, Command::Rdy) => {
+                self.send_packet(Command::Ack, vec![]);
+                Transfer::Idle
+            }
+            (Transfer::SendWaitRtsAck(var), Command::Ack) => Transfer::SendWaitCts(var),
+            (Transfer::SendWaitCts(var), Command::Cts) => {
+                self.send_packet(Command::Ack, vec![]);
+                self.send_packet(Command::Data, var.data);
+                Transfer::SendWaitDataAck
+            }
+            (Transfer::SendWaitCts(_), Command::Skip) => {
+                self.send_packet(Command::Ack, vec![]);
+                return Err("Variable refused by receiver");
+            }
+            (Transfer::SendWaitDataAck, Command::Ack) => {
+                self.send_packet(Command::Eot, vec![]);
+                Transfer::SendWaitEotAck
+            }
+            (Transfer::SendWaitEotAck, Command::Ack) => {
+                debug!("DBUS send complete");
+                Transfer::Idle
+            }
+            (Transfer::ReceiveWaitCtsAck(var, size), Command::Ack) => {
+                Transfer::ReceiveWaitData(var, size)
+            }
+            (Transfer::ReceiveWaitData(mut var, size), Command::Data) => {
+                if packet.data.len() != size {
+                    return Err("Variable data size does not match header");
+                }
+                self.send_packet(Command::Ack, vec![]);
+                var.data = packet.data;
+                Transfer::ReceiveWaitEot(var)
+            }
+            (Transfer::ReceiveWaitEot(var), Command::Eot) => {
+                self.send_packet(Command::Ack, vec![]);
+                debug!(
+                    "DBUS received {:?} variable {:?}",
+                    var.ty,
+                    String::from_utf8_lossy(&var.name)
+                );
+                self.received.push(var);
+                Transfer::Idle
+            }
+            (_, Command::Err) => return Err("Receiver reported a checksum error"),
+            (_, Command::Eot) => {
+                self.send_packet(Command::Ack, vec![]);
+                return Err("Transfer ended early");
+            }
+            (_, _) => return Err("Unexpected packet"),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, Endpoint, Error, Packet};
+    use crate::link::cable;
+    use crate::tifiles::{Variable, VariableType};
+    use crate::{Emulator, Model, Z80};
+    use std::time::Duration;
+
+    fn program() -> Variable {
+        Variable {
+            name: b"A"[..].into(),
+            ty: VariableType::Program,
+            version: Some(0),
+            flags: Some(0),
+            data: vec![3, 0, 0xBB, 0x6D, 0xC9],
+        }
+    }
+
+    #[test]
+    fn packet_roundtrip() {
+        let packet = Packet {
+            machine: 0x73,
+            command: Command::Data,
+            data: vec![0x01, 0xFF, 0x10],
+        };
+        let encoded = packet.encode();
+        assert_eq!(
+            encoded,
+            [0x73, 0x15, 0x03, 0x00, 0x01, 0xFF, 0x10, 0x10, 0x01]
+        );
+        assert_eq!(
+            Packet::decode(&encoded[..5]).expect("Partial packet should not fail"),
+            None
+        );
+        assert_eq!(
+            Packet::decode(&encoded).unwrap(),
+            Some((packet, encoded.len()))
+        );
+
+        let mut corrupt = encoded;
+        corrupt[5] = 0;
+        assert!(Packet::decode(&corrupt).is_err());
+    }
+
+    #[test]
+    fn transfer_variable() {
+        let (a, b) = cable();
+        let mut sender = Endpoint::new(a);
+        let mut receiver = Endpoint::new(b);
+        let var = program();
+
+        sender.send(var.clone());
+        for _ in 0..10000 {
+            sender.step(Duration::from_millis(1)).unwrap();
+            receiver.step(Duration::from_millis(1)).unwrap();
+            if sender.is_idle() && receiver.is_idle() {
+                break;
+            }
+        }
+        assert!(sender.is_idle());
+        assert_eq!(receiver.take_received(), vec![var]);
+    }
+
+    #[test]
+    fn calculator_stops_responding() {
+        // Receive 4 bytes into RAM, then stop listening
+        #[rustfmt::skip]
+        let code: &[u8] = &[
+            0x21, 0x00, 0xC0, // ld hl, $C000
+            0x06, 0x04,       // ld b, 4
+            0x0E, 0x08,       // byte: ld c, 8
+            0xDB, 0x00,       // bit: in a, (0)
+            0xE6, 0x03,       // and 3
+            0xFE, 0x03,       // cp 3
+            0x28, 0xF8,       // jr z, bit
+            0xD3, 0x00,       // out (0), a ; acknowledge with the other line
+            0x1F,             // rra
+            0xCB, 0x1A,       // rr d
+            0xDB, 0x00,       // release: in a, (0)
+            0xE6, 0x03,       // and 3
+            0x28, 0xFA,       // jr z, release
+            0xAF,             // xor a
+            0xD3, 0x00,       // out (0), a
+            0x0D,             // dec c
+            0x20, 0xE7,       // jr nz, bit
+            0x72,             // ld (hl), d
+            0x23,             // inc hl
+            0x10, 0xE1,       // djnz byte
+            0x18, 0xFE,       // jr $
+        ];
+        let mut emu = Emulator::with_flash_pages(Model::TI83Plus, &[(0, code)]).unwrap();
+        let mut cpu = Z80::new();
+        let mut host = Endpoint::connect(&mut emu);
+        host.set_timeout(Duration::from_millis(100));
+        host.send(program());
+
+        let mut result = Ok(());
+        for _ in 0..1000 {
+            let elapsed = emu.run(&mut cpu, Duration::from_millis(1));
+            result = host.step(elapsed);
+            if result.is_err() {
+                break;
+            }
+        }
+        assert_eq!(result, Err(Error::Timeout));
+        assert!(host.is_idle());
+        assert_eq!(emu.link.read_port() & 3, 3);
+
+        // The RTS packet's header reached the calculator
+        let received: Vec<u8> = (0xC000..0xC004).map(|a| emu.mem[a]).collect();
+        assert_eq!(received, [0x23, 0xC9, 13, 0]);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::scheduler::{Event, Scheduler};
 use arr_macro::arr;
 
 pub struct Display {
@@ -21,8 +22,143 @@ pub struct Display {
     addr_x: u8,
     /// Y address; active word in the current row.
     addr_y: u8,
+    /// Z address; the row of display RAM shown at the top of the screen.
+    addr_z: u8,
+    /// Contrast level, 0-63.
+    contrast: u8,
+    /// Whether the display is powered on.
+    powered_on: bool,
+    /// Set when the driver is in test mode, which prevents normal display.
+    test_mode: bool,
+
+    /// Length of an LCD refresh period in CPU cycles.
+    refresh_cycles: u64,
+
+    /// Set after each access until the [Event::LcdReady] event.
+    busy: bool,
+    /// CPU cycle count at which the driver will stop being busy.
+    busy_until: u64,
+    /// Number of CPU cycles the driver is busy for after each access.
+    busy_cycles: u64,
+    /// If set, accesses while busy are ignored like on hardware rather than
+    /// being allowed to succeed.
+    strict_timing: bool,
+    /// Number of accesses made while the driver was busy.
+    busy_violations: u32,
+
+    /// The most recent CPU cycle count the display has been told about.
+    now: u64,
+    /// Pixel on-time tracking for grayscale rendering, if enabled.
+    persistence: Option<Box<Persistence>>,
 }
 
+/// Tracks how long each pixel has been lit over each LCD refresh period, so pixels
+/// that flicker can be rendered in shades of gray like they appear on a real LCD.
+///
+/// Time is measured in CPU cycles. Each pixel's intensity is the fraction of the
+/// most recently completed refresh period for which it was lit, so the result
+/// doesn't depend on how often it is read.
+struct Persistence {
+    /// Length of a refresh period in CPU cycles.
+    period: u64,
+    /// Cycle at which the current refresh period began.
+    window_start: u64,
+    /// Cycle from which each lit pixel's on-time has not yet been accumulated.
+    ///
+    /// Values for unlit pixels are meaningless.
+    lit_since: Box<[u64]>,
+    /// Cycles for which each pixel has been lit during the current period, not
+    /// including time since `lit_since`.
+    lit_cycles: Box<[u64]>,
+    /// Intensity of each pixel over the last completed period.
+    frame: [u8; Display::ROWS * Display::COLS],
+}
+
+impl Persistence {
+    fn new(buf: &[u8; Display::ROWS * Display::COLS], now: u64, period: u64) -> Self {
+        let mut frame = *buf;
+        for px in frame.iter_mut() {
+            *px *= 0xFF;
+        }
+        Persistence {
+            period,
+            window_start: now,
+            lit_since: vec![now; Display::ROWS * Display::COLS].into_boxed_slice(),
+            lit_cycles: vec![0; Display::ROWS * Display::COLS].into_boxed_slice(),
+            frame,
+        }
+    }
+
+    /// Record that the pixel at `idx` changed state at `now`.
+    fn pixel_changed(&mut self, idx: usize, lit: bool, now: u64) {
+        if lit {
+            self.lit_since[idx] = now;
+        } else {
+            self.lit_cycles[idx] += now - self.lit_since[idx];
+        }
+    }
+
+    /// Accumulate on-time for every pixel up to `now`.
+    ///
+    /// This must be done before any change that doesn't go through [pixel_changed].
+    fn flush(&mut self, buf: &[u8], now: u64) {
+        for ((&px, since), cycles) in buf
+            .iter()
+            .zip(self.lit_since.iter_mut())
+            .zip(self.lit_cycles.iter_mut())
+        {
+            if px != 0 {
+                *cycles += now - *since;
+            }
+            *since = now;
+        }
+    }
+
+    /// Complete any refresh periods that have ended by `now`.
+    ///
+    /// This must be done before recording any change at `now`.
+    fn advance(&mut self, buf: &[u8], now: u64) {
+        let end = self.window_start + self.period;
+        if now < end {
+            return;
+        }
+        self.flush(buf, end);
+        for (dst, cycles) in self.frame.iter_mut().zip(self.lit_cycles.iter_mut()) {
+            *dst = (*cycles * 0xFF / self.period) as u8;
+            *cycles = 0;
+        }
+        self.window_start = end;
+
+        if now >= end + self.period {
+            // Nothing changed for at least one whole period
+            for (dst, &px) in self.frame.iter_mut().zip(buf) {
+                *dst = px * 0xFF;
+            }
+            self.window_start = now - (now - end) % self.period;
+            self.flush(buf, self.window_start);
+            for cycles in self.lit_cycles.iter_mut() {
+                *cycles = 0;
+            }
+        }
+    }
+
+    /// Change the length of refresh periods, beginning with the current one.
+    fn set_period(&mut self, period: u64) {
+        self.period = period;
+    }
+}
+
+/// Rate at which the LCD refreshes its pixels, in Hz.
+///
+/// Real panels vary somewhat, but refresh at roughly this rate.
+const REFRESH_HZ: u64 = 60;
+
+/// Time the LCD driver is busy for following each access, in microseconds.
+///
+/// Real drivers vary somewhat, but TI-OS' `LCD_BUSY_QUICK` routine waits for
+/// about this long.
+const BUSY_TIME_US: u64 = 10;
+
 #[derive(Debug)]
 enum AutoAddressMode {
     DecrementX,
@@ -71,11 +207,173 @@ impl Display {
             address_update_pending: true,
             addr_x: 0,
             addr_y: 0,
+            addr_z: 0,
+            // The contrast TI-OS uses by default
+            contrast: 0x30,
+            powered_on: true,
+            test_mode: false,
+            // Until told the clock rate, assume 6 MHz
+            refresh_cycles: 6_000_000 / REFRESH_HZ,
+            busy: false,
+            busy_until: 0,
+            busy_cycles: 0,
+            strict_timing: false,
+            busy_violations: 0,
+            now: 0,
+            persistence: None,
+        }
+    }
+
+    /// Update the display's notion of the current time, in CPU cycles.
+    ///
+    /// This should be called before changes to the display that aren't made
+    /// through the LCD ports, so grayscale tracking can attribute them to the
+    /// right time.
+    pub fn set_time(&mut self, now: u64) {
+        self.now = now;
+        if let Some(ref mut p) = self.persistence {
+            p.advance(&self.buf, now);
+        }
+    }
+
+    /// Enable or disable tracking of pixel on-time for grayscale output.
+    pub fn set_grayscale(&mut self, enabled: bool) {
+        self.persistence = if enabled {
+            Some(Box::new(Persistence::new(
+                &self.buf,
+                self.now,
+                self.refresh_cycles,
+            )))
+        } else {
+            None
+        };
+    }
+
+    pub fn is_grayscale(&self) -> bool {
+        self.persistence.is_some()
+    }
+
+    /// Get the displayed image as intensities, where 0 is unlit and 255 is fully lit.
+    ///
+    /// When grayscale is enabled, each value is the fraction of the last complete LCD
+    /// refresh period for which the pixel was lit, like the LCD's slow response
+    /// averages pixels that flicker. Otherwise each pixel is either fully lit or
+    /// unlit.
+    pub fn grayscale_buffer(&self) -> [u8; Self::ROWS * Self::COLS] {
+        let intensity = match self.persistence {
+            Some(ref p) => p.frame,
+            None => {
+                let mut out = self.buf;
+                for px in out.iter_mut() {
+                    *px *= 0xFF;
+                }
+                out
+            }
+        };
+        self.offset_rows(&intensity)
+    }
+
+    /// Account for on-time of all pixels before a change that may affect many of them.
+    fn flush_persistence(&mut self) {
+        if let Some(ref mut p) = self.persistence {
+            p.flush(&self.buf, self.now);
         }
     }
 
-    pub fn get_buffer(&self) -> &[u8; Self::ROWS * Self::COLS] {
-        &self.buf
+    /// Set the CPU clock rate in Hz, used to compute how long the driver stays busy
+    /// and the length of refresh periods.
+    pub fn set_clock_rate(&mut self, hz: u32) {
+        self.busy_cycles = hz as u64 * BUSY_TIME_US / 1_000_000;
+        self.refresh_cycles = hz as u64 / REFRESH_HZ;
+        if let Some(ref mut p) = self.persistence {
+            p.set_period(self.refresh_cycles);
+        }
+    }
+
+    /// Choose whether accesses made while the driver is busy are ignored.
+    ///
+    /// Hardware ignores such accesses so programs that don't wait long enough
+    /// will display garbage, but by default they are allowed (and logged) for
+    /// compatibility with other emulators.
+    pub fn set_strict_timing(&mut self, strict: bool) {
+        self.strict_timing = strict;
+    }
+
+    /// Get the number of accesses made while the driver was busy.
+    ///
+    /// This is useful for detecting programs that don't wait for the LCD, which
+    /// will fail on real hardware.
+    pub fn busy_violations(&self) -> u32 {
+        self.busy_violations
+    }
+
+    /// Handle the [Event::LcdReady] event, which ends the busy period of an access.
+    pub(crate) fn ready(&mut self) {
+        self.busy = false;
+    }
+
+    /// Begin an access at the given cycle, returning true if it should proceed.
+    ///
+    /// The driver becomes busy for a while after every access, until the
+    /// [Event::LcdReady] event it schedules.
+    fn begin_access(&mut self, now: u64, what: &str, scheduler: &mut Scheduler) -> bool {
+        let proceed = if self.busy {
+            self.busy_violations += 1;
+            if self.strict_timing {
+                warn!(
+                    "Ignored LCD {} while busy for {} more cycle(s)",
+                    what,
+                    self.busy_until - now
+                );
+                false
+            } else {
+                debug!(
+                    "LCD {} while busy for {} more cycle(s)",
+                    what,
+                    self.busy_until - now
+                );
+                true
+            }
+        } else {
+            true
+        };
+
+        if proceed && self.busy_cycles > 0 {
+            self.busy = true;
+            self.busy_until = now + self.busy_cycles;
+            scheduler.schedule(Event::LcdReady, self.busy_until);
+        }
+        proceed
+    }
+
+    /// Get the image currently shown on the display, one byte per pixel.
+    ///
+    /// Rows are offset by the Z address, so this reflects what appears on the
+    /// screen rather than the contents of display RAM. Contrast and power are
+    /// not applied; see [contrast] and [is_on].
+    pub fn get_buffer(&self) -> [u8; Self::ROWS * Self::COLS] {
+        self.offset_rows(&self.buf)
+    }
+
+    /// Rotate rows of a buffer in display RAM order by the Z address, to screen order.
+    fn offset_rows(&self, src: &[u8; Self::ROWS * Self::COLS]) -> [u8; Self::ROWS * Self::COLS] {
+        let split = self.addr_z as usize * Self::COLS;
+        let mut out = [0; Self::ROWS * Self::COLS];
+        out[..src.len() - split].copy_from_slice(&src[split..]);
+        out[src.len() - split..].copy_from_slice(&src[..split]);
+        out
+    }
+
+    /// Get the current contrast level, from 0 (lightest) to 63 (darkest).
+    pub fn contrast(&self) -> u8 {
+        self.contrast
+    }
+
+    /// Return true if the display is on and showing the contents of display RAM.
+    ///
+    /// The display shows nothing if powered off, or if the driver is in test mode.
+    pub fn is_on(&self) -> bool {
+        self.powered_on && !self.test_mode
     }
 
     pub fn get_pixel(&self, x: u8, y: u8) -> u8 {
@@ -108,6 +406,7 @@ impl Display {
     pub const COLS: usize = 96;
 
     pub fn clear(&mut self) {
+        self.flush_persistence();
         for byte in self.buf.iter_mut() {
             *byte = 0;
         }
@@ -121,6 +420,7 @@ impl Display {
             self.clear();
             return;
         }
+        self.flush_persistence();
         let mut rows = self.as_rows();
 
         use ScrollDirection::*;
@@ -157,6 +457,7 @@ impl Display {
     /// `data` must be 768 bytes.
     pub fn blit_fullscreen(&mut self, data: &[u8]) {
         assert_eq!(768, data.len());
+        self.flush_persistence();
         for (block, screen) in data
             .iter()
             .copied()
@@ -176,6 +477,7 @@ impl Display {
         if col > Self::COLS || row > Self::ROWS {
             return;
         }
+        self.flush_persistence();
 
         for (&data_row, screen_row) in data.iter().zip(&mut self.as_rows()[row..]) {
             // Mask to width bits, explode into bytes
@@ -187,6 +489,7 @@ impl Display {
     }
 
     pub fn invert_pixel(&mut self, x: u8, y: u8) {
+        self.flush_persistence();
         *self.get_pixel_mut(x, y) ^= 1;
     }
 
@@ -195,8 +498,13 @@ impl Display {
         self.addr_y = std::cmp::min(self.addr_y, self.word_mode.max_y_addr());
     }
 
-    pub fn write_control(&mut self, command: u8) {
+    /// Write a command to the driver at the given CPU cycle (port 0x10).
+    pub(crate) fn write_control(&mut self, command: u8, now: u64, scheduler: &mut Scheduler) {
         trace!("LCD command write {:02X}", command);
+        self.set_time(now);
+        if !self.begin_access(now, "command write", scheduler) {
+            return;
+        }
         let mut wrote_addr = false;
         match command {
             0x00 => {
@@ -206,6 +514,14 @@ impl Display {
             0x01 => {
                 self.word_mode = WordMode::Bit8;
             }
+            0x02 => {
+                self.powered_on = false;
+                debug!("LCD off");
+            }
+            0x03 => {
+                self.powered_on = true;
+                debug!("LCD on");
+            }
             0x04 => {
                 self.auto_address_mode = AutoAddressMode::DecrementX;
             }
@@ -218,18 +534,38 @@ impl Display {
             0x07 => {
                 self.auto_address_mode = AutoAddressMode::IncrementY;
             }
+            0x08..=0x0B | 0x10..=0x13 => {
+                // Op-amp power supply levels; these affect the image on hardware
+                // but don't have any useful effect to emulate.
+                trace!("Ignored LCD op-amp control {:#04x}", command);
+            }
+            0x18 => {
+                self.test_mode = false;
+            }
+            0x1C..=0x1F => {
+                warn!("LCD entered test mode with command {:#04x}", command);
+                self.test_mode = true;
+            }
             y if y & 0xE0 == 0x20 => {
                 self.addr_y = y & 0x1F;
                 self.clamp_y_addr();
                 wrote_addr = true;
                 debug!("Set LCD Y addr = {}", self.addr_y);
             }
+            z if z & 0xC0 == 0x40 => {
+                self.addr_z = z & 0x3F;
+                debug!("Set LCD Z addr = {}", self.addr_z);
+            }
             x if x & 0xC0 == 0x80 => {
                 // Set x address
                 self.addr_x = x & 0x3F;
                 wrote_addr = true;
                 debug!("Set LCD X addr = {}", self.addr_x);
             }
+            c if c & 0xC0 == 0xC0 => {
+                self.contrast = c & 0x3F;
+                debug!("Set LCD contrast = {}", self.contrast);
+            }
             unimp => {
                 warn!("Unimplemented LCD command {:#04x}", unimp);
             }
@@ -240,13 +576,16 @@ impl Display {
         }
     }
 
+    /// Read the driver status (port 0x10).
+    ///
+    /// Bit 7 is set while the driver is busy.
     pub fn read_status(&self) -> u8 {
-        let busy = 0;
+        let busy = self.busy as u8;
         let word_size = match self.word_mode {
             WordMode::Bit6 => 0,
             WordMode::Bit8 => 1,
         };
-        let display_on = 1;
+        let display_on = self.powered_on as u8;
         let reset = 0;
 
         use AutoAddressMode::*;
@@ -288,11 +627,16 @@ impl Display {
         };
     }
 
-    pub fn write_data(&mut self, data: u8) {
+    /// Write data to the driver at the given CPU cycle (port 0x11).
+    pub(crate) fn write_data(&mut self, data: u8, now: u64, scheduler: &mut Scheduler) {
         debug!(
             "LCD data write {:02X} to ({},{}) {:?} {:?}",
             data, self.addr_y, self.addr_x, self.word_mode, self.auto_address_mode,
         );
+        self.set_time(now);
+        if !self.begin_access(now, "data write", scheduler) {
+            return;
+        }
 
         let word_size = self.word_mode.word_size();
         if self.addr_y as usize * word_size >= Self::COLS {
@@ -306,14 +650,26 @@ impl Display {
         let bytes_write = &bytes[8 - word_size..];
         // Copy bits to buffer
         let buf_start = (self.addr_x as usize * Self::COLS) + (self.addr_y as usize * word_size);
-        for (dst, &src) in self.buf[buf_start..].iter_mut().zip(bytes_write.iter()) {
-            *dst = src;
+        for (idx, &src) in (buf_start..self.buf.len()).zip(bytes_write.iter()) {
+            if self.buf[idx] != src {
+                if let Some(ref mut p) = self.persistence {
+                    p.pixel_changed(idx, src != 0, now);
+                }
+                self.buf[idx] = src;
+            }
         }
 
         self.do_autoaddressing();
     }
 
-    pub fn read_data(&mut self) -> u8 {
+    /// Read data from the driver at the given CPU cycle (port 0x11).
+    ///
+    /// Reads while busy in strict mode return 0 and don't affect the address.
+    pub(crate) fn read_data(&mut self, now: u64, scheduler: &mut Scheduler) -> u8 {
+        self.set_time(now);
+        if !self.begin_access(now, "data read", scheduler) {
+            return 0;
+        }
         let word_size = self.word_mode.word_size();
         let out = if self.addr_y as usize * word_size >= Self::COLS {
             // Y address is outside the screen, just read 0.
@@ -379,6 +735,15 @@ fn pack_byte(x: u64) -> u8 {
 #[cfg(test)]
 mod tests {
     use super::{Display, ScrollDirection};
+    use crate::scheduler::{Event, Scheduler};
+
+    /// Handle LCD events up to the given cycle, like the emulator does.
+    fn run_until(display: &mut Display, scheduler: &mut Scheduler, now: u64) {
+        while let Some((event, _)) = scheduler.pop_due(now) {
+            assert_eq!(event, Event::LcdReady);
+            display.ready();
+        }
+    }
 
     #[quickcheck]
     fn expand_byte_expands(x: u8) {
@@ -396,6 +761,113 @@ mod tests {
         assert_eq!(x, super::pack_byte(super::expand_byte(x)));
     }
 
+    #[test]
+    fn busy_after_access() {
+        let mut display = Display::new();
+        let mut scheduler = Scheduler::new();
+        display.set_clock_rate(6_000_000);
+
+        display.write_control(0x80, 100, &mut scheduler);
+        run_until(&mut display, &mut scheduler, 101);
+        assert_eq!(display.read_status() & 0x80, 0x80);
+        run_until(&mut display, &mut scheduler, 160);
+        assert_eq!(display.read_status() & 0x80, 0);
+
+        // Writes while busy succeed unless strict, but are counted
+        display.write_data(0xFF, 170, &mut scheduler);
+        run_until(&mut display, &mut scheduler, 180);
+        display.write_data(0xFF, 180, &mut scheduler);
+        assert_eq!(display.busy_violations(), 1);
+        assert_eq!(display.get_pixel(7, 1), 1);
+
+        display.set_strict_timing(true);
+        run_until(&mut display, &mut scheduler, 250);
+        display.write_data(0xFF, 250, &mut scheduler);
+        run_until(&mut display, &mut scheduler, 260);
+        display.write_data(0xFF, 260, &mut scheduler);
+        assert_eq!(display.busy_violations(), 2);
+        assert_eq!(display.get_pixel(0, 3), 0);
+    }
+
+    #[test]
+    fn z_address_offsets_rows() {
+        let mut display = Display::new();
+        let mut scheduler = Scheduler::new();
+        display.as_rows()[0][0] = 1;
+        display.as_rows()[5][0] = 1;
+
+        display.write_control(0x45, 0, &mut scheduler);
+        let buf = display.get_buffer();
+        assert_eq!(buf[0], 1);
+        assert_eq!(buf[(Display::ROWS - 5) * Display::COLS], 1);
+        assert_eq!(display.get_pixel(0, 0), 1);
+    }
+
+    #[test]
+    fn contrast_and_power() {
+        let mut display = Display::new();
+        let mut scheduler = Scheduler::new();
+        display.write_control(0xC0 | 20, 0, &mut scheduler);
+        assert_eq!(display.contrast(), 20);
+
+        display.write_control(0x02, 100, &mut scheduler);
+        assert!(!display.is_on());
+        assert_eq!(display.read_status() & 0x20, 0);
+        display.write_control(0x03, 200, &mut scheduler);
+        assert!(display.is_on());
+
+        display.write_control(0x1C, 300, &mut scheduler);
+        assert!(!display.is_on());
+        display.write_control(0x18, 400, &mut scheduler);
+        assert!(display.is_on());
+    }
+
+    #[test]
+    fn grayscale_averages_flicker() {
+        let mut display = Display::new();
+        let mut scheduler = Scheduler::new();
+        // Refresh every 1000 cycles
+        display.set_clock_rate(60_000);
+        display.set_grayscale(true);
+
+        // Pixel 0 on for 3/4 of the time, pixel 1 for half
+        display.write_control(0x80, 0, &mut scheduler);
+        display.write_control(0x20, 0, &mut scheduler);
+        display.write_data(0xC0, 0, &mut scheduler);
+        display.write_control(0x80, 250, &mut scheduler);
+        display.write_data(0x80, 250, &mut scheduler);
+        display.write_control(0x80, 750, &mut scheduler);
+        display.write_data(0x40, 750, &mut scheduler);
+        // Nothing is blended until a refresh period is complete
+        display.set_time(999);
+        assert_eq!(display.grayscale_buffer()[0], 0);
+        display.set_time(1000);
+
+        let buf = display.grayscale_buffer();
+        assert_eq!(buf[0], 191);
+        assert_eq!(buf[1], 127);
+        assert_eq!(buf[2], 0);
+
+        // New period starts fresh; a bulk change is accounted too
+        display.set_time(1500);
+        display.clear();
+        assert_eq!(display.grayscale_buffer(), buf);
+        display.set_time(2000);
+        let buf = display.grayscale_buffer();
+        assert_eq!(buf[0], 0);
+        assert_eq!(buf[1], 127);
+
+        // Periods without changes show the display as-is
+        display.write_control(0x80, 2100, &mut scheduler);
+        display.write_data(0x80, 2100, &mut scheduler);
+        display.set_time(5500);
+        let buf = display.grayscale_buffer();
+        assert_eq!(buf[0], 255);
+        assert_eq!(buf[1], 0);
+        display.set_time(6000);
+        assert_eq!(display.grayscale_buffer()[0], 255);
+    }
+
     fn setup_scrolling() -> Display {
         let mut display = Display::new();
         // Make each row say its original index
@@ -0,0 +1,518 @@
+//! TI's floating point number format.
+//!
+//! Real numbers are 9 bytes: a type byte where bit 7 is the sign and bits 0-4 are
+//! the variable type, an exponent biased by 0x80, and 14 BCD digits of mantissa
+//! with the most significant first. The mantissa is normalized so its first digit
+//! is nonzero, except for zero which has exponent 0x80 and all zero digits. The
+//! value of a number is the mantissa as `d.ddddddddddddd` times ten to the power
+//! of the exponent, which may range from -99 to 99.
+//!
+//! Complex numbers are two reals for the real and imaginary parts, each with the
+//! complex type (0x0C) in its type byte.
+//!
+//! Arithmetic is exact then rounded to 14 digits, which may differ from TI-OS in
+//! the last digit because the OS computes with guard digits that it truncates.
+
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of significant digits in a number.
+const DIGITS: u32 = 14;
+/// Largest exponent that can be represented; smaller exponents underflow to zero.
+const MAX_EXPONENT: i32 = 99;
+
+/// Type byte of real numbers.
+pub const REAL_TYPE: u8 = 0x00;
+/// Type byte of each part of complex numbers.
+pub const COMPLEX_TYPE: u8 = 0x0C;
+
+/// A real number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Real {
+    negative: bool,
+    /// Power of ten of the first mantissa digit.
+    exponent: i32,
+    /// 14 digits, where the first is nonzero unless the number is zero.
+    mantissa: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The result is too large to represent.
+    Overflow,
+    DivideByZero,
+    /// The input is not a valid number.
+    Invalid,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Real {
+    pub const ZERO: Real = Real {
+        negative: false,
+        exponent: 0,
+        mantissa: 0,
+    };
+
+    /// Construct a number from an exact value `value * 10^scale`, rounding to
+    /// 14 digits.
+    fn from_parts(negative: bool, mut value: u128, mut scale: i32) -> Result<Real> {
+        if value == 0 {
+            return Ok(Real::ZERO);
+        }
+
+        // Round to 14 digits, rounding halves away from zero
+        let mut digits = 1;
+        while digits < 39 && 10u128.pow(digits) <= value {
+            digits += 1;
+        }
+        if digits > DIGITS {
+            let divisor = 10u128.pow(digits - DIGITS);
+            let remainder = value % divisor;
+            value /= divisor;
+            scale += (digits - DIGITS) as i32;
+            if remainder * 2 >= divisor {
+                value += 1;
+            }
+            if value == 10u128.pow(DIGITS) {
+                value /= 10;
+                scale += 1;
+            }
+        } else {
+            value *= 10u128.pow(DIGITS - digits);
+            scale -= (DIGITS - digits) as i32;
+        }
+
+        let exponent = scale + DIGITS as i32 - 1;
+        if exponent > MAX_EXPONENT {
+            Err(Error::Overflow)
+        } else if exponent < -MAX_EXPONENT {
+            Ok(Real::ZERO)
+        } else {
+            Ok(Real {
+                negative,
+                exponent,
+                mantissa: value as u64,
+            })
+        }
+    }
+
+    /// Get the exact value of this number as `value * 10^scale`.
+    fn parts(&self) -> (u128, i32) {
+        (self.mantissa as u128, self.exponent - (DIGITS as i32 - 1))
+    }
+
+    /// Decode a number from its representation in memory.
+    ///
+    /// The type bits are ignored, so this can also decode each part of a complex
+    /// number. Invalid BCD digits are interpreted as their binary values.
+    pub fn from_bytes(bytes: &[u8; 9]) -> Real {
+        let mantissa = bytes[2..].iter().fold(0u64, |m, &b| {
+            m * 100 + (b >> 4) as u64 * 10 + (b & 0xF) as u64
+        });
+        if mantissa == 0 {
+            return Real::ZERO;
+        }
+        Real {
+            negative: bytes[0] & 0x80 != 0,
+            exponent: bytes[1] as i32 - 0x80,
+            mantissa,
+        }
+    }
+
+    /// Encode this number to its representation in memory, with the given type
+    /// in the low bits of the first byte.
+    pub fn to_bytes(&self, ty: u8) -> [u8; 9] {
+        let mut out = [0; 9];
+        out[0] = ty | if self.negative { 0x80 } else { 0 };
+        out[1] = (self.exponent + 0x80) as u8;
+        let mut mantissa = self.mantissa;
+        for byte in out[2..].iter_mut().rev() {
+            *byte = (mantissa % 10) as u8 | (((mantissa / 10) % 10) as u8) << 4;
+            mantissa /= 100;
+        }
+        out
+    }
+
+    /// Convert a finite `f64` to the nearest number, failing if it is out of range.
+    pub fn from_f64(value: f64) -> Result<Real> {
+        if !value.is_finite() {
+            return Err(Error::Invalid);
+        }
+        format!("{:.*e}", DIGITS as usize - 1, value).parse()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let (value, scale) = self.parts();
+        let sign = if self.negative { "-" } else { "" };
+        format!("{}{}e{}", sign, value, scale).parse().unwrap()
+    }
+
+    /// Convert an integer to a number.
+    pub fn from_int(value: i64) -> Real {
+        Real::from_parts(value < 0, value.unsigned_abs() as u128, 0)
+            .expect("Integers are always in range")
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Get the power of ten of the first digit of this number.
+    pub fn exponent(&self) -> i32 {
+        self.exponent
+    }
+
+    pub fn neg(&self) -> Real {
+        Real {
+            negative: !self.negative && !self.is_zero(),
+            ..*self
+        }
+    }
+
+    pub fn abs(&self) -> Real {
+        Real {
+            negative: false,
+            ..*self
+        }
+    }
+
+    pub fn add(&self, other: &Real) -> Result<Real> {
+        if self.is_zero() {
+            return Ok(*other);
+        } else if other.is_zero() {
+            return Ok(*self);
+        }
+
+        let (a, a_scale) = self.parts();
+        let (b, b_scale) = other.parts();
+        // If the numbers are of very different magnitudes the smaller one can't
+        // affect the rounded result, and aligning them could overflow.
+        let scale = std::cmp::min(a_scale, b_scale);
+        if (a_scale - b_scale).abs() > DIGITS as i32 + 2 {
+            return Ok(if a_scale > b_scale { *self } else { *other });
+        }
+        let a = a * 10u128.pow((a_scale - scale) as u32);
+        let b = b * 10u128.pow((b_scale - scale) as u32);
+
+        match (self.negative, other.negative) {
+            (x, y) if x == y => Real::from_parts(x, a + b, scale),
+            _ if a >= b => Real::from_parts(self.negative, a - b, scale),
+            _ => Real::from_parts(other.negative, b - a, scale),
+        }
+    }
+
+    pub fn sub(&self, other: &Real) -> Result<Real> {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Real) -> Result<Real> {
+        let (a, a_scale) = self.parts();
+        let (b, b_scale) = other.parts();
+        Real::from_parts(self.negative != other.negative, a * b, a_scale + b_scale)
+    }
+
+    pub fn div(&self, other: &Real) -> Result<Real> {
+        if other.is_zero() {
+            return Err(Error::DivideByZero);
+        }
+        let (a, a_scale) = self.parts();
+        let (b, b_scale) = other.parts();
+
+        // Compute extra digits, with a final digit that is nonzero if the quotient
+        // is inexact so rounding is correct.
+        const EXTRA: u32 = 20;
+        let a = a * 10u128.pow(EXTRA);
+        let quotient = (a / b) * 10 + (a % b).min(1);
+        Real::from_parts(
+            self.negative != other.negative,
+            quotient,
+            a_scale - b_scale - EXTRA as i32 - 1,
+        )
+    }
+
+    /// Get the integer part of this number, rounding toward zero.
+    pub fn trunc(&self) -> Real {
+        let digits = self.exponent + 1;
+        if self.is_zero() || digits >= DIGITS as i32 {
+            *self
+        } else if digits <= 0 {
+            Real::ZERO
+        } else {
+            let unit = 10u64.pow(DIGITS - digits as u32);
+            Real {
+                mantissa: self.mantissa / unit * unit,
+                ..*self
+            }
+            .normalized()
+        }
+    }
+
+    /// Round this number to `places` digits after the decimal point, which may be
+    /// negative to round to the left of it. Halves are rounded away from zero.
+    pub fn round(&self, places: i32) -> Result<Real> {
+        let (value, scale) = self.parts();
+        if self.is_zero() || scale >= -places {
+            return Ok(*self);
+        }
+        let drop = (-places - scale) as u32;
+        if drop > DIGITS {
+            return Ok(Real::ZERO);
+        }
+        let divisor = 10u128.pow(drop);
+        let rounded = (value + divisor / 2) / divisor;
+        Real::from_parts(self.negative, rounded, scale + drop as i32)
+    }
+
+    /// Get the greatest integer less than or equal to this number.
+    pub fn floor(&self) -> Real {
+        let trunc = self.trunc();
+        if self.negative && trunc != *self {
+            trunc
+                .sub(&Real::from_int(1))
+                .expect("Integers never overflow")
+        } else {
+            trunc
+        }
+    }
+
+    /// Get the fractional part of this number, which has the same sign.
+    pub fn fract(&self) -> Real {
+        self.sub(&self.trunc()).expect("Fractions never overflow")
+    }
+
+    /// Return the number with zero made canonical.
+    fn normalized(self) -> Real {
+        if self.mantissa == 0 {
+            Real::ZERO
+        } else {
+            self
+        }
+    }
+
+    /// Convert this number to an integer, failing if it is not an integer in the
+    /// range of `i64`.
+    pub fn to_int(&self) -> Result<i64> {
+        if self.trunc() != *self {
+            return Err(Error::Invalid);
+        }
+        let (value, scale) = self.parts();
+        let magnitude = if scale >= 0 {
+            10u128
+                .checked_pow(scale as u32)
+                .and_then(|s| value.checked_mul(s))
+        } else {
+            Some(value / 10u128.pow((-scale) as u32))
+        };
+        match magnitude.and_then(|m| i64::try_from(m).ok()) {
+            Some(m) if self.negative => Ok(-m),
+            Some(m) => Ok(m),
+            None => Err(Error::Overflow),
+        }
+    }
+}
+
+impl PartialOrd for Real {
+    fn partial_cmp(&self, other: &Real) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Real {
+    fn cmp(&self, other: &Real) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+
+        let magnitude = |r: &Real| {
+            if r.is_zero() {
+                (i32::MIN, 0)
+            } else {
+                (r.exponent, r.mantissa)
+            }
+        };
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => magnitude(self).cmp(&magnitude(other)),
+            (true, true) => magnitude(other).cmp(&magnitude(self)),
+        }
+    }
+}
+
+/// Parse a decimal number, such as `-12.5` or `1.5e-7`.
+impl FromStr for Real {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Real> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let (number, exponent) = match s.find(['e', 'E']) {
+            None => (s, 0),
+            Some(i) => (
+                &s[..i],
+                s[i + 1..].parse::<i32>().map_err(|_| Error::Invalid)?,
+            ),
+        };
+        let (int, frac) = match number.find('.') {
+            None => (number, ""),
+            Some(i) => (&number[..i], &number[i + 1..]),
+        };
+        if int.is_empty() && frac.is_empty() {
+            return Err(Error::Invalid);
+        }
+
+        // Accumulate digits, ignoring any beyond what can affect rounding.
+        let mut value = 0u128;
+        let mut scale = exponent;
+        let mut significant = 0;
+        for (i, c) in int.chars().chain(frac.chars()).enumerate() {
+            let digit = c.to_digit(10).ok_or(Error::Invalid)?;
+            if significant < 2 * DIGITS {
+                value = value * 10 + digit as u128;
+                if value != 0 {
+                    significant += 1;
+                }
+                if i >= int.len() {
+                    scale -= 1;
+                }
+            } else if i < int.len() {
+                scale += 1;
+            }
+        }
+        Real::from_parts(negative, value, scale)
+    }
+}
+
+/// Format a number in decimal, using scientific notation for very large or
+/// small values.
+impl fmt::Display for Real {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+
+        let digits = self.mantissa.to_string();
+        let digits = digits.trim_end_matches('0');
+        if self.exponent >= 10 || self.exponent < -3 {
+            write!(f, "{}", &digits[..1])?;
+            if digits.len() > 1 {
+                write!(f, ".{}", &digits[1..])?;
+            }
+            write!(f, "E{}", self.exponent)
+        } else if self.exponent < 0 {
+            let zeros = "0".repeat((-self.exponent - 1) as usize);
+            write!(f, "0.{}{}", zeros, digits)
+        } else {
+            let int_len = self.exponent as usize + 1;
+            if digits.len() <= int_len {
+                write!(f, "{}{}", digits, "0".repeat(int_len - digits.len()))
+            } else {
+                write!(f, "{}.{}", &digits[..int_len], &digits[int_len..])
+            }
+        }
+    }
+}
+
+/// A complex number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Complex {
+    pub re: Real,
+    pub im: Real,
+}
+
+impl Complex {
+    pub fn from_bytes(bytes: &[u8; 18]) -> Complex {
+        let (re, im) = bytes.split_at(9);
+        Complex {
+            re: Real::from_bytes(re.try_into().unwrap()),
+            im: Real::from_bytes(im.try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 18] {
+        let mut out = [0; 18];
+        out[..9].copy_from_slice(&self.re.to_bytes(COMPLEX_TYPE));
+        out[9..].copy_from_slice(&self.im.to_bytes(COMPLEX_TYPE));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Complex, Error, Real};
+
+    fn real(s: &str) -> Real {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn encoding() {
+        let pi = real("3.1415926535898");
+        assert_eq!(
+            pi.to_bytes(0),
+            [0x00, 0x80, 0x31, 0x41, 0x59, 0x26, 0x53, 0x58, 0x98]
+        );
+        assert_eq!(
+            real("-0.00125").to_bytes(0),
+            [0x80, 0x7D, 0x12, 0x50, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(Real::ZERO.to_bytes(0), [0, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(Real::from_bytes(&pi.to_bytes(0)), pi);
+
+        let z = Complex {
+            re: real("1"),
+            im: real("-2"),
+        };
+        let bytes = z.to_bytes();
+        assert_eq!(bytes[0], 0x0C);
+        assert_eq!(bytes[9], 0x8C);
+        assert_eq!(Complex::from_bytes(&bytes), z);
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Real::from_f64(0.1).unwrap(), real("0.1"));
+        assert_eq!(Real::from_f64(-1.5e-7).unwrap().to_string(), "-1.5E-7");
+        assert_eq!(real("1234.5").to_f64(), 1234.5);
+        assert_eq!(real("12345678901234567").to_string(), "1.2345678901235E16");
+        assert_eq!(real("100").to_string(), "100");
+        assert_eq!(real("0.025").to_string(), "0.025");
+        assert_eq!(Real::from_int(-42).to_int(), Ok(-42));
+        assert_eq!(real("1.5").to_int(), Err(Error::Invalid));
+        assert_eq!("1e100".parse::<Real>(), Err(Error::Overflow));
+        assert!(Real::from_f64(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(real("0.1").add(&real("0.2")), Ok(real("0.3")));
+        assert_eq!(real("1").sub(&real("1e-20")), Ok(real("1")));
+        assert_eq!(real("5").sub(&real("7")), Ok(real("-2")));
+        assert_eq!(real("-1.5").mul(&real("4")), Ok(real("-6")));
+        assert_eq!(real("2").div(&real("3")), Ok(real("0.66666666666667")));
+        assert_eq!(real("1").div(&Real::ZERO), Err(Error::DivideByZero));
+        assert_eq!(real("9e99").mul(&real("10")), Err(Error::Overflow));
+        assert_eq!(real("-2.5").floor(), real("-3"));
+        assert_eq!(real("-2.5").trunc(), real("-2"));
+        assert_eq!(real("-2.5").fract(), real("-0.5"));
+        assert_eq!(real("2.345").round(2), Ok(real("2.35")));
+        assert_eq!(real("-1234.5").round(-2), Ok(real("-1200")));
+        assert_eq!(real("0.6").round(0), Ok(real("1")));
+        for exp in 16..=40 {
+            let small = real(&format!("1e-{}", exp));
+            assert_eq!(real("1").add(&small), Ok(real("1")));
+            assert_eq!(small.sub(&real("1")), Ok(real("-1")));
+        }
+        assert!(real("-3") < real("-2.5"));
+        assert!(real("0.001") > Real::ZERO);
+    }
+}
@@ -7,6 +7,14 @@ pub const curRow: u16 = 0x844B;
 pub const curCol: u16 = 0x844C;
 
 pub const OP1: u16 = 0x8478;
+pub const OP2: u16 = 0x8483;
+pub const OP3: u16 = 0x848E;
+pub const OP4: u16 = 0x8499;
+pub const OP5: u16 = 0x84A4;
+pub const OP6: u16 = 0x84AF;
+
+/// Size of the assembly program running at userMem.
+pub const asm_prgm_size: u16 = 0x89FB;
 
 pub const textShadow: u16 = 0x8508;
 
@@ -15,13 +23,21 @@ pub const penRow: u16 = 0x86d8;
 
 pub const cmdShad: u16 = 0x966e;
 
+pub const fpBase: u16 = 0x9822;
+pub const FPS: u16 = 0x9824;
+pub const OPBase: u16 = 0x9826;
+pub const OPS: u16 = 0x9828;
 pub const pTemp: u16 = 0x982E;
 pub const progPtr: u16 = 0x9830;
+pub const newDataPtr: u16 = 0x9832;
 
 pub const flags: u16 = 0x98f0;
 pub const kbdFlags: u8 = 0;
 pub const kbdSCR: u8 = 3;
 
+pub const onFlags: u8 = 9;
+pub const onInterrupt: u8 = 4;
+
 pub const appFlags: u8 = 0xd;
 pub const appAutoScroll: u8 = 2;
 pub const indicFlags: u8 = 0x12;
@@ -37,5 +53,8 @@ pub const textWrite: u8 = 7;
 /// Primary graph buffer
 pub const plotSScreen: u16 = 0x9340;
 
+/// Start of user memory, where variable data is stored.
+pub const userMem: u16 = 0x9D95;
+
 /// Fixed value, topmost byte of the symbol table.
 pub const symTable: u16 = 0xFE66;
@@ -1,5 +1,7 @@
 //! The interrupt scheduler
 
+use crate::crystal::CrystalTimers;
+use crate::scheduler::{Event, Scheduler};
 use bitflags::bitflags;
 use std::time::Duration;
 
@@ -24,7 +26,9 @@ use std::time::Duration;
 /// interrupt will not be acknowledged.
 ///
 /// Port 4 reads interrupt status and writes various controls. The bit for
-/// each interrupt reads as whether that interrupt is pending.
+/// each interrupt reads as whether that interrupt is pending. Bit 3 reads as 0
+/// while the ON key is held, and bits 5-7 read as whether each of the crystal
+/// timers has a pending interrupt.
 ///
 /// On write, bits 1 and 2 adjust the timer frequencies, where the default is
 /// ~118 Hz with timer1 only. See [the table on WikiTI](
@@ -33,88 +37,217 @@ use std::time::Duration;
 ///
 /// ## Implementation
 ///
-/// Only the ON key and timer1 are currently implemented.
+/// All four interrupts are implemented. Timers are driven by events on the
+/// emulator's [Scheduler], so they fire at exact CPU cycles. Link activity becomes
+/// pending when the emulator sees a change in the link lines made by the other end
+/// of a cable (see [LinkPort](crate::link::LinkPort)), which it also schedules as an
+/// event. Timer 2 runs at the
+/// same frequency as timer 1, but fires half a period later so enabling both
+/// doubles the interrupt rate. Both timers run continuously, but only become
+/// pending while enabled.
+///
+/// The crystal timers present on later models are also owned by the interrupt
+/// controller, since they are a source of interrupts. They are controlled by their
+/// own ports rather than port 3; see [CrystalTimers].
 #[derive(Debug)]
 pub struct InterruptController {
-    /// Timer 1 is normally enabled at about 120 Hz.
-    timer1_period: Duration,
-    timer1_remaining: Duration,
-    timer1_enabled: bool,
-    timer1_pending: bool,
+    /// CPU clock frequency, used to convert timer periods to cycles.
+    clock_rate: u32,
+    /// Timer frequency selected by port 4.
+    timer_speed: u8,
+    timer1: Timer,
+    timer2: Timer,
+    crystal_timers: CrystalTimers,
 
     on_enabled: bool,
     on_pending: bool,
+    on_held: bool,
+
+    link_enabled: bool,
+    link_pending: bool,
+
+    /// Scheduler driven by the deprecated [InterruptController::advance], for
+    /// controllers not owned by an emulator.
+    standalone: Option<Box<Standalone>>,
+}
+
+#[derive(Debug)]
+struct Standalone {
+    scheduler: Scheduler,
+    /// Current CPU cycle.
+    now: u64,
 }
 
+/// A periodic hardware timer.
+#[derive(Debug)]
+struct Timer {
+    enabled: bool,
+    pending: bool,
+}
+
+impl Timer {
+    fn new(enabled: bool) -> Self {
+        Timer {
+            enabled,
+            pending: false,
+        }
+    }
+
+    fn fire(&mut self) {
+        self.pending |= self.enabled;
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.pending &= enabled;
+    }
+}
+
+/// Timer frequencies selected by bits 1 and 2 of port 4, in Hz.
+const TIMER_FREQUENCIES: [u32; 4] = [560, 248, 170, 118];
+
 impl InterruptController {
+    /// Create an interrupt controller for a 6 MHz CPU that is driven by
+    /// [InterruptController::advance].
+    #[deprecated(
+        note = "Use the interrupt controller of an Emulator, which drives it from CPU cycles"
+    )]
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let timer1_period = Duration::from_nanos(1e9 as u64 / 118);
+        let mut scheduler = Scheduler::new();
+        let mut out = Self::with_scheduler(6_000_000, &mut scheduler);
+        out.standalone = Some(Box::new(Standalone { scheduler, now: 0 }));
+        out
+    }
 
-        InterruptController {
-            timer1_period,
-            timer1_remaining: timer1_period,
-            timer1_enabled: true,
-            timer1_pending: false,
+    /// Create an interrupt controller, scheduling timers to begin at cycle 0.
+    pub(crate) fn with_scheduler(clock_rate: u32, scheduler: &mut Scheduler) -> Self {
+        let out = InterruptController {
+            clock_rate,
+            // Timer 1 is normally enabled at about 120 Hz.
+            timer_speed: 3,
+            timer1: Timer::new(true),
+            timer2: Timer::new(false),
+            crystal_timers: CrystalTimers::new(),
 
             on_enabled: true,
             on_pending: false,
-        }
+            on_held: false,
+
+            link_enabled: false,
+            link_pending: false,
+
+            standalone: None,
+        };
+        out.restart_timers(0, scheduler);
+        out
+    }
+
+    /// Get the period of timers 1 and 2 in CPU cycles.
+    fn timer_period(&self) -> u64 {
+        (self.clock_rate / TIMER_FREQUENCIES[self.timer_speed as usize]) as u64
+    }
+
+    fn restart_timers(&self, now: u64, scheduler: &mut Scheduler) {
+        let period = self.timer_period();
+        scheduler.schedule(Event::Timer1, now + period);
+        scheduler.schedule(Event::Timer2, now + period + period / 2);
     }
 
+    /// Press the ON key, raising an interrupt if enabled.
     pub fn on_pressed(&mut self) {
-        self.on_pending = true;
+        if !self.on_held {
+            self.on_pending |= self.on_enabled;
+        }
+        self.on_held = true;
+    }
+
+    pub fn on_released(&mut self) {
+        self.on_held = false;
+    }
+
+    /// Signal activity on the link port, raising an interrupt if enabled.
+    pub fn link_activity(&mut self) {
+        self.link_pending |= self.link_enabled;
     }
 
     pub fn is_pending(&self) -> bool {
-        self.timer1_pending || self.on_pending
+        self.timer1.pending
+            || self.timer2.pending
+            || self.on_pending
+            || self.link_pending
+            || self.crystal_timers.pending() != 0
+    }
+
+    /// Handle an event scheduled by this controller.
+    ///
+    /// `at` is the cycle at which the event was scheduled to occur, which may be
+    /// somewhat earlier than the current cycle.
+    pub(crate) fn handle_event(&mut self, event: Event, at: u64, scheduler: &mut Scheduler) {
+        match event {
+            Event::Timer1 => {
+                trace!("Timer1 fires at cycle {}", at);
+                self.timer1.fire();
+                scheduler.schedule(Event::Timer1, at + self.timer_period());
+            }
+            Event::Timer2 => {
+                trace!("Timer2 fires at cycle {}", at);
+                self.timer2.fire();
+                scheduler.schedule(Event::Timer2, at + self.timer_period());
+            }
+            Event::LinkActivity => self.link_activity(),
+            Event::CrystalTimer(n) => self.crystal_timers.expire(n, at, scheduler),
+            // The emulator gives LCD events to the display, and a standalone
+            // controller has no display, so there is nothing to do.
+            Event::LcdReady => {}
+        }
     }
 
     /// Update timers as if the system has run for the given duration.
     ///
-    /// This function drives timers and should be called after any CPU run.
+    /// This only affects controllers created with [InterruptController::new];
+    /// an emulator's controller is driven by the emulator as it runs.
+    #[deprecated(note = "Timers are driven by Emulator::run")]
     pub fn advance(&mut self, duration: Duration) {
-        debug!("Advance timers {:?}", duration);
-        if duration >= self.timer1_remaining {
-            // Interrupt becomes pending
-            self.timer1_pending = true;
-            debug!("Timer1 interrupt fires");
-
-            if duration >= (self.timer1_remaining + self.timer1_period) {
-                warn!(
-                    "Timer step of {:?} overflowed and skipped timer interrupts",
-                    duration
-                );
-                self.timer1_remaining = self.timer1_period;
-            } else {
-                self.timer1_remaining = self.timer1_period + self.timer1_remaining - duration;
+        let mut standalone = match self.standalone.take() {
+            Some(s) => s,
+            None => {
+                warn!("Ignoring advance() of an emulator's interrupt controller");
+                return;
             }
-        } else {
-            self.timer1_remaining -= duration;
+        };
+        standalone.now += (duration.as_nanos() * self.clock_rate as u128 / 1_000_000_000) as u64;
+        while let Some((event, at)) = standalone.scheduler.pop_due(standalone.now) {
+            self.handle_event(event, at, &mut standalone.scheduler);
         }
+        self.standalone = Some(standalone);
     }
 
     /// Poll for pending interrupts.
     ///
-    /// The application should call this periodically, ideally at least as
-    /// often as timer interrupts may fire.
-    ///
     /// Returns whether any interrupts are pending (in which case the CPU IRQ
-    /// line should be set), and the time until next interrupt, if known.
+    /// line should be set), and the time until the next timer event, if known.
+    #[deprecated(note = "Use InterruptController::irq_pending")]
     pub fn poll(&mut self) -> (bool, Option<Duration>) {
-        let pending =
-            (self.timer1_pending && self.timer1_enabled) || (self.on_pending && self.on_enabled);
-        let next = if self.timer1_enabled {
-            Some(self.timer1_remaining)
-        } else {
-            None
-        };
+        let next = self.standalone.as_ref().and_then(|s| {
+            let cycles = s.scheduler.next()? - s.now;
+            Some(Duration::from_secs_f64(
+                cycles as f64 / self.clock_rate as f64,
+            ))
+        });
+        (self.irq_pending(), next)
+    }
 
-        trace!(
-            "Interrupt controller polled: IRQ pending={}, next timer in {:?}",
-            pending,
-            next
-        );
-        (pending, next)
+    /// Return true if any enabled interrupt is pending, in which case the CPU IRQ
+    /// line should be set.
+    pub fn irq_pending(&self) -> bool {
+        let pending = (self.timer1.pending && self.timer1.enabled)
+            || (self.timer2.pending && self.timer2.enabled)
+            || (self.on_pending && self.on_enabled)
+            || (self.link_pending && self.link_enabled)
+            || self.crystal_timers.pending() != 0;
+        trace!("Interrupt controller polled: IRQ pending={}", pending);
+        pending
     }
 
     /// Read port 3, returning the enable status of interrupts.
@@ -123,9 +256,15 @@ impl InterruptController {
         if self.on_enabled {
             out |= InterruptFlags::ON;
         }
-        if self.timer1_enabled {
+        if self.timer1.enabled {
             out |= InterruptFlags::TIMER1;
         }
+        if self.timer2.enabled {
+            out |= InterruptFlags::TIMER2;
+        }
+        if self.link_enabled {
+            out |= InterruptFlags::LINKPORT;
+        }
 
         out.bits()
     }
@@ -136,13 +275,19 @@ impl InterruptController {
 
         self.on_enabled = value.contains(InterruptFlags::ON);
         self.on_pending &= self.on_enabled;
+        self.link_enabled = value.contains(InterruptFlags::LINKPORT);
+        self.link_pending &= self.link_enabled;
 
-        self.timer1_enabled = value.contains(InterruptFlags::TIMER1);
-        self.timer1_pending &= self.timer1_enabled;
+        self.timer1
+            .set_enabled(value.contains(InterruptFlags::TIMER1));
+        self.timer2
+            .set_enabled(value.contains(InterruptFlags::TIMER2));
         trace!(
-            "Wrote port 3; timer1 enabled={} pending={}",
-            self.timer1_enabled,
-            self.timer1_pending
+            "Wrote port 3; timer1 enabled={} pending={}, timer2 enabled={} pending={}",
+            self.timer1.enabled,
+            self.timer1.pending,
+            self.timer2.enabled,
+            self.timer2.pending
         );
     }
 
@@ -152,11 +297,65 @@ impl InterruptController {
         if self.on_pending {
             out |= InterruptFlags::ON;
         }
-        if self.timer1_pending {
+        if self.timer1.pending {
             out |= InterruptFlags::TIMER1;
         }
+        if self.timer2.pending {
+            out |= InterruptFlags::TIMER2;
+        }
+        if !self.on_held {
+            out |= InterruptFlags::ON_RELEASED;
+        }
+        if self.link_pending {
+            out |= InterruptFlags::LINKPORT;
+        }
 
-        out.bits()
+        out.bits() | (self.crystal_timers.pending() << 5)
+    }
+
+    /// Write port 4, setting the timer frequency from bits 1 and 2.
+    ///
+    /// Changing the frequency restarts both timers, but writes that don't change
+    /// it (such as to change the memory mapping mode) have no effect on them.
+    pub(crate) fn write_control_port(&mut self, value: u8, now: u64, scheduler: &mut Scheduler) {
+        let speed = (value >> 1) & 3;
+        if speed == self.timer_speed {
+            return;
+        }
+        self.timer_speed = speed;
+        debug!("Timer period set to {} cycles", self.timer_period());
+        self.restart_timers(now, scheduler);
+    }
+
+    /// Set the CPU clock frequency.
+    ///
+    /// The timers continue at the same frequency, which corresponds to a different
+    /// number of CPU cycles.
+    pub(crate) fn set_cpu_clock_rate(&mut self, hz: u32, now: u64, scheduler: &mut Scheduler) {
+        for &event in &[Event::Timer1, Event::Timer2] {
+            if let Some(at) = scheduler.scheduled(event) {
+                let remaining = (at - now) * hz as u64 / self.clock_rate as u64;
+                scheduler.schedule(event, now + remaining);
+            }
+        }
+        self.clock_rate = hz;
+        self.crystal_timers.set_cpu_clock_rate(hz, now, scheduler);
+    }
+
+    /// Read one of the crystal timer ports (0x30-0x38).
+    pub fn read_crystal_port(&self, port: u8, now: u64) -> u8 {
+        self.crystal_timers.read_port(port, now)
+    }
+
+    /// Write one of the crystal timer ports (0x30-0x38).
+    pub(crate) fn write_crystal_port(
+        &mut self,
+        port: u8,
+        value: u8,
+        now: u64,
+        scheduler: &mut Scheduler,
+    ) {
+        self.crystal_timers.write_port(port, value, now, scheduler);
     }
 }
 
@@ -165,6 +364,79 @@ bitflags! {
         const ON = 0x01;
         const TIMER1 = 0x02;
         const TIMER2 = 0x04;
+        /// Port 4 only: reset while ON is held.
+        const ON_RELEASED = 0x08;
         const LINKPORT = 0x10;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::InterruptController;
+    use crate::scheduler::{Event, Scheduler};
+
+    /// The mask TI-OS writes to port 3: ON, timer 1 and bit 3 (not low power).
+    const TIOS_INTERRUPT_MASK: u8 = 0x0B;
+
+    /// Handle timer events up to the given cycle, like the emulator does.
+    fn run_until(ic: &mut InterruptController, scheduler: &mut Scheduler, now: u64) {
+        while let Some((event, at)) = scheduler.pop_due(now) {
+            ic.handle_event(event, at, scheduler);
+        }
+    }
+
+    #[test]
+    fn tios_mask_enables_on_and_timer1() {
+        let mut scheduler = Scheduler::new();
+        let mut ic = InterruptController::with_scheduler(6_000_000, &mut scheduler);
+        ic.write_mask_port(TIOS_INTERRUPT_MASK);
+        assert_eq!(ic.read_mask_port(), 0x03);
+
+        // Timer 2 fires after timer 1 but stays masked.
+        run_until(&mut ic, &mut scheduler, 6_000_000 / 118 * 3 / 2);
+        assert!(ic.irq_pending());
+        assert_eq!(ic.read_status_port() & 0x07, 0x02);
+
+        ic.on_pressed();
+        assert_eq!(ic.read_status_port() & 0x0F, 0x03);
+    }
+
+    #[test]
+    fn timer2_fires_at_its_rate() {
+        for (speed, &hz) in super::TIMER_FREQUENCIES.iter().enumerate() {
+            let mut scheduler = Scheduler::new();
+            let mut ic = InterruptController::with_scheduler(6_000_000, &mut scheduler);
+            ic.write_control_port((speed as u8) << 1, 0, &mut scheduler);
+            // Timer 2 only
+            ic.write_mask_port(0x04);
+
+            let period = (6_000_000 / hz) as u64;
+            let first = period + period / 2;
+            assert_eq!(scheduler.scheduled(Event::Timer2), Some(first));
+            for n in 0..4 {
+                let at = first + n * period;
+                run_until(&mut ic, &mut scheduler, at - 1);
+                assert!(!ic.irq_pending(), "{} Hz fired early", hz);
+                run_until(&mut ic, &mut scheduler, at);
+                assert_eq!(ic.read_status_port() & 0x07, 0x04);
+
+                // Acknowledge
+                ic.write_mask_port(0);
+                ic.write_mask_port(0x04);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn standalone_timer1() {
+        let mut ic = InterruptController::new();
+        let (pending, next) = ic.poll();
+        assert!(!pending);
+        assert_eq!(next.map(|d| d.as_micros()), Some(8474));
+
+        ic.advance(std::time::Duration::from_millis(9));
+        assert!(ic.poll().0);
+        assert_eq!(ic.read_status_port() & 0x02, 0x02);
+    }
+}
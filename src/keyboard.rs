@@ -1,9 +1,11 @@
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
-/// Keyboard keys, excluding ON (which goes through the interrupt controller)
+/// Keyboard keys.
 ///
-/// Variant values are the same as the scan codes returned by _GetCSC.
+/// Variant values are the same as the scan codes returned by _GetCSC, except
+/// for ON which has no scan code. ON is not part of the key matrix and is
+/// instead handled by the interrupt controller.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
 pub enum Key {
     Down = 1,
@@ -55,6 +57,71 @@ pub enum Key {
     Second = 0x36,
     Mode = 0x37,
     Del = 0x38,
+    /// Not a real scan code: GetCSC never returns this value.
+    On = 0x40,
+}
+
+impl Key {
+    /// Get the key code that the TI-BASIC `getKey` function returns for this key.
+    ///
+    /// Key codes are the row of the key counting from the top of the keypad
+    /// times ten, plus its column.
+    pub fn key_code(self) -> u8 {
+        use Key::*;
+
+        match self {
+            YEquals => 11,
+            Window => 12,
+            Zoom => 13,
+            Trace => 14,
+            Graph => 15,
+            Second => 21,
+            Mode => 22,
+            Del => 23,
+            Left => 24,
+            Up => 25,
+            Right => 26,
+            Alpha => 31,
+            GraphVar => 32,
+            Stat => 33,
+            Down => 34,
+            Math => 41,
+            Apps => 42,
+            Program => 43,
+            Vars => 44,
+            Clear => 45,
+            Reciprocal => 51,
+            Sine => 52,
+            Cosine => 53,
+            Tangent => 54,
+            Caret => 55,
+            Square => 61,
+            Comma => 62,
+            OpenParen => 63,
+            CloseParen => 64,
+            Divide => 65,
+            Log => 71,
+            Seven => 72,
+            Eight => 73,
+            Nine => 74,
+            Multiply => 75,
+            NaturalLog => 81,
+            Four => 82,
+            Five => 83,
+            Six => 84,
+            Minus => 85,
+            Store => 91,
+            One => 92,
+            Two => 93,
+            Three => 94,
+            Plus => 95,
+            On => 101,
+            Zero => 102,
+            Period => 103,
+            Negate => 104,
+            Enter => 105,
+        }
+    }
 }
 
 impl std::convert::TryFrom<sdl2::keyboard::Keycode> for Key {
@@ -87,6 +154,7 @@ impl std::convert::TryFrom<sdl2::keyboard::Keycode> for Key {
             Keycode::Return | Keycode::KpEnter => Key::Enter,
             Keycode::Period | Keycode::KpPeriod => Key::Period,
             Keycode::Backspace => Key::Clear,
+            Keycode::F12 => Key::On,
             _ => return Err(()),
         })
     }
@@ -122,6 +190,7 @@ impl std::convert::TryInto<sdl2::keyboard::Keycode> for Key {
             Key::Enter => Keycode::Return,
             Key::Period => Keycode::Period,
             Key::Clear => Keycode::Backspace,
+            Key::On => Keycode::F12,
             _ => return Err(()),
         })
     }
@@ -158,14 +227,24 @@ impl Keyboard {
         }
     }
 
+    /// Press a key in the key matrix.
+    ///
+    /// ON is ignored, because it is handled by the interrupt controller.
     pub fn key_down(&mut self, key: Key) {
         trace!("Key down: {:?}", key);
-        self.keys_up[key_group(key)] &= !(1 << key_bit(key));
+        if let (Some(group), Some(bit)) = (key_group(key), key_bit(key)) {
+            self.keys_up[group] &= !(1 << bit);
+        }
     }
 
+    /// Release a key in the key matrix.
+    ///
+    /// ON is ignored, like for [key_down].
     pub fn key_up(&mut self, key: Key) {
         trace!("Key up: {:?}", key);
-        self.keys_up[key_group(key)] |= 1 << key_bit(key);
+        if let (Some(group), Some(bit)) = (key_group(key), key_bit(key)) {
+            self.keys_up[group] |= 1 << bit;
+        }
     }
 
     /// Write the keyboard port (1).
@@ -244,10 +323,11 @@ impl Keyboard {
 /// Keys that can repeat when polled with GetCSC
 static REPEATABLE_KEYS: [Key; 5] = [Key::Up, Key::Right, Key::Down, Key::Left, Key::Del];
 
-fn key_group(k: Key) -> usize {
+/// Get the group of a key in the key matrix, or `None` for ON, which isn't in it.
+fn key_group(k: Key) -> Option<usize> {
     use Key::*;
 
-    match k {
+    Some(match k {
         Down | Left | Right | Up => 0,
         Enter | Plus | Minus | Multiply | Divide | Caret | Clear => 1,
         Negate | Three | Six | Nine | CloseParen | Tangent | Vars => 2,
@@ -255,13 +335,15 @@ fn key_group(k: Key) -> usize {
         Zero | One | Four | Seven | Comma | Sine | Apps | GraphVar => 4,
         Store | NaturalLog | Log | Square | Reciprocal | Math | Alpha => 5,
         Graph | Trace | Zoom | Window | YEquals | Second | Mode | Del => 6,
-    }
+        On => return None,
+    })
 }
 
-fn key_bit(k: Key) -> u8 {
+/// Get the bit of a key within its group, or `None` for ON.
+fn key_bit(k: Key) -> Option<u8> {
     use Key::*;
 
-    match k {
+    Some(match k {
         Down | Enter | Negate | Period | Zero | Graph => 0,
         Left | Plus | Three | Two | One | Store | Trace => 1,
         Right | Minus | Six | Five | Four | NaturalLog | Zoom => 2,
@@ -270,5 +352,6 @@ fn key_bit(k: Key) -> u8 {
         Caret | Tangent | Cosine | Sine | Reciprocal | Second => 5,
         Clear | Vars | Program | Apps | Math | Mode => 6,
         Stat | GraphVar | Alpha | Del => 7,
-    }
+        On => return None,
+    })
 }
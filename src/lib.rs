@@ -40,19 +40,12 @@ Obviously required traps:
  * 0038: IM 1 vector
 
 Likely required traps:
- * The other documented rst vectors
-   * 08 => OP1ToOP2
-   * 10 => FindSym
-   * 18 => PushRealO1
-   * 20 => Mov9ToOP1
-   * 30 => FPAdd
  * LCD_BUSY_QUICK (0x000B; 47 cycles)
  * Ion vectors
  * MOS vectors
 
 Major bcalls:
  * PutS and friends (honor flags too)
- * ChkFindSym
 
 Ports that we probably need good fidelity for:
  * Keypad ports
@@ -72,14 +65,23 @@ Used by phoenix:
 
 */
 
+pub mod app;
+pub mod basic;
 mod bcalls;
 mod checksum;
+mod crystal;
+pub mod dbus;
 pub mod display;
+pub mod float;
 mod interrupt;
 pub mod keyboard;
+pub mod link;
 pub mod memory;
-mod tifiles;
+mod model;
+mod scheduler;
+pub mod tifiles;
 mod traps;
+pub mod vat;
 pub mod z80;
 
 pub mod include {
@@ -91,16 +93,38 @@ pub mod include {
 pub use display::Display;
 pub use interrupt::InterruptController;
 pub use memory::Memory;
+pub use model::Model;
 pub use z80::{Flags, Z80};
 
 pub struct Emulator {
+    model: Model,
+    /// The value last written to port 0x20, selecting the CPU speed.
+    cpu_speed: u8,
+    /// CPU clock frequency in Hz.
+    ///
+    /// Changes to `cpu_speed` take effect at the end of the current call to
+    /// [Z80::run], so the cycles of a run are all at the same rate.
     clock_rate: u32,
+    /// Number of CPU cycles elapsed before the current call to [Z80::run].
+    cycles: u64,
+    scheduler: scheduler::Scheduler,
     pub mem: Memory,
     pub interrupt_controller: InterruptController,
     pub display: Display,
     pub keyboard: keyboard::Keyboard,
+    pub link: link::LinkPort,
+    /// First flash page of the running app, which holds the branch table for
+    /// bcalls into the app's other pages.
+    app_page: Option<u8>,
+    /// If false, the OS is user-supplied and trap instructions are ignored.
+    traps_enabled: bool,
+    execution_fault: ExecutionFault,
+    /// Set when an execution fault requires the system be reset after the current run.
+    reset_requested: bool,
     /// If true, emulation has terminated.
     terminate: Cell<bool>,
+    /// The BASIC program being run by the OS, if any.
+    basic: Option<basic::Interpreter>,
 }
 
 static FLASH_IMAGE: &[(u8, &[u8])] = &[
@@ -110,6 +134,33 @@ static FLASH_IMAGE: &[(u8, &[u8])] = &[
     (4, include_bytes!("mirageos.bin")),
 ];
 
+/// Get the CPU clock rate selected by a port 0x20 value.
+///
+/// Zero selects 6 MHz and any other value 15 MHz.
+fn clock_rate_for_speed(speed: u8) -> u32 {
+    if speed & 3 == 0 {
+        6_000_000
+    } else {
+        15_000_000
+    }
+}
+
+/// What happens when the CPU executes code from memory it is not permitted to.
+///
+/// See [Memory::is_executable] for which memory may be executed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExecutionFault {
+    /// Reset the calculator, like real hardware does.
+    ///
+    /// All state except flash is lost. A user-supplied OS boots again, but with
+    /// tihle's OS emulation stops because there is nothing to boot.
+    #[default]
+    Reset,
+    /// Stop emulation without changing any state, logging the faulting address and
+    /// CPU state.
+    Stop,
+}
+
 /// Kinds of memory access
 #[derive(Debug, PartialEq, Eq)]
 enum MemoryAccessKind {
@@ -120,29 +171,219 @@ enum MemoryAccessKind {
 }
 
 impl Emulator {
-    /// Construct a new emulator.
+    /// Construct a new emulator of the default model.
     ///
     /// Initially the CPU is terminated; call [load_program] to start the
     /// CPU so calls to [run] will run the CPU.
     pub fn new() -> Self {
-        Emulator {
-            clock_rate: 6_000_000,
-            mem: Memory::new(FLASH_IMAGE),
-            interrupt_controller: InterruptController::new(),
+        Self::with_model(Model::default())
+    }
+
+    /// Construct a new emulator emulating the given calculator model.
+    pub fn with_model(model: Model) -> Self {
+        let mem = Memory::new(model, FLASH_IMAGE).expect("tihle's OS should fit in flash");
+        Self::with_memory(model, mem, true)
+    }
+
+    /// Construct an emulator that runs the OS in a ROM dump instead of tihle's OS.
+    ///
+    /// The ROM must be the complete flash contents of a calculator of the given
+    /// model. See [Emulator::with_flash_pages] for details of how the emulator
+    /// behaves.
+    pub fn with_rom(model: Model, rom: &[u8]) -> Result<Self, RomError> {
+        let expected = model.flash_pages() as usize * memory::PAGE_SIZE;
+        if rom.len() != expected {
+            return Err(RomError::IncorrectSize {
+                expected,
+                actual: rom.len(),
+            });
+        }
+
+        let pages: Vec<(u8, &[u8])> = rom
+            .chunks(memory::PAGE_SIZE)
+            .enumerate()
+            .map(|(i, page)| (i as u8, page))
+            .collect();
+        Self::with_flash_pages(model, &pages)
+    }
+
+    /// Construct an emulator that runs the OS from an 8xu OS upgrade instead of
+    /// tihle's OS.
+    ///
+    /// Only the pages of the OS are present, so this works with an OS that doesn't
+    /// depend on the boot code. See [Emulator::with_flash_pages] for details of how
+    /// the emulator behaves.
+    pub fn with_os(os: tifiles::FlashFile, model: Model) -> Result<Self, RomError> {
+        if os.ty != tifiles::FlashType::Os {
+            return Err(RomError::NotAnOs);
+        }
+        let pages: Vec<(u8, &[u8])> = os.pages.iter().map(|p| (p.page, &p.data[..])).collect();
+        Self::with_flash_pages(model, &pages)
+    }
+
+    /// Construct an emulator with only the given flash pages present, instead of
+    /// tihle's OS.
+    ///
+    /// [Emulator::with_os] uses this to run an OS from an 8xu OS upgrade, but an
+    /// OS normally also depends on the boot code, which is not part of an upgrade.
+    ///
+    /// The emulator begins running immediately, with the CPU starting from reset.
+    /// Trap instructions are ignored and programs cannot be loaded with
+    /// [Emulator::load_program]; send them over the link port with a
+    /// [dbus::Endpoint] instead.
+    ///
+    /// Fails if a page doesn't exist on the model or is larger than a flash page.
+    pub fn with_flash_pages<'i, I: 'i + IntoIterator<Item = &'i (u8, &'i [u8])>>(
+        model: Model,
+        pages: I,
+    ) -> Result<Self, RomError> {
+        let emu = Self::with_memory(model, Memory::new(model, pages)?, false);
+        emu.terminate.set(false);
+        Ok(emu)
+    }
+
+    fn with_memory(model: Model, mem: Memory, traps_enabled: bool) -> Self {
+        let clock_rate = clock_rate_for_speed(0);
+        let mut scheduler = scheduler::Scheduler::new();
+        let interrupt_controller = InterruptController::with_scheduler(clock_rate, &mut scheduler);
+
+        let mut emu = Emulator {
+            model,
+            cpu_speed: 0,
+            clock_rate,
+            cycles: 0,
+            scheduler,
+            mem,
+            interrupt_controller,
             display: Display::new(),
             keyboard: keyboard::Keyboard::new(),
+            link: link::LinkPort::new(),
+            app_page: None,
+            traps_enabled,
+            execution_fault: Default::default(),
+            reset_requested: false,
             terminate: Cell::new(true),
+            basic: None,
+        };
+        emu.display.set_clock_rate(emu.clock_rate);
+        if traps_enabled {
+            // RAM is initialized with no variables
+            vat::clear(&mut emu.mem);
         }
+        emu
     }
 
+    /// Reset the emulator to its initial state.
+    ///
+    /// Any link cable remains connected and configuration such as the
+    /// [ExecutionFault] behavior is retained.
     pub fn reset(&mut self) {
-        *self = Self::new();
+        let cable = self.link.disconnect();
+        let execution_fault = self.execution_fault;
+        if self.traps_enabled {
+            *self = Self::with_model(self.model);
+        } else {
+            // Flash is retained and the user-supplied OS starts again
+            let mem = Memory::with_flash(self.model, self.mem.take_flash());
+            *self = Self::with_memory(self.model, mem, false);
+            self.terminate.set(false);
+        }
+        self.execution_fault = execution_fault;
+        if let Some(end) = cable {
+            self.link.connect(end);
+        }
+    }
+
+    /// Set what happens when the CPU executes code from protected memory.
+    pub fn set_execution_fault(&mut self, fault: ExecutionFault) {
+        self.execution_fault = fault;
+    }
+
+    /// Connect the link ports of two emulators with a cable.
+    ///
+    /// The emulators should be run alternately with small steps so each sees
+    /// changes made by the other in a timely manner.
+    pub fn connect_link(&mut self, other: &mut Emulator) {
+        let (a, b) = link::cable();
+        self.link.connect(a);
+        other.link.connect(b);
+    }
+
+    /// Raise the link interrupt if the other end of the link cable has changed the
+    /// state of the lines.
+    /// Check for changes to the link lines made by the other end of the cable,
+    /// scheduling a link interrupt at cycle `now` if there are any.
+    fn poll_link(&mut self, now: u64) {
+        if self.link.take_activity() {
+            debug!("Link activity: lines={:02b}", self.link.lines());
+            self.scheduler.schedule(scheduler::Event::LinkActivity, now);
+        }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Press a key.
+    ///
+    /// This should be preferred to using the keyboard directly, because the ON
+    /// key is not part of the keyboard.
+    pub fn key_down(&mut self, key: keyboard::Key) {
+        if key == keyboard::Key::On {
+            self.interrupt_controller.on_pressed();
+        } else {
+            self.keyboard.key_down(key);
+        }
+    }
+
+    /// Release a key.
+    pub fn key_up(&mut self, key: keyboard::Key) {
+        if key == keyboard::Key::On {
+            self.interrupt_controller.on_released();
+        } else {
+            self.keyboard.key_up(key);
+        }
     }
 
     pub fn is_running(&self) -> bool {
         !self.terminate.get()
     }
 
+    /// Get the current CPU clock frequency in Hz.
+    ///
+    /// This is 6 MHz unless a program selects a higher speed on models that support
+    /// it, so it may change while running.
+    pub fn clock_rate(&self) -> u32 {
+        self.clock_rate
+    }
+
+    /// Write port 0x20, changing the CPU clock rate.
+    ///
+    /// Because the CPU's cycle count is converted to time at the end of a run, the
+    /// CPU is made to yield and the new rate applies from the end of the run.
+    fn set_cpu_speed(&mut self, cpu: &mut Z80, value: u8) {
+        self.cpu_speed = value & 3;
+        if clock_rate_for_speed(self.cpu_speed) != self.clock_rate {
+            cpu.request_yield();
+        }
+    }
+
+    /// Switch to the clock rate selected by port 0x20, if it has changed.
+    fn update_clock_rate(&mut self) {
+        let clock_rate = clock_rate_for_speed(self.cpu_speed);
+        if clock_rate == self.clock_rate {
+            return;
+        }
+        debug!("CPU clock rate set to {} Hz", clock_rate);
+
+        let now = self.cycles;
+        self.run_events(now);
+        self.clock_rate = clock_rate;
+        self.display.set_clock_rate(clock_rate);
+        self.interrupt_controller
+            .set_cpu_clock_rate(clock_rate, now, &mut self.scheduler);
+    }
+
     fn duration_to_cycles(&self, duration: Duration) -> usize {
         let cycle_secs = 1.0 / self.clock_rate as f64;
 
@@ -155,6 +396,24 @@ impl Emulator {
         Duration::from_secs_f64(cycles as f64 / self.clock_rate as f64)
     }
 
+    /// Get the total number of CPU cycles that have elapsed, including those in
+    /// the current CPU run.
+    fn current_cycle(&self, cpu: &Z80) -> u64 {
+        self.cycles + cpu.cycles_run() as u64
+    }
+
+    /// Handle all scheduled events that occur at or before cycle `now`.
+    fn run_events(&mut self, now: u64) {
+        while let Some((event, at)) = self.scheduler.pop_due(now) {
+            match event {
+                scheduler::Event::LcdReady => self.display.ready(),
+                _ => self
+                    .interrupt_controller
+                    .handle_event(event, at, &mut self.scheduler),
+            }
+        }
+    }
+
     /// Run the emulator for up to `max_step`, returning the amount of time
     /// the emulated CPU ran for.
     pub fn run(&mut self, cpu: &mut Z80, max_step: Duration) -> Duration {
@@ -163,46 +422,50 @@ impl Emulator {
             return Duration::from_secs(0);
         }
 
-        let (irq_pending, until_next_interrupt) = self.interrupt_controller.poll();
+        self.poll_link(self.cycles);
+        self.run_events(self.cycles);
+        let irq_pending = self.interrupt_controller.irq_pending();
+        let next_event = self.scheduler.next();
         debug!(
-            "IRQ pending: {}; next interrupt: {:?}",
-            irq_pending, until_next_interrupt
+            "IRQ pending: {}; next event at cycle {:?}",
+            irq_pending, next_event
         );
         cpu.set_irq(irq_pending);
 
-        // Run the CPU for the requested time or until the next interrupt,
-        // whichever is sooner.
-        // TODO: this won't handle enabling interrupts while running;
-        // the core needs to be able to break based on requests from
-        // callbacks to do this accurately.
-        let step_duration = match until_next_interrupt {
-            None => max_step,
-            Some(t) => std::cmp::min(max_step, t),
+        // Run the CPU for the requested time or until the next event, whichever
+        // is sooner. The CPU yields early if interrupts are enabled or the schedule
+        // changes, so events are handled within an instruction of when they occur.
+        let max_cycles = self.duration_to_cycles(max_step) as u64;
+        let step_cycles = match next_event {
+            None => max_cycles,
+            Some(at) => std::cmp::min(max_cycles, at - self.cycles),
         };
 
         let duration_run = if cpu.is_halted() && !irq_pending {
-            debug!("CPU halted, wait {:?} for interrupt", step_duration);
-            step_duration
+            debug!("CPU halted, wait {} cycles for interrupt", step_cycles);
+            self.cycles += step_cycles;
+            self.cycles_to_duration(step_cycles as usize)
         } else {
-            debug!(
-                "Run CPU for {:?} ({} cycles)",
-                step_duration,
-                self.duration_to_cycles(step_duration)
-            );
-            let cycles_run = cpu.run(self.duration_to_cycles(step_duration), self);
+            debug!("Run CPU for {} cycles", step_cycles);
+            let cycles_run = cpu.run(step_cycles as usize, self);
+            self.cycles += cycles_run as u64;
             self.cycles_to_duration(cycles_run)
         };
+        self.display.set_time(self.cycles);
+        self.update_clock_rate();
 
-        self.interrupt_controller.advance(duration_run);
+        self.run_events(self.cycles);
+        if self.reset_requested {
+            self.reset();
+            cpu.reset();
+        }
         duration_run
     }
 
-    /// Load an 8xp-format program from the given reader.
+    /// Load an 8xp-format program from the given reader and run it.
     ///
-    /// The program will be loaded at 9D95 with the CPU set to begin execution there,
-    /// and the system state will be set up consistent with the detected type of program
-    /// (such as setting up the context for the appropriate shell). The emulator
-    /// will be reset so [run] will run the CPU.
+    /// The program is stored in the VAT as if with [Emulator::load_variable], then
+    /// run with [Emulator::run_program].
     pub fn load_program<R: std::io::Read>(
         &mut self,
         cpu: &mut Z80,
@@ -210,36 +473,163 @@ impl Emulator {
     ) -> Result<tifiles::Variable, LoadProgramError> {
         use tifiles::{File, VariableType};
 
+        if !self.traps_enabled {
+            return Err(LoadProgramError::UserOs);
+        }
+
         let file = File::read_from(r)?;
-        let mut var = file.var;
-        if var.ty != VariableType::Program && var.ty != VariableType::ProtectedProgram {
+        if file.var.ty != VariableType::Program && file.var.ty != VariableType::ProtectedProgram {
             return Err(LoadProgramError::UnsupportedType);
         }
+        self.store_variable(&file.var)?;
+        self.run_program(cpu, &file.var.name)
+    }
+
+    /// Load a variable of any type from an 8x* file into RAM.
+    ///
+    /// The variable is added to the VAT, replacing any existing variable with the
+    /// same name. Nothing else is changed, so this can be used to load data such
+    /// as levels for a program that is running or will be run with
+    /// [Emulator::run_program].
+    pub fn load_variable<R: std::io::Read>(
+        &mut self,
+        r: R,
+    ) -> Result<tifiles::Variable, LoadProgramError> {
+        if !self.traps_enabled {
+            return Err(LoadProgramError::UserOs);
+        }
+
+        let file = tifiles::File::read_from(r)?;
+        self.store_variable(&file.var)?;
+        Ok(file.var)
+    }
+
+    fn store_variable(&mut self, var: &tifiles::Variable) -> Result<(), LoadProgramError> {
+        if let Some(existing) = vat::find(&self.mem, var.ty, &var.name) {
+            debug!("Replacing existing variable {:?}", existing);
+            vat::delete(&mut self.mem, &existing);
+        }
+        vat::insert(&mut self.mem, var.ty, &var.name, &var.data)?;
+        Ok(())
+    }
+
+    /// Run the program with the given name from the VAT.
+    ///
+    /// Assembly programs have their code copied to 9D95 with the CPU set to begin
+    /// execution there, and the system state is set up consistent with the
+    /// detected type of program (such as setting up the context for the
+    /// appropriate shell). BASIC programs are run by the OS's interpreter, which
+    /// displays on the home screen. Other variables are retained, but any program
+    /// that was already running is stopped.
+    pub fn run_program(
+        &mut self,
+        cpu: &mut Z80,
+        name: &[u8],
+    ) -> Result<tifiles::Variable, LoadProgramError> {
+        use include::tios;
+        use tifiles::{ASM_PROGRAM_SIGNATURE, UNSQUISHED_PROGRAM_SIGNATURE};
+
+        if !self.traps_enabled {
+            return Err(LoadProgramError::UserOs);
+        }
+
+        let var = self.find_program(name)?;
+        let regs = cpu.regs_mut();
+        // Set up stack to return to the reset vector at exit.
+        self.mem[0xfffe] = 0;
+        self.mem[0xffff] = 0;
+        regs.sp = 0xfffe;
+        self.setup_tios_context(cpu);
+        self.basic = None;
+
+        let data = var.calc_data();
+        if data.starts_with(ASM_PROGRAM_SIGNATURE) || data.starts_with(UNSQUISHED_PROGRAM_SIGNATURE)
+        {
+            self.start_asm_program(cpu, var.clone())?;
+        } else {
+            let tokens =
+                basic::tokens::split_tokens(var.calc_data()).map_err(LoadProgramError::Tokens)?;
+            // Any assembly program that was running is no longer needed.
+            let running_size = self.mem.read_u16(tios::asm_prgm_size);
+            vat::delete_mem(&mut self.mem, tios::userMem, running_size);
+            self.mem.write_u16(tios::asm_prgm_size, 0);
+
+            bcalls::set_flag(self, cpu, tios::appFlags, tios::appAutoScroll);
+            self.basic = Some(basic::Interpreter::new(tokens));
+            cpu.regs_mut().pc = basic::BASIC_LOOP;
+            self.mem.set_bank_a_page(4);
+            self.app_page = None;
+        }
+
+        self.terminate.set(false);
+        Ok(var)
+    }
+
+    /// Find a program in RAM by name.
+    pub(crate) fn find_program(&self, name: &[u8]) -> Result<tifiles::Variable, LoadProgramError> {
+        use tifiles::{Variable, VariableType};
+
+        let entry = vat::find(&self.mem, VariableType::Program, name)
+            .filter(|e| e.in_ram())
+            .ok_or(LoadProgramError::NotFound)?;
+        let size = vat::data_size(&self.mem, &entry);
+        let var = Variable {
+            name: entry.name.clone(),
+            ty: entry.ty().unwrap_or(VariableType::Program),
+            version: Some(entry.version),
+            flags: None,
+            data: (entry.data..entry.data + size)
+                .map(|addr| self.mem[addr])
+                .collect(),
+        };
 
         let internal_len = var.data[0] as u16 | (var.data[1] as u16) << 8;
         if internal_len != (var.data.len() - 2) as u16 {
             return Err(LoadProgramError::IncorrectLength);
         }
+        Ok(var)
+    }
 
-        // t2ByteTok, tAsmCmp signature marks an assembly program
-        if var.data[2..4] != b"\xbb\x6d"[..] {
+    /// Copy an assembly program to userMem and begin executing it, replacing any
+    /// assembly program that was already there. Unsquished programs are converted
+    /// to machine code first.
+    ///
+    /// The stack and other system state are not changed, except as needed to
+    /// support the shell the program was written for.
+    pub(crate) fn start_asm_program(
+        &mut self,
+        cpu: &mut Z80,
+        mut var: tifiles::Variable,
+    ) -> Result<(), LoadProgramError> {
+        use include::tios;
+        use tifiles::ASM_PROGRAM_SIGNATURE;
+
+        if var.squish().map_err(LoadProgramError::InvalidHex)? {
+            debug!("Squished {} byte(s) of code", var.data.len() - 4);
+        }
+        if !var.calc_data().starts_with(ASM_PROGRAM_SIGNATURE) {
             return Err(LoadProgramError::InvalidSignature);
         }
+
+        // Like TI-OS, the program's code is copied to userMem for execution, moving
+        // variables up. The copy of any program that was already running is removed.
+        let running_size = self.mem.read_u16(tios::asm_prgm_size);
+        vat::delete_mem(&mut self.mem, tios::userMem, running_size);
+        self.mem.write_u16(tios::asm_prgm_size, 0);
         let uses_ion_libraries = var.patch_ion_program();
         var.patch_mos_program();
 
-        let code_size = internal_len - 2;
-        let load_addr = 0x9d95u16; // userMem
+        let code_size = (var.data.len() - 4) as u16;
+        let load_addr = tios::userMem;
         debug!("Loading {} byte(s) of code to {:04X}", code_size, load_addr);
-        self.mem[load_addr..load_addr + code_size].copy_from_slice(&var.data[4..]);
-
-        let regs = cpu.regs_mut();
-        // Set up stack to return to the reset vector at exit.
-        self.mem[0xfffe] = 0;
-        self.mem[0xffff] = 0;
-        regs.sp = 0xfffe;
+        vat::insert_mem(&mut self.mem, load_addr, code_size)?;
+        // Large programs can span RAM pages, so copy bytewise rather than as a slice.
+        for (addr, &byte) in (load_addr..load_addr + code_size).zip(&var.data[4..]) {
+            self.mem[addr] = byte;
+        }
+        self.mem.write_u16(tios::asm_prgm_size, code_size);
         // Begin executing at load address
-        regs.pc = load_addr as u16;
+        cpu.regs_mut().pc = load_addr;
 
         if uses_ion_libraries {
             use include::{ion, mirageos};
@@ -258,12 +648,61 @@ impl Emulator {
             vector(ion::ionDecompress, mirageos::ionDecompress);
         }
 
-        self.setup_tios_context(cpu);
         // Map Mirage into bank A
         self.mem.set_bank_a_page(4);
+        self.app_page = None;
+        Ok(())
+    }
+
+    /// Install a Flash app from an 8xk file read from the given reader.
+    ///
+    /// The app is written to the flash pages following any apps that are already
+    /// installed, where it can be found with [Emulator::apps] and started with
+    /// [Emulator::launch_app].
+    pub fn install_app<R: std::io::Read>(&mut self, r: R) -> Result<app::App, LoadAppError> {
+        if !self.traps_enabled {
+            return Err(LoadAppError::UserOs);
+        }
+
+        let file = tifiles::FlashFile::read_from(r)?;
+        let app = app::install(&mut self.mem, self.model, &file)?;
+        info!(
+            "Installed app {:?} on flash page {:02X}",
+            String::from_utf8_lossy(&app.name),
+            app.page
+        );
+        Ok(app)
+    }
+
+    /// Get the apps installed in flash.
+    pub fn apps(&self) -> Vec<app::App> {
+        app::installed(&self.mem, self.model)
+    }
+
+    /// Start running an installed app.
+    ///
+    /// The app's first page is mapped into bank A and execution begins at its
+    /// entry point, with the system set up as TI-OS does when starting an app.
+    /// bcalls made by the app to addresses below 4000 are resolved through the
+    /// app's branch table.
+    pub fn launch_app(&mut self, cpu: &mut Z80, app: &app::App) {
+        debug!(
+            "Launching app {:?} at {:02X}:{:04X}",
+            String::from_utf8_lossy(&app.name),
+            app.page,
+            app.entry
+        );
+        let regs = cpu.regs_mut();
+        // Returning from the app resets, like a program.
+        self.mem.write_u16(0xfffe, 0);
+        regs.sp = 0xfffe;
+        regs.pc = app.entry;
+
+        self.setup_tios_context(cpu);
+        self.mem.set_bank_a_page(app.page);
+        self.app_page = Some(app.page);
 
         self.terminate.set(false);
-        Ok(var)
     }
 
     fn setup_tios_context(&mut self, core: &mut Z80) {
@@ -282,10 +721,6 @@ impl Emulator {
             self.mem[addr] = 0;
         }
 
-        // The VAT is empty
-        self.mem.write_u16(tios::progPtr, tios::symTable);
-        self.mem.write_u16(tios::pTemp, tios::symTable);
-
         // The unused hardware stack area is zeroed
         for addr in tios::symTable + 1..regs.sp {
             self.mem[addr] = 0;
@@ -295,15 +730,45 @@ impl Emulator {
         for &byte in &[tios::curRow, tios::curCol, tios::penRow, tios::penCol] {
             self.mem[byte] = 0;
         }
+
+        // Code may only be executed from RAM page 1, on models that can limit it
+        if self.model.is_se_hardware() {
+            self.mem.write_exec_limit_port(0x25, 0x10);
+            self.mem.write_exec_limit_port(0x26, 0x20);
+        }
     }
 
     #[inline]
-    fn read_memory(&mut self, _core: &mut Z80, addr: u16, access_kind: MemoryAccessKind) -> u8 {
-        let byte = self.mem[addr];
+    fn read_memory(&mut self, core: &mut Z80, addr: u16, access_kind: MemoryAccessKind) -> u8 {
+        if access_kind == MemoryAccessKind::Instruction && !self.mem.is_executable(addr) {
+            return self.execution_fault(core, addr);
+        }
+        let byte = self.mem.read(addr);
         trace!("Memory read {:?} {:04X} -> {:02X}", access_kind, addr, byte);
         byte
     }
 
+    /// Handle an instruction fetch from memory that may not be executed, returning
+    /// the instruction the CPU should execute instead.
+    #[cold]
+    fn execution_fault(&mut self, core: &mut Z80, addr: u16) -> u8 {
+        error!(
+            "Executed protected memory at {:04X} ({:?}); {:?}",
+            addr,
+            self.mem.page_at(addr),
+            self.execution_fault
+        );
+        match self.execution_fault {
+            ExecutionFault::Reset => self.reset_requested = true,
+            ExecutionFault::Stop => info!("{:#?}", core.regs()),
+        }
+        self.terminate.set(true);
+        core.request_yield();
+
+        // HALT leaves PC at the faulting instruction
+        0x76
+    }
+
     #[inline]
     fn write_memory(&mut self, core: &mut Z80, addr: u16, value: u8) {
         trace!("Memory write {:02X} -> {:04X}", value, addr);
@@ -314,19 +779,54 @@ impl Emulator {
 
     fn write_io(&mut self, cpu: &mut Z80, port: u8, value: u8) {
         match port {
+            0x00 => self.link.write_port(value),
             0x01 => self.keyboard.set_active_mask(value),
             0x03 => {
+                self.run_events(self.current_cycle(cpu));
                 self.interrupt_controller.write_mask_port(value);
-                // TODO since this can affect scheduling, we should actually force
-                // the CPU to yield so we can run until the next interrupt that may
-                // be sooner than originally thought.
-                let (pending, _) = self.interrupt_controller.poll();
+                let pending = self.interrupt_controller.irq_pending();
                 debug!("Port 3 write {:02X} sets IRQ={}", value, pending);
                 cpu.set_irq(pending);
             }
+            0x04 => {
+                self.mem.set_mapping_mode(if value & 1 == 0 {
+                    memory::MappingMode::Mode0
+                } else {
+                    memory::MappingMode::Mode1
+                });
+                let now = self.current_cycle(cpu);
+                self.run_events(now);
+                self.interrupt_controller
+                    .write_control_port(value, now, &mut self.scheduler);
+                // Timers restart if the frequency changes
+                cpu.request_yield();
+            }
+            0x05 if self.model.is_se_hardware() => self.mem.set_bank_c_page(value),
             0x06 => self.mem.set_bank_a_page(value),
-            0x10 => self.display.write_control(value),
-            0x11 => self.display.write_data(value),
+            0x07 => self.mem.set_bank_b_page(value),
+            0x10 => {
+                let now = self.current_cycle(cpu);
+                self.run_events(now);
+                self.display.write_control(value, now, &mut self.scheduler);
+            }
+            0x11 => {
+                let now = self.current_cycle(cpu);
+                self.run_events(now);
+                self.display.write_data(value, now, &mut self.scheduler);
+            }
+            0x14 => self.mem.set_flash_unlocked(value & 1 != 0),
+            0x20 if self.model.is_se_hardware() => self.set_cpu_speed(cpu, value),
+            0x22..=0x26 if self.model.is_se_hardware() => {
+                self.mem.write_exec_limit_port(port, value)
+            }
+            0x30..=0x38 if self.model.is_se_hardware() => {
+                let now = self.current_cycle(cpu);
+                self.run_events(now);
+                self.interrupt_controller
+                    .write_crystal_port(port, value, now, &mut self.scheduler);
+                cpu.set_irq(self.interrupt_controller.irq_pending());
+                cpu.request_yield();
+            }
             _ => {
                 warn!(
                     "Unhandled port write to {:#04x} (value={:#04x})",
@@ -336,14 +836,37 @@ impl Emulator {
         }
     }
 
-    fn read_io(&mut self, _core: &mut Z80, port: u8) -> u8 {
+    fn read_io(&mut self, core: &mut Z80, port: u8) -> u8 {
         match port {
+            0x00 => self.link.read_port(),
             0x01 => self.keyboard.read(),
             0x03 => self.interrupt_controller.read_mask_port(),
-            0x04 => self.interrupt_controller.read_status_port(),
+            0x04 => {
+                let now = self.current_cycle(core);
+                self.poll_link(now);
+                self.run_events(now);
+                self.interrupt_controller.read_status_port()
+            }
+            0x05 if self.model.is_se_hardware() => self.mem.get_bank_c_page(),
             0x06 => self.mem.get_bank_a_page(),
-            0x10 => self.display.read_status(),
-            0x11 => self.display.read_data(),
+            0x07 => self.mem.get_bank_b_page(),
+            0x10 => {
+                self.run_events(self.current_cycle(core));
+                self.display.read_status()
+            }
+            0x11 => {
+                let now = self.current_cycle(core);
+                self.run_events(now);
+                self.display.read_data(now, &mut self.scheduler)
+            }
+            0x14 => self.mem.is_flash_unlocked() as u8,
+            0x20 if self.model.is_se_hardware() => self.cpu_speed,
+            0x22..=0x26 if self.model.is_se_hardware() => self.mem.read_exec_limit_port(port),
+            0x30..=0x38 if self.model.is_se_hardware() => {
+                let now = self.current_cycle(core);
+                self.run_events(now);
+                self.interrupt_controller.read_crystal_port(port, now)
+            }
             _ => {
                 warn!("Unhandled port read from {:#04x}", port);
                 0
@@ -352,6 +875,15 @@ impl Emulator {
     }
 
     fn trap(&mut self, trap_no: u16, core: &mut Z80) -> usize {
+        if !self.traps_enabled {
+            // Execute as the illegal instruction it is on real hardware, which
+            // skips only the ED 25 bytes.
+            trace!("Ignoring trap {:04X} with user-supplied OS", trap_no);
+            core.regs_mut().pc -= 2;
+            return 8;
+        }
+        // Traps may update the display directly
+        self.display.set_time(self.current_cycle(core));
         if let Some(trap) = traps::Trap::from_u16(trap_no) {
             trap.handle(self, core)
         } else {
@@ -368,6 +900,43 @@ pub enum LoadProgramError {
     InvalidSignature,
     /// The internal length field does not match the actual size.
     IncorrectLength,
+    /// Programs cannot be loaded directly when running a user-supplied OS.
+    UserOs,
+    /// The variable could not be stored in RAM.
+    Vat(vat::Error),
+    /// There is no program with the requested name in RAM.
+    NotFound,
+    /// A BASIC program ends partway through a token.
+    Tokens(basic::tokens::Error),
+    /// An unsquished assembly program's hex text is malformed.
+    InvalidHex(tifiles::Error),
+}
+
+#[derive(Debug)]
+pub enum LoadAppError {
+    FileRead(tifiles::Error),
+    /// The file is not an app.
+    UnsupportedType,
+    /// The app's header is missing or invalid, or its pages don't fit it.
+    InvalidHeader,
+    /// An app with the same name is already installed.
+    AlreadyInstalled,
+    /// There are not enough free flash pages to install the app.
+    InsufficientSpace,
+    /// Apps cannot be installed when running a user-supplied OS.
+    UserOs,
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    /// The ROM is the wrong size for the selected model.
+    IncorrectSize { expected: usize, actual: usize },
+    /// The flash file is not an OS.
+    NotAnOs,
+    /// A flash page that the selected model doesn't have.
+    PageOutOfRange(u8),
+    /// A flash page with more data than fits in a page.
+    PageTooLarge(u8),
 }
 
 impl std::convert::From<std::io::Error> for LoadProgramError {
@@ -381,3 +950,251 @@ impl std::convert::From<tifiles::Error> for LoadProgramError {
         LoadProgramError::FileRead(other)
     }
 }
+
+impl std::convert::From<vat::Error> for LoadProgramError {
+    fn from(other: vat::Error) -> Self {
+        LoadProgramError::Vat(other)
+    }
+}
+
+impl std::convert::From<tifiles::Error> for LoadAppError {
+    fn from(other: tifiles::Error) -> Self {
+        LoadAppError::FileRead(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::include::tios;
+    use crate::tifiles::VariableType;
+
+    /// Get an emulator with enough of the OS to take interrupts and run BASIC
+    /// programs: the reset trap, an interrupt handler that acknowledges with
+    /// TI-OS's mask and the BASIC loop.
+    pub(crate) fn emulator() -> (Emulator, Z80) {
+        let mut emu = Emulator::new();
+        let page = emu.mem.flash_page_mut(0);
+        page[..4].copy_from_slice(&[0xED, 0x25, 0, 0]);
+        page[0x38..0x49].copy_from_slice(&[
+            0xF3, 0xED, 0x25, 3, 0, 0x08, 0xAF, 0xD3, 0x03, 0x3E, 0x0B, 0xD3, 0x03, 0x08, 0xFB,
+            0xED, 0x4D,
+        ]);
+        page[basic::BASIC_LOOP as usize..][..6].copy_from_slice(&[0xED, 0x25, 4, 0, 0x18, 0xFA]);
+        let mut cpu = Z80::new();
+        cpu.regs_mut().iy = tios::flags;
+        (emu, cpu)
+    }
+
+    /// Write code to RAM and start running it.
+    fn load_code(emu: &mut Emulator, cpu: &mut Z80, code: &[u8]) {
+        for (i, &b) in code.iter().enumerate() {
+            emu.mem[0x8000 + i as u16] = b;
+        }
+        cpu.regs_mut().pc = 0x8000;
+        cpu.regs_mut().sp = 0xFFF0;
+        emu.terminate.set(false);
+    }
+
+    /// Build an 8x* file containing a single variable.
+    fn var_file(ty: VariableType, name: &[u8], data: &[u8]) -> Vec<u8> {
+        let var = tifiles::Variable {
+            name: name.into(),
+            ty,
+            version: None,
+            flags: Some(0),
+            data: data.to_vec(),
+        };
+        let mut file = Vec::new();
+        tifiles::File::new(var).write_to(&mut file).unwrap();
+        file
+    }
+
+    /// Front-ends may run the emulator on a thread of its own, with or without a
+    /// link cable connected.
+    #[test]
+    fn emulator_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Emulator>();
+        assert_send::<link::CableEnd>();
+    }
+
+    #[test]
+    fn on_key_interrupts() {
+        let (mut emu, mut cpu) = emulator();
+        // im 1 \ ei \ jr $
+        load_code(&mut emu, &mut cpu, &[0xED, 0x56, 0xFB, 0x18, 0xFE]);
+        emu.run(&mut cpu, Duration::from_millis(1));
+        assert_eq!(emu.interrupt_controller.read_status_port() & 0x09, 0x08);
+
+        emu.key_down(keyboard::Key::On);
+        // Held, and the interrupt is pending
+        assert_eq!(emu.interrupt_controller.read_status_port() & 0x09, 0x01);
+        assert!(emu.interrupt_controller.irq_pending());
+        // The OS interrupt handler requests a break
+        emu.run(&mut cpu, Duration::from_millis(1));
+        assert!(bcalls::test_flag(
+            &emu,
+            &cpu,
+            tios::onFlags,
+            tios::onInterrupt
+        ));
+        assert!(!emu.interrupt_controller.irq_pending());
+
+        emu.key_up(keyboard::Key::On);
+        assert_eq!(emu.interrupt_controller.read_status_port() & 0x09, 0x08);
+
+        // ON isn't in the key matrix, so pressing it there does nothing
+        emu.keyboard.set_active_mask(0);
+        emu.keyboard.key_down(keyboard::Key::On);
+        assert_eq!(emu.keyboard.read(), 0xFF);
+        emu.keyboard.key_up(keyboard::Key::On);
+    }
+
+    #[test]
+    fn cpu_speed_switch() {
+        let (mut emu, mut cpu) = emulator();
+        // ld b, 100 \ djnz $ \ ld a, 1 \ out ($20), a \ jr $
+        load_code(
+            &mut emu,
+            &mut cpu,
+            &[0x06, 100, 0x10, 0xFE, 0x3E, 0x01, 0xD3, 0x20, 0x18, 0xFE],
+        );
+        let timer1 = emu.scheduler.scheduled(scheduler::Event::Timer1).unwrap();
+
+        // Cycles before the switch are counted at 6 MHz, and the CPU stops to
+        // reschedule events at the new rate.
+        let elapsed = emu.run(&mut cpu, Duration::from_millis(1));
+        assert_eq!(emu.clock_rate(), 15_000_000);
+        assert_eq!(emu.cycles, 1320);
+        assert!((elapsed.as_nanos() as i64 - 220_000).abs() <= 1);
+        assert_eq!(
+            emu.scheduler.scheduled(scheduler::Event::Timer1),
+            Some(1320 + (timer1 - 1320) * 15 / 6)
+        );
+
+        // Then time passes at 15 MHz
+        let elapsed = emu.run(&mut cpu, Duration::from_millis(1));
+        assert!((emu.cycles as i64 - 16_320).abs() < 12);
+        assert!((elapsed.as_secs_f64() - 0.001).abs() < 1e-6);
+    }
+
+    #[test]
+    fn execution_fault() {
+        // jp $C000, which is RAM page 0
+        let code = [0xC3, 0x00, 0xC0];
+        let (mut emu, mut cpu) = emulator();
+        load_code(&mut emu, &mut cpu, &code);
+        emu.setup_tios_context(&mut cpu);
+        emu.set_execution_fault(ExecutionFault::Stop);
+        emu.run(&mut cpu, Duration::from_millis(1));
+        assert!(!emu.is_running());
+        assert_eq!(cpu.regs().pc, 0xC000);
+
+        // By default the calculator resets
+        let (mut emu, mut cpu) = emulator();
+        load_code(&mut emu, &mut cpu, &code);
+        emu.setup_tios_context(&mut cpu);
+        emu.run(&mut cpu, Duration::from_millis(1));
+        assert_eq!(cpu.regs().pc, 0);
+
+        // The 83+ has no execution limits
+        let mut emu = Emulator::with_model(Model::TI83Plus);
+        let mut cpu = Z80::new();
+        load_code(&mut emu, &mut cpu, &code);
+        emu.setup_tios_context(&mut cpu);
+        emu.run(&mut cpu, Duration::from_millis(1));
+        assert!(emu.is_running());
+    }
+
+    #[test]
+    fn rst_findsym() {
+        let (mut emu, mut cpu) = emulator();
+        // trap _FindSym \ ret
+        emu.mem.flash_page_mut(0)[0x10..0x15].copy_from_slice(&[0xED, 0x25, 0xF4, 0x42, 0xC9]);
+        vat::clear(&mut emu.mem);
+        let entry = vat::insert(&mut emu.mem, VariableType::Real, b"A", &[0; 9]).unwrap();
+        emu.mem[tios::OP1..tios::OP1 + 3].copy_from_slice(b"\x00A\0");
+
+        // rst 10h \ jr $
+        load_code(&mut emu, &mut cpu, &[0xD7, 0x18, 0xFE]);
+        emu.run(&mut cpu, Duration::from_millis(1));
+        assert_eq!(cpu.regs().pc, 0x8001);
+        assert!(!cpu.flags().contains(Flags::C));
+        assert_eq!(cpu.regs().hl, entry.address);
+        assert_eq!(cpu.regs().de, entry.data);
+    }
+
+    #[test]
+    fn boot_os_upgrade() {
+        use tifiles::{FlashFile, FlashPage, FlashType};
+
+        let os = |ty, page| FlashFile {
+            version: (1, 0),
+            flags: 0,
+            object_type: 0x88,
+            date: [0; 4],
+            name: b"basecode"[..].into(),
+            device: 0x73,
+            ty,
+            os_header: None,
+            pages: vec![FlashPage {
+                page,
+                // ld a, $42 \ jr $
+                data: vec![0x3E, 0x42, 0x18, 0xFE],
+            }],
+            signature: None,
+        };
+
+        let mut emu = Emulator::with_os(os(FlashType::Os, 0), Model::TI83PlusSE).unwrap();
+        let mut cpu = Z80::new();
+        assert_eq!(emu.model(), Model::TI83PlusSE);
+        emu.run(&mut cpu, Duration::from_millis(1));
+        assert!(emu.is_running());
+        assert_eq!(cpu.regs().get_a(), 0x42);
+        assert_eq!(cpu.regs().pc, 2);
+
+        assert!(matches!(
+            Emulator::with_os(os(FlashType::App, 0), Model::TI83PlusSE),
+            Err(RomError::NotAnOs)
+        ));
+        assert!(matches!(
+            Emulator::with_os(os(FlashType::Os, 0x20), Model::TI83Plus),
+            Err(RomError::PageOutOfRange(0x20))
+        ));
+        let mut large = os(FlashType::Os, 1);
+        large.pages[0].data = vec![0; memory::PAGE_SIZE + 1];
+        assert!(matches!(
+            Emulator::with_os(large, Model::TI83Plus),
+            Err(RomError::PageTooLarge(1))
+        ));
+    }
+
+    #[test]
+    fn load_and_run_programs() {
+        let mut emu = Emulator::new();
+        let mut cpu = Z80::new();
+        let levels = var_file(VariableType::AppVar, b"LEVELS", &[1, 0, 0x42]);
+        emu.load_variable(&levels[..]).unwrap();
+        let prog = &[6, 0, 0xBB, 0x6D, 0, 0, 0, 0xC9];
+        emu.load_variable(&var_file(VariableType::Program, b"GAME", prog)[..])
+            .unwrap();
+        assert!(!emu.is_running());
+
+        emu.run_program(&mut cpu, b"GAME").unwrap();
+        assert!(emu.is_running());
+        assert_eq!(&emu.mem[tios::userMem..tios::userMem + 4], &[0, 0, 0, 0xC9]);
+        let levels = vat::find(&emu.mem, VariableType::AppVar, b"LEVELS").unwrap();
+        assert_eq!(levels.data, tios::userMem + 4);
+        assert_eq!(emu.mem[levels.data + 2], 0x42);
+
+        // Reloading replaces the variable, and running again replaces the running copy
+        let levels = var_file(VariableType::AppVar, b"LEVELS", &[1, 0, 0x43]);
+        emu.load_variable(&levels[..]).unwrap();
+        emu.run_program(&mut cpu, b"GAME").unwrap();
+        assert_eq!(vat::entries(&emu.mem).len(), 2);
+        let levels = vat::find(&emu.mem, VariableType::AppVar, b"LEVELS").unwrap();
+        assert_eq!(emu.mem[levels.data + 2], 0x43);
+        assert_eq!(emu.mem.read_u16(tios::FPS), tios::userMem + 4 + 8 + 3);
+    }
+}
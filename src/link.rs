@@ -0,0 +1,204 @@
+//! The link port and cables connecting link ports together.
+//!
+//! The link port has two lines, tip and ring. Each line is pulled high by a
+//! resistor and any device connected to it may pull it low, so a line reads as
+//! high only when nothing is driving it. Port 0 reads the state of the lines and
+//! writes whether the calculator drives each line low.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// The tip line, in bit 0 of port 0.
+pub const TIP: u8 = 0x01;
+/// The ring line, in bit 1 of port 0.
+pub const RING: u8 = 0x02;
+const LINES: u8 = TIP | RING;
+
+/// A cable that may be connected between two link ports.
+///
+/// Each end of the cable records which lines the device at that end is driving
+/// low, so the state of the lines can be computed from either end. The ends may
+/// be on different threads.
+#[derive(Debug, Default)]
+struct Cable {
+    driven: [AtomicU8; 2],
+}
+
+/// One end of a link cable.
+///
+/// Ends are created in pairs by [cable], and can be connected to an emulated link
+/// port with [LinkPort::connect] or used directly by other code that wants to talk
+/// to the calculator over the link port.
+#[derive(Debug)]
+pub struct CableEnd {
+    cable: Arc<Cable>,
+    index: usize,
+}
+
+/// Create a new link cable, returning both of its ends.
+pub fn cable() -> (CableEnd, CableEnd) {
+    let cable = Arc::new(Cable::default());
+    (
+        CableEnd {
+            cable: cable.clone(),
+            index: 0,
+        },
+        CableEnd { cable, index: 1 },
+    )
+}
+
+impl CableEnd {
+    /// Set which lines this end drives low, as a mask of [TIP] and [RING].
+    pub fn set_driven(&self, lines: u8) {
+        self.cable.driven[self.index].store(lines & LINES, Ordering::SeqCst);
+    }
+
+    /// Get which lines this end drives low.
+    pub fn driven(&self) -> u8 {
+        self.cable.driven[self.index].load(Ordering::SeqCst)
+    }
+
+    /// Get which lines the device at the other end drives low.
+    pub fn remote_driven(&self) -> u8 {
+        self.cable.driven[self.index ^ 1].load(Ordering::SeqCst)
+    }
+
+    /// Get the state of the lines, where set bits are lines that are high.
+    pub fn lines(&self) -> u8 {
+        !(self.driven() | self.remote_driven()) & LINES
+    }
+}
+
+impl Drop for CableEnd {
+    /// Stop driving the lines when disconnected, so the other end sees them pulled up.
+    fn drop(&mut self) {
+        self.set_driven(0);
+    }
+}
+
+/// The calculator's link port (port 0).
+///
+/// When read, bits 0 and 1 are the state of the tip and ring lines (set if high)
+/// and bits 4 and 5 are the values last written to bits 0 and 1. When written,
+/// setting bit 0 or 1 pulls the corresponding line low.
+///
+/// Changes to the lines made by the device at the other end of the cable are
+/// reported as link activity through [LinkPort::take_activity], which is a source
+/// of interrupts.
+#[derive(Debug, Default)]
+pub struct LinkPort {
+    /// Lines driven low by this port.
+    driven: u8,
+    cable: Option<CableEnd>,
+    /// Lines driven by the remote device when last checked for activity.
+    remote_seen: u8,
+}
+
+impl LinkPort {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Connect a cable to this port, replacing any that was already connected.
+    pub fn connect(&mut self, end: CableEnd) {
+        end.set_driven(self.driven);
+        self.remote_seen = end.remote_driven();
+        self.cable = Some(end);
+    }
+
+    /// Disconnect the cable from this port, returning it if one was connected.
+    pub fn disconnect(&mut self) -> Option<CableEnd> {
+        self.remote_seen = 0;
+        let end = self.cable.take();
+        if let Some(ref end) = end {
+            end.set_driven(0);
+        }
+        end
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.cable.is_some()
+    }
+
+    /// Get the state of the lines, where set bits are lines that are high.
+    pub fn lines(&self) -> u8 {
+        match self.cable {
+            Some(ref end) => end.lines(),
+            None => !self.driven & LINES,
+        }
+    }
+
+    pub fn read_port(&self) -> u8 {
+        self.lines() | (self.driven << 4)
+    }
+
+    pub fn write_port(&mut self, value: u8) {
+        self.driven = value & LINES;
+        trace!("Link port write {:02X}: lines={:02b}", value, self.lines());
+        if let Some(ref end) = self.cable {
+            end.set_driven(self.driven);
+        }
+    }
+
+    /// Return true if the remote device has changed the lines since the last call.
+    pub fn take_activity(&mut self) -> bool {
+        let remote = match self.cable {
+            Some(ref end) => end.remote_driven(),
+            None => 0,
+        };
+        let active = remote != self.remote_seen;
+        self.remote_seen = remote;
+        active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cable, LinkPort, RING, TIP};
+
+    #[test]
+    fn lines_are_pulled_up() {
+        let (a, b) = cable();
+        let mut port_a = LinkPort::new();
+        let mut port_b = LinkPort::new();
+        port_a.connect(a);
+        port_b.connect(b);
+        assert_eq!(port_a.read_port(), TIP | RING);
+        assert_eq!(port_b.read_port(), TIP | RING);
+
+        // Either end can pull a line low
+        port_a.write_port(TIP);
+        assert_eq!(port_a.read_port(), RING | (TIP << 4));
+        assert_eq!(port_b.read_port(), RING);
+        port_b.write_port(TIP | RING);
+        assert_eq!(port_a.lines(), 0);
+        port_a.write_port(0);
+        assert_eq!(port_a.lines(), 0);
+        assert_eq!(port_a.read_port(), 0);
+
+        // Disconnecting releases the other side
+        port_b.disconnect();
+        assert_eq!(port_a.lines(), TIP | RING);
+        assert_eq!(port_b.lines(), 0);
+    }
+
+    #[test]
+    fn remote_changes_are_activity() {
+        let (a, b) = cable();
+        let mut port = LinkPort::new();
+        port.connect(a);
+
+        // Local writes are not activity
+        port.write_port(TIP);
+        assert!(!port.take_activity());
+
+        b.set_driven(RING);
+        assert!(port.take_activity());
+        assert!(!port.take_activity());
+        assert_eq!(port.lines(), 0);
+
+        drop(b);
+        assert!(port.take_activity());
+        assert_eq!(port.lines(), RING);
+    }
+}
@@ -1,41 +1,174 @@
+use crate::{Model, RomError};
 use std::ops::Range;
 
-/// Number of flash pages that exist. Must be a power of two.
-const FLASH_PAGES: u8 = 0x20;
+/// Size of a single memory page, in bytes.
+pub const PAGE_SIZE: usize = 0x4000;
+
+/// A page of physical memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Page {
+    Flash(u8),
+    Ram(u8),
+}
+
+impl Page {
+    /// Get this page with the least significant bit of its number set or cleared.
+    ///
+    /// Memory mapping mode 1 maps a pair of pages through bank A by doing this.
+    fn with_low_bit(self, set: bool) -> Page {
+        let adjust = |n: u8| if set { n | 1 } else { n & !1 };
+        match self {
+            Page::Flash(n) => Page::Flash(adjust(n)),
+            Page::Ram(n) => Page::Ram(adjust(n)),
+        }
+    }
+}
+
+/// Memory mapping mode, selected by bit 0 of port 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingMode {
+    /// The usual mapping: flash page 0, bank A, bank B then RAM page 0 (or
+    /// the page selected by port 5).
+    Mode0,
+    /// Flash page 0, bank A with the low bit of its page cleared, bank A with
+    /// the low bit of its page set, then bank B.
+    Mode1,
+}
+
+/// State of the flash chip's command interface.
+///
+/// The flash chip is normally in read mode, and accepts AMD-style command
+/// sequences of writes to special addresses to program bytes or erase sectors.
+/// See [WikiTI](https://wikiti.brandonw.net/index.php?title=83Plus:Flash) for
+/// descriptions of the command sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlashCommand {
+    /// Reads return the contents of memory.
+    Read,
+    /// AA has been written to AAA.
+    Unlock1,
+    /// 55 has been written to 555 after the first unlock cycle.
+    Unlock2,
+    /// A0 has been written to AAA after unlocking; the next write programs a byte.
+    Program,
+    /// 80 has been written to AAA after unlocking; erase must be unlocked again.
+    EraseSetup,
+    /// AA has been written to AAA following erase setup.
+    EraseUnlock1,
+    /// 55 has been written to 555 following erase setup; the next write to a sector
+    /// with value 30 erases that sector.
+    EraseUnlock2,
+    /// An operation is in progress, and reads from flash return the status byte.
+    ///
+    /// Bit 6 of the status toggles on every read while busy, and the operation
+    /// completes once `polls` reads have been made. If `polls` is `None` the
+    /// operation has failed and the chip will remain busy until reset.
+    Busy { status: u8, polls: Option<u8> },
+}
+
+/// Number of status reads a byte program takes to complete.
+const FLASH_PROGRAM_POLLS: u8 = 1;
+/// Number of status reads a sector erase takes to complete.
+///
+/// Real erases take a very long time, but there's no benefit to making programs wait
+/// as long as they would on hardware.
+const FLASH_ERASE_POLLS: u8 = 16;
+/// Number of flash pages in an erasable sector.
+const FLASH_SECTOR_PAGES: u8 = 4;
 
 /// Emulator memory.
 ///
 /// This struct controls the memory map and access to various memories. It
 /// supports indexing with u16 to perform memory accesses, and range indexing
 /// as long as addresses do not span multiple memory banks.
+///
+/// The address space is divided into four 16k banks:
+///  * 0000-3FFF is always flash page 0
+///  * 4000-7FFF is bank A, selected by port 6
+///  * 8000-BFFF is bank B, selected by port 7
+///  * C000-FFFF is RAM; page 0 on the 83+, or selected by port 5 on later models
+///
+/// When memory mapping mode 1 is selected the layout changes; see [MappingMode].
+///
+/// Flash cannot be written directly, but when unlocked through port 0x14 writes to
+/// flash are interpreted as commands to the flash chip, which can program and erase it.
 pub struct Memory {
-    flash: Box<[[u8; 0x4000]]>,
-    ram: [u8; 0x8000],
-    // Page currently mapped into memory bank A
-    bank_a_page: u8,
+    model: Model,
+    flash: Box<[[u8; PAGE_SIZE]]>,
+    ram: Box<[[u8; PAGE_SIZE]]>,
+    mapping_mode: MappingMode,
+    bank_a: Page,
+    bank_b: Page,
+    /// RAM page selected by port 5.
+    bank_c_ram_page: u8,
+    /// If set, flash commands are accepted.
+    flash_unlocked: bool,
+    flash_command: FlashCommand,
+    /// Execution limits set by ports 0x22-0x26; see [Memory::is_executable].
+    exec_limits: [u8; 5],
 }
 
-const PAGE0_ADDRS: std::ops::Range<u16> = 0..0x4000;
-const BANKA_ADDRS: std::ops::Range<u16> = 0x4000..0x8000;
-const RAM_ADDRS: std::ops::RangeInclusive<u16> = 0x8000..=0xFFFF;
+/// First port controlling execution limits.
+const EXEC_LIMIT_PORT_BASE: u8 = 0x22;
+/// Size of the RAM blocks that execution limits are specified in.
+const RAM_EXEC_BLOCK_SIZE: usize = 0x400;
 
 impl Memory {
-    pub fn new<'i, I: 'i + IntoIterator<Item = &'i (u8, &'i [u8])>>(flash_pages: I) -> Self {
-        let mut flash: Box<_> = vec![[0u8; 0x4000]; FLASH_PAGES as usize].into_boxed_slice();
-        for (page, contents) in flash_pages {
-            flash[*page as usize][..contents.len()].copy_from_slice(contents);
+    /// Construct memory with the given contents of flash pages, leaving other
+    /// pages zeroed.
+    ///
+    /// Fails if a page doesn't exist on the model or its contents are larger
+    /// than a page.
+    pub fn new<'i, I: 'i + IntoIterator<Item = &'i (u8, &'i [u8])>>(
+        model: Model,
+        flash_pages: I,
+    ) -> Result<Self, RomError> {
+        let mut flash: Box<_> =
+            vec![[0u8; PAGE_SIZE]; model.flash_pages() as usize].into_boxed_slice();
+        for &(page, contents) in flash_pages {
+            let dst = flash
+                .get_mut(page as usize)
+                .ok_or(RomError::PageOutOfRange(page))?;
+            dst.get_mut(..contents.len())
+                .ok_or(RomError::PageTooLarge(page))?
+                .copy_from_slice(contents);
         }
+        Ok(Self::with_flash(model, flash))
+    }
+
+    /// Construct memory with the given flash contents, such as from [Memory::take_flash].
+    ///
+    /// Panics if the number of flash pages is incorrect for the model.
+    pub fn with_flash(model: Model, flash: Box<[[u8; PAGE_SIZE]]>) -> Self {
+        assert_eq!(
+            flash.len(),
+            model.flash_pages() as usize,
+            "Flash size is incorrect for {:?}",
+            model
+        );
 
         // Fill RAM with pseudo-random values
-        let mut ram = [0; 0x8000];
-        for (ram, value) in ram.iter_mut().zip((0u8..=0xFF).cycle()) {
-            *ram = value;
+        let mut ram: Box<_> = vec![[0u8; PAGE_SIZE]; model.ram_pages() as usize].into_boxed_slice();
+        for page in ram.iter_mut() {
+            for (ram, value) in page.iter_mut().zip((0u8..=0xFF).cycle()) {
+                *ram = value;
+            }
         }
 
         Memory {
+            model,
             flash,
             ram,
-            bank_a_page: 0,
+            mapping_mode: MappingMode::Mode0,
+            bank_a: Page::Flash(0),
+            // TI-OS runs with RAM page 1 in bank B and page 0 above it, making
+            // RAM appear contiguous from 8000 to FFFF.
+            bank_b: Page::Ram(1),
+            bank_c_ram_page: 0,
+            flash_unlocked: false,
+            flash_command: FlashCommand::Read,
+            // Nothing is protected until limits are set.
+            exec_limits: [0, 0xFF, 0, 0, 0xFF],
         }
     }
 
@@ -53,13 +186,13 @@ impl Memory {
     /// Read a byte from memory bank A, in the given page.
     ///
     /// The given address must be in bank A (0x4000-0x8000). The read byte
-    /// will come from the given memory page as if it were mapped into bank A.
+    /// will come from the given flash page as if it were mapped into bank A.
     pub fn read_paged(&self, page: u8, addr: u16) -> u8 {
         assert!(
-            BANKA_ADDRS.contains(&addr),
+            (0x4000..0x8000).contains(&addr),
             "Paged read must refer to addresses in memory bank A"
         );
-        self.flash[page as usize][(addr - BANKA_ADDRS.start) as usize]
+        self.flash[page as usize][addr as usize & (PAGE_SIZE - 1)]
     }
 
     /// Read a 16-bit value like [read_paged].
@@ -67,31 +200,320 @@ impl Memory {
         (self.read_paged(page, addr) as u16) | ((self.read_paged(page, addr + 1) as u16) << 8)
     }
 
+    /// Memory read as performed by the CPU.
+    ///
+    /// This differs from indexing in that reads from flash return the chip status
+    /// while a flash operation is in progress.
+    pub fn read(&mut self, addr: u16) -> u8 {
+        if let (Page::Flash(_), FlashCommand::Busy { status, polls }) =
+            (self.page_at(addr), self.flash_command)
+        {
+            // Toggle bit 6 on every read and complete the operation when polled enough
+            self.flash_command = match polls {
+                Some(0) | Some(1) => FlashCommand::Read,
+                Some(n) => FlashCommand::Busy {
+                    status: status ^ 0x40,
+                    polls: Some(n - 1),
+                },
+                None => FlashCommand::Busy {
+                    status: status ^ 0x40,
+                    polls: None,
+                },
+            };
+            return status;
+        }
+
+        self[addr]
+    }
+
     /// Checked memory write.
     ///
-    /// Fails if the given address refers to read-only memory.
+    /// Writes to flash are passed to the flash chip as commands if flash is
+    /// unlocked. Fails if the given address refers to read-only memory.
     pub fn put(&mut self, addr: u16, value: u8) -> Result<(), ()> {
-        if RAM_ADDRS.contains(&addr) {
-            self[addr] = value;
-            Ok(())
+        match self.page_at(addr) {
+            Page::Ram(_) => {
+                self[addr] = value;
+                Ok(())
+            }
+            Page::Flash(page) if self.flash_unlocked => {
+                self.flash_write(page, addr as usize & (PAGE_SIZE - 1), value);
+                Ok(())
+            }
+            Page::Flash(page) => {
+                warn!(
+                    "Ignored write of byte {:02X} to flash page {:02X} at {:04X}",
+                    value, page, addr
+                );
+                Err(())
+            }
+        }
+    }
+
+    /// Return whether flash is unlocked for writing (port 0x14 bit 0).
+    pub fn is_flash_unlocked(&self) -> bool {
+        self.flash_unlocked
+    }
+
+    /// Lock or unlock flash for writing (port 0x14 bit 0).
+    pub fn set_flash_unlocked(&mut self, unlocked: bool) {
+        trace!("Flash unlocked: {}", unlocked);
+        self.flash_unlocked = unlocked;
+        if !unlocked && self.flash_command != FlashCommand::Read {
+            debug!("Flash locked during command {:?}", self.flash_command);
+            self.flash_command = FlashCommand::Read;
+        }
+    }
+
+    /// Get the contents of a flash page, regardless of what is mapped.
+    pub fn flash_page(&self, page: u8) -> &[u8; PAGE_SIZE] {
+        &self.flash[page as usize]
+    }
+
+    /// Mutably access a flash page, bypassing the flash chip's command interface.
+    ///
+    /// This is meant for the emulator to install data in flash as if it had been
+    /// written by the OS.
+    pub fn flash_page_mut(&mut self, page: u8) -> &mut [u8; PAGE_SIZE] {
+        &mut self.flash[page as usize]
+    }
+
+    /// Take the contents of flash, leaving this memory with none.
+    ///
+    /// This is meant for moving flash to new memory with [Memory::with_flash].
+    pub fn take_flash(&mut self) -> Box<[[u8; PAGE_SIZE]]> {
+        std::mem::take(&mut self.flash)
+    }
+
+    /// Return whether code may be executed from the given address.
+    ///
+    /// Memory bank 0 is always executable. Elsewhere, code may be executed from
+    /// flash pages between the values of ports 0x22 and 0x23 inclusive, where bit 0
+    /// of port 0x24 is bit 8 of the upper limit. Code may be executed from RAM
+    /// in 1k blocks of physical RAM (where block 0x10 is the beginning of RAM page 1)
+    /// from the value of port 0x25 up to but not including the value of port 0x26.
+    ///
+    /// TI-OS allows RAM execution only on RAM page 1, which is why programs cannot
+    /// run code above C000 in the usual memory mapping.
+    pub fn is_executable(&self, addr: u16) -> bool {
+        let limits = &self.exec_limits;
+        match self.page_at(addr) {
+            _ if addr < PAGE_SIZE as u16 => true,
+            Page::Flash(n) => {
+                let upper = limits[1] as u16 | ((limits[2] as u16 & 1) << 8);
+                limits[0] <= n && n as u16 <= upper
+            }
+            Page::Ram(n) => {
+                let physical = n as usize * PAGE_SIZE + (addr as usize % PAGE_SIZE);
+                let block = physical / RAM_EXEC_BLOCK_SIZE;
+                limits[3] as usize <= block && block < limits[4] as usize
+            }
+        }
+    }
+
+    /// Read one of the execution limit ports, 0x22-0x26.
+    pub fn read_exec_limit_port(&self, port: u8) -> u8 {
+        self.exec_limits[(port - EXEC_LIMIT_PORT_BASE) as usize]
+    }
+
+    /// Write one of the execution limit ports, 0x22-0x26.
+    pub fn write_exec_limit_port(&mut self, port: u8, value: u8) {
+        debug!("Execution limit port {:02X} set to {:02X}", port, value);
+        self.exec_limits[(port - EXEC_LIMIT_PORT_BASE) as usize] = value;
+    }
+
+    /// Handle a write to the flash chip at the given page and offset within it.
+    fn flash_write(&mut self, page: u8, offset: usize, value: u8) {
+        use FlashCommand::*;
+
+        // The chip only decodes the low address bits for commands.
+        let cmd_addr = offset & 0xFFF;
+        trace!(
+            "Flash write {:02X} -> {:02X}:{:04X} in state {:?}",
+            value,
+            page,
+            offset,
+            self.flash_command
+        );
+
+        self.flash_command = match (self.flash_command, cmd_addr, value) {
+            (Read, 0xAAA, 0xAA) => Unlock1,
+            (Unlock1, 0x555, 0x55) => Unlock2,
+            (Unlock2, 0xAAA, 0xA0) => Program,
+            (Unlock2, 0xAAA, 0x80) => EraseSetup,
+            (EraseSetup, 0xAAA, 0xAA) => EraseUnlock1,
+            (EraseUnlock1, 0x555, 0x55) => EraseUnlock2,
+            (Program, _, _) => {
+                let byte = &mut self.flash[page as usize][offset];
+                if *byte & value != value {
+                    // Programming can only clear bits; trying to set them
+                    // fails and sets the timeout bit.
+                    warn!(
+                        "Flash program of {:02X} over {:02X} at {:02X}:{:04X} failed",
+                        value, *byte, page, offset
+                    );
+                    Busy {
+                        status: (!value & 0x80) | 0x20,
+                        polls: None,
+                    }
+                } else {
+                    *byte = value;
+                    Busy {
+                        status: !value & 0x80,
+                        polls: Some(FLASH_PROGRAM_POLLS),
+                    }
+                }
+            }
+            // Writing F0 anywhere resets to read mode, including from errors.
+            (_, _, 0xF0) => Read,
+            (Busy { .. }, _, _) => {
+                debug!("Ignored flash write while busy");
+                self.flash_command
+            }
+            (EraseUnlock2, _, 0x30) => {
+                let sector_start = page & !(FLASH_SECTOR_PAGES - 1);
+                debug!(
+                    "Erasing flash sector of pages {:02X}-{:02X}",
+                    sector_start,
+                    sector_start + FLASH_SECTOR_PAGES - 1
+                );
+                for page in self.flash[sector_start as usize..]
+                    .iter_mut()
+                    .take(FLASH_SECTOR_PAGES as usize)
+                {
+                    *page = [0xFF; PAGE_SIZE];
+                }
+                // DQ3 is set when the erase has begun
+                Busy {
+                    status: 0x08,
+                    polls: Some(FLASH_ERASE_POLLS),
+                }
+            }
+            (state, _, _) => {
+                if state != Read {
+                    warn!(
+                        "Invalid flash command sequence: wrote {:02X} to {:02X}:{:04X} in state {:?}",
+                        value, page, offset, state
+                    );
+                }
+                Read
+            }
+        };
+    }
+
+    /// Get the page that is currently mapped at the given address.
+    pub fn page_at(&self, addr: u16) -> Page {
+        match (addr >> 14, self.mapping_mode) {
+            (0, _) => Page::Flash(0),
+            (1, MappingMode::Mode0) => self.bank_a,
+            (1, MappingMode::Mode1) => self.bank_a.with_low_bit(false),
+            (2, MappingMode::Mode0) => self.bank_b,
+            (2, MappingMode::Mode1) => self.bank_a.with_low_bit(true),
+            (3, MappingMode::Mode0) => Page::Ram(self.bank_c_ram_page),
+            (3, MappingMode::Mode1) => self.bank_b,
+            _ => unreachable!(),
+        }
+    }
+
+    fn page_data(&self, page: Page) -> &[u8; PAGE_SIZE] {
+        match page {
+            Page::Flash(n) => &self.flash[n as usize],
+            Page::Ram(n) => &self.ram[n as usize],
+        }
+    }
+
+    fn page_data_mut(&mut self, page: Page) -> &mut [u8; PAGE_SIZE] {
+        match page {
+            Page::Flash(n) => &mut self.flash[n as usize],
+            Page::Ram(n) => &mut self.ram[n as usize],
+        }
+    }
+
+    /// Decode a value written to a bank select port into the selected page.
+    ///
+    /// The 83+ uses bit 6 to select RAM, whereas later models use bit 7 and
+    /// have more pages. Unimplemented page bits are ignored.
+    fn decode_page(&self, value: u8) -> Page {
+        let ram_bit = if self.model.is_se_hardware() {
+            0x80
         } else {
-            warn!(
-                "Ignored write of byte {:02X} to read-only memory at {:04X}",
-                value, addr
-            );
-            Err(())
+            0x40
+        };
+
+        if value & ram_bit != 0 {
+            Page::Ram(value & (self.model.ram_pages() - 1))
+        } else {
+            Page::Flash(value & (self.model.flash_pages() - 1))
         }
     }
 
-    /// Get the current page mapped into bank A.
+    /// Encode a page as it reads back from a bank select port.
+    fn encode_page(&self, page: Page) -> u8 {
+        match page {
+            Page::Flash(n) => n,
+            Page::Ram(n) if self.model.is_se_hardware() => 0x80 | n,
+            Page::Ram(n) => 0x40 | n,
+        }
+    }
+
+    /// Get the current page mapped into bank A, as read from port 6.
     pub fn get_bank_a_page(&self) -> u8 {
-        self.bank_a_page
+        self.encode_page(self.bank_a)
     }
 
-    /// Set the page mapped into bank A.
+    /// Set the page mapped into bank A, as written to port 6.
     pub fn set_bank_a_page(&mut self, value: u8) {
-        assert!(FLASH_PAGES.is_power_of_two());
-        self.bank_a_page = value & (FLASH_PAGES - 1)
+        self.bank_a = self.decode_page(value);
+    }
+
+    /// Get the current page mapped into bank B, as read from port 7.
+    pub fn get_bank_b_page(&self) -> u8 {
+        self.encode_page(self.bank_b)
+    }
+
+    /// Set the page mapped into bank B, as written to port 7.
+    pub fn set_bank_b_page(&mut self, value: u8) {
+        self.bank_b = self.decode_page(value);
+    }
+
+    /// Get the RAM page mapped at C000 in memory mapping mode 0 (port 5).
+    pub fn get_bank_c_page(&self) -> u8 {
+        self.bank_c_ram_page
+    }
+
+    /// Set the RAM page mapped at C000 in memory mapping mode 0 (port 5).
+    ///
+    /// Has no effect on the 83+, which always has RAM page 0 mapped there.
+    pub fn set_bank_c_page(&mut self, value: u8) {
+        if self.model.is_se_hardware() {
+            self.bank_c_ram_page = value & (self.model.ram_pages() - 1);
+        }
+    }
+
+    pub fn get_mapping_mode(&self) -> MappingMode {
+        self.mapping_mode
+    }
+
+    pub fn set_mapping_mode(&mut self, mode: MappingMode) {
+        trace!("Memory mapping mode {:?}", mode);
+        self.mapping_mode = mode;
+    }
+
+    /// Get the page and offset within it for a range of addresses, panicking if
+    /// the range spans multiple memory banks.
+    fn resolve_range(&self, index: &Range<u16>) -> (Page, Range<usize>) {
+        let bank_start = index.start as usize & !(PAGE_SIZE - 1);
+        if index.end < index.start || index.end as usize > bank_start + PAGE_SIZE {
+            panic!(
+                "Attempted slice indexing of unmapped memory or spanning memories: {:?}",
+                index
+            );
+        }
+
+        (
+            self.page_at(index.start),
+            (index.start as usize - bank_start)..(index.end as usize - bank_start),
+        )
     }
 }
 
@@ -101,15 +523,7 @@ impl std::ops::Index<u16> for Memory {
 
     #[inline]
     fn index(&self, index: u16) -> &u8 {
-        if PAGE0_ADDRS.contains(&index) {
-            &self.flash[0][(index - PAGE0_ADDRS.start) as usize]
-        } else if BANKA_ADDRS.contains(&index) {
-            &self.flash[self.bank_a_page as usize][(index - BANKA_ADDRS.start) as usize]
-        } else if RAM_ADDRS.contains(&index) {
-            &self.ram[(index - RAM_ADDRS.start()) as usize]
-        } else {
-            unreachable!();
-        }
+        &self.page_data(self.page_at(index))[index as usize & (PAGE_SIZE - 1)]
     }
 }
 
@@ -117,15 +531,8 @@ impl std::ops::Index<u16> for Memory {
 impl std::ops::IndexMut<u16> for Memory {
     #[inline]
     fn index_mut(&mut self, index: u16) -> &mut u8 {
-        if PAGE0_ADDRS.contains(&index) {
-            &mut self.flash[0][(index - PAGE0_ADDRS.start) as usize]
-        } else if BANKA_ADDRS.contains(&index) {
-            &mut self.flash[self.bank_a_page as usize][(index - BANKA_ADDRS.start) as usize]
-        } else if RAM_ADDRS.contains(&index) {
-            &mut self.ram[(index - RAM_ADDRS.start()) as usize]
-        } else {
-            unreachable!();
-        }
+        let page = self.page_at(index);
+        &mut self.page_data_mut(page)[index as usize & (PAGE_SIZE - 1)]
     }
 }
 
@@ -137,21 +544,8 @@ impl std::ops::Index<Range<u16>> for Memory {
     type Output = [u8];
 
     fn index(&self, index: Range<u16>) -> &[u8] {
-        if PAGE0_ADDRS.contains(&index.start) && PAGE0_ADDRS.contains(&index.end) {
-            &self.flash[0][(index.start - PAGE0_ADDRS.start) as usize
-                ..(index.end - PAGE0_ADDRS.start) as usize]
-        } else if BANKA_ADDRS.contains(&index.start) && BANKA_ADDRS.contains(&index.end) {
-            &self.flash[self.bank_a_page as usize][(index.start - BANKA_ADDRS.start) as usize
-                ..(index.end - BANKA_ADDRS.start) as usize]
-        } else if RAM_ADDRS.contains(&index.start) && RAM_ADDRS.contains(&index.end) {
-            &self.ram[(index.start - RAM_ADDRS.start()) as usize
-                ..(index.end - RAM_ADDRS.start()) as usize]
-        } else {
-            panic!(
-                "Attempted slice indexing of unmapped memory or spanning memories: {:?}",
-                index
-            );
-        }
+        let (page, range) = self.resolve_range(&index);
+        &self.page_data(page)[range]
     }
 }
 
@@ -161,20 +555,186 @@ impl std::ops::Index<Range<u16>> for Memory {
 /// cannot reliably be included in a single slice.
 impl std::ops::IndexMut<Range<u16>> for Memory {
     fn index_mut(&mut self, index: Range<u16>) -> &mut [u8] {
-        if PAGE0_ADDRS.contains(&index.start) && PAGE0_ADDRS.contains(&index.end) {
-            &mut self.flash[0][(index.start - PAGE0_ADDRS.start) as usize
-                ..(index.end - PAGE0_ADDRS.start) as usize]
-        } else if BANKA_ADDRS.contains(&index.start) && BANKA_ADDRS.contains(&index.end) {
-            &mut self.flash[self.bank_a_page as usize][(index.start - BANKA_ADDRS.start) as usize
-                ..(index.end - BANKA_ADDRS.start) as usize]
-        } else if RAM_ADDRS.contains(&index.start) && RAM_ADDRS.contains(&index.end) {
-            &mut self.ram[(index.start - RAM_ADDRS.start()) as usize
-                ..(index.end - RAM_ADDRS.start()) as usize]
-        } else {
-            panic!(
-                "Attempted slice indexing of unmapped memory or spanning memories: {:?}",
-                index
-            );
+        let (page, range) = self.resolve_range(&index);
+        &mut self.page_data_mut(page)[range]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MappingMode, Memory, Page};
+    use crate::{Model, RomError};
+
+    #[test]
+    fn default_ram_is_contiguous() {
+        let mut mem = Memory::new(Model::TI84PlusSE, &[]).unwrap();
+        assert_eq!(mem.page_at(0x8000), Page::Ram(1));
+        assert_eq!(mem.page_at(0xC000), Page::Ram(0));
+
+        mem.put(0xBFFF, 0x12).unwrap();
+        mem.put(0xC000, 0x34).unwrap();
+        assert_eq!(mem.ram[1][0x3FFF], 0x12);
+        assert_eq!(mem.ram[0][0], 0x34);
+        assert!(mem.put(0x4000, 0).is_err());
+    }
+
+    #[test]
+    fn bank_ports_select_ram() {
+        let mut mem = Memory::new(Model::TI84PlusSE, &[]).unwrap();
+        mem.set_bank_a_page(0x83);
+        mem.set_bank_b_page(0x1F);
+        mem.set_bank_c_page(5);
+
+        assert_eq!(mem.page_at(0x4000), Page::Ram(3));
+        assert_eq!(mem.page_at(0x8000), Page::Flash(0x1F));
+        assert_eq!(mem.page_at(0xC000), Page::Ram(5));
+        assert_eq!(mem.get_bank_a_page(), 0x83);
+        assert_eq!(mem.get_bank_b_page(), 0x1F);
+
+        // Writes go to the mapped RAM page
+        mem[0x4001] = 0xAA;
+        assert_eq!(mem.ram[3][1], 0xAA);
+    }
+
+    #[test]
+    fn ti83plus_pages() {
+        let mut mem = Memory::new(Model::TI83Plus, &[]).unwrap();
+        mem.set_bank_a_page(0x41);
+        assert_eq!(mem.page_at(0x4000), Page::Ram(1));
+        assert_eq!(mem.get_bank_a_page(), 0x41);
+
+        // Flash page numbers wrap, and port 5 doesn't exist
+        mem.set_bank_a_page(0x25);
+        assert_eq!(mem.page_at(0x4000), Page::Flash(5));
+        mem.set_bank_c_page(1);
+        assert_eq!(mem.page_at(0xC000), Page::Ram(0));
+    }
+
+    #[test]
+    fn mapping_mode_1() {
+        let mut mem = Memory::new(Model::TI83PlusSE, &[]).unwrap();
+        mem.set_mapping_mode(MappingMode::Mode1);
+        mem.set_bank_a_page(0x83);
+        mem.set_bank_b_page(0x80);
+
+        assert_eq!(mem.page_at(0x0000), Page::Flash(0));
+        assert_eq!(mem.page_at(0x4000), Page::Ram(2));
+        assert_eq!(mem.page_at(0x8000), Page::Ram(3));
+        assert_eq!(mem.page_at(0xC000), Page::Ram(0));
+    }
+
+    fn unlocked_flash() -> Memory {
+        let mut mem = Memory::new(Model::TI84PlusSE, &[]).unwrap();
+        mem.set_flash_unlocked(true);
+        mem.set_bank_a_page(0x10);
+        mem
+    }
+
+    fn flash_command(mem: &mut Memory, command: &[(u16, u8)]) {
+        for &(addr, value) in command {
+            mem.put(addr, value).unwrap();
         }
     }
+
+    #[test]
+    fn flash_locked_ignores_writes() {
+        let mut mem = Memory::new(Model::TI84PlusSE, &[]).unwrap();
+        assert!(mem.put(0x4AAA, 0xAA).is_err());
+        assert_eq!(mem.flash_command, super::FlashCommand::Read);
+    }
+
+    #[test]
+    fn flash_program() {
+        let mut mem = unlocked_flash();
+        mem.flash[0x10][0x123] = 0xFF;
+        flash_command(&mut mem, &[(0x4AAA, 0xAA), (0x4555, 0x55), (0x4AAA, 0xA0)]);
+        mem.put(0x4123, 0x5A).unwrap();
+
+        // Status reads return the complement of bit 7, then completes.
+        assert_eq!(mem.read(0x4123) & 0x80, 0x80);
+        assert_eq!(mem.read(0x4123), 0x5A);
+    }
+
+    #[test]
+    fn flash_program_cannot_set_bits() {
+        let mut mem = unlocked_flash();
+        mem.flash[0x10][0] = 0x0F;
+        flash_command(&mut mem, &[(0x4AAA, 0xAA), (0x4555, 0x55), (0x4AAA, 0xA0)]);
+        mem.put(0x4000, 0x30).unwrap();
+
+        // Stays busy with the error bit set until reset
+        let first = mem.read(0x4000);
+        let second = mem.read(0x4000);
+        assert_eq!(first & 0x20, 0x20);
+        assert_eq!(first ^ second, 0x40);
+
+        mem.put(0x4000, 0xF0).unwrap();
+        assert_eq!(mem.read(0x4000), 0x0F);
+    }
+
+    #[test]
+    fn flash_sector_erase() {
+        let mut mem = unlocked_flash();
+        mem.flash[0x11][0x1000] = 0;
+        mem.flash[0x14][0] = 0;
+        flash_command(
+            &mut mem,
+            &[
+                (0x4AAA, 0xAA),
+                (0x4555, 0x55),
+                (0x4AAA, 0x80),
+                (0x4AAA, 0xAA),
+                (0x4555, 0x55),
+                (0x4000, 0x30),
+            ],
+        );
+
+        // Poll until complete, checking the toggle bit
+        let mut last = mem.read(0x4000);
+        let mut polls = 1;
+        while last != 0xFF {
+            let status = mem.read(0x4000);
+            if status != 0xFF {
+                assert_eq!(status ^ last, 0x40);
+            }
+            last = status;
+            polls += 1;
+            assert!(polls < 100, "Erase should complete");
+        }
+
+        // Pages 10-13 are one sector; 14 is in the next.
+        assert_eq!(mem.flash[0x11][0x1000], 0xFF);
+        assert_eq!(mem.flash[0x14][0], 0);
+    }
+
+    #[test]
+    fn execution_limits() {
+        let mut mem = Memory::new(Model::TI84PlusSE, &[]).unwrap();
+        assert!(mem.is_executable(0xC000));
+
+        // TI-OS limits: only RAM page 1
+        mem.write_exec_limit_port(0x25, 0x10);
+        mem.write_exec_limit_port(0x26, 0x20);
+        assert!(mem.is_executable(0x8000));
+        assert!(mem.is_executable(0xBFFF));
+        assert!(!mem.is_executable(0xC000));
+
+        // Flash pages 2-3 only, except bank 0
+        mem.write_exec_limit_port(0x22, 2);
+        mem.write_exec_limit_port(0x23, 3);
+        assert!(mem.is_executable(0x0000));
+        mem.set_bank_a_page(3);
+        assert!(mem.is_executable(0x4000));
+        mem.set_bank_a_page(4);
+        assert!(!mem.is_executable(0x4000));
+        mem.write_exec_limit_port(0x24, 1);
+        assert!(mem.is_executable(0x4000));
+    }
+
+    #[test]
+    #[should_panic]
+    fn range_spanning_banks_panics() {
+        let mem = Memory::new(Model::TI84PlusSE, &[]).unwrap();
+        let _ = &mem[0xBFF0..0xC010];
+    }
 }
@@ -0,0 +1,57 @@
+//! Calculator models and their hardware differences.
+
+/// A calculator model.
+///
+/// The models differ mostly in how much memory they have and which ports are
+/// implemented; see [WikiTI](https://wikiti.brandonw.net/index.php?title=83Plus:Ports)
+/// for details of which hardware is present on each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Model {
+    TI83Plus,
+    TI83PlusSE,
+    TI84Plus,
+    #[default]
+    TI84PlusSE,
+}
+
+impl Model {
+    /// Get the number of 16k flash pages present.
+    pub fn flash_pages(&self) -> u8 {
+        match *self {
+            Model::TI83Plus => 0x20,
+            Model::TI84Plus => 0x40,
+            Model::TI83PlusSE | Model::TI84PlusSE => 0x80,
+        }
+    }
+
+    /// Get the number of 16k RAM pages present.
+    pub fn ram_pages(&self) -> u8 {
+        match *self {
+            Model::TI83Plus => 2,
+            _ => 8,
+        }
+    }
+
+    /// Return true if this model has the hardware added with the 83+ SE.
+    ///
+    /// This includes the port 5 RAM page select, memory bank B able to map any
+    /// page and the crystal timers.
+    pub fn is_se_hardware(&self) -> bool {
+        *self != Model::TI83Plus
+    }
+}
+
+impl std::str::FromStr for Model {
+    type Err = ();
+
+    /// Parse a model's short name, like `83p` or `84pse`.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "83p" => Model::TI83Plus,
+            "83pse" => Model::TI83PlusSE,
+            "84p" => Model::TI84Plus,
+            "84pse" => Model::TI84PlusSE,
+            _ => return Err(()),
+        })
+    }
+}
@@ -0,0 +1,119 @@
+//! Scheduling of timed hardware events.
+//!
+//! Time is measured in CPU cycles since the emulator was reset, so events occur
+//! at exactly the same point in a program's execution regardless of how the
+//! emulator is run. The emulator runs the CPU until the next scheduled event and
+//! then dispatches it to the device that scheduled it, which may schedule it
+//! again (periodic timers, for instance).
+
+/// Something that happens at a scheduled time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Hardware timer 1 fires.
+    Timer1,
+    /// Hardware timer 2 fires.
+    Timer2,
+    /// The LCD driver finishes an access and is no longer busy.
+    LcdReady,
+    /// The device at the other end of the link cable changed the lines.
+    LinkActivity,
+    /// One of the crystal timers expires, numbered from 0.
+    CrystalTimer(u8),
+}
+
+impl Event {
+    const COUNT: usize = 7;
+
+    fn index(self) -> usize {
+        match self {
+            Event::Timer1 => 0,
+            Event::Timer2 => 1,
+            Event::LcdReady => 2,
+            Event::LinkActivity => 3,
+            Event::CrystalTimer(n) => 4 + n as usize,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => Event::Timer1,
+            1 => Event::Timer2,
+            2 => Event::LcdReady,
+            3 => Event::LinkActivity,
+            n => Event::CrystalTimer((n - 4) as u8),
+        }
+    }
+}
+
+/// Tracks when each [Event] will next occur.
+///
+/// Each kind of event may be scheduled at most once; scheduling an event that is
+/// already scheduled replaces the earlier time.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    events: [Option<u64>; Event::COUNT],
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Schedule an event to occur at the given cycle.
+    pub fn schedule(&mut self, event: Event, at: u64) {
+        trace!("Schedule {:?} at cycle {}", event, at);
+        self.events[event.index()] = Some(at);
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        self.events[event.index()] = None;
+    }
+
+    /// Get the cycle at which an event is scheduled to occur, if it is.
+    pub fn scheduled(&self, event: Event) -> Option<u64> {
+        self.events[event.index()]
+    }
+
+    /// Get the cycle at which the next event will occur, if any are scheduled.
+    pub fn next(&self) -> Option<u64> {
+        self.events.iter().flatten().copied().min()
+    }
+
+    /// Remove the earliest event scheduled to occur at or before `now`,
+    /// returning it and the cycle at which it was scheduled.
+    pub fn pop_due(&mut self, now: u64) -> Option<(Event, u64)> {
+        let (index, at) = self
+            .events
+            .iter()
+            .enumerate()
+            .filter_map(|(i, at)| at.map(|at| (i, at)))
+            .filter(|&(_, at)| at <= now)
+            .min_by_key(|&(_, at)| at)?;
+
+        self.events[index] = None;
+        Some((Event::from_index(index), at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Scheduler};
+
+    #[test]
+    fn events_pop_in_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Event::Timer1, 100);
+        scheduler.schedule(Event::CrystalTimer(2), 50);
+        scheduler.schedule(Event::Timer2, 300);
+        scheduler.schedule(Event::Timer2, 200);
+        assert_eq!(scheduler.next(), Some(50));
+
+        assert_eq!(scheduler.pop_due(10), None);
+        assert_eq!(scheduler.pop_due(150), Some((Event::CrystalTimer(2), 50)));
+        assert_eq!(scheduler.pop_due(150), Some((Event::Timer1, 100)));
+        assert_eq!(scheduler.pop_due(150), None);
+
+        scheduler.cancel(Event::Timer2);
+        assert_eq!(scheduler.next(), None);
+    }
+}
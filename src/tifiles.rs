@@ -1,7 +1,9 @@
 use num_traits::FromPrimitive;
-use std::io::{Error as IoError, Read, Result as IoResult};
+use std::convert::TryInto;
+use std::io::{Error as IoError, Read, Result as IoResult, Write};
 
 use super::checksum::ChecksumRead;
+use crate::float::{Complex, Real, REAL_TYPE};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -32,7 +34,7 @@ pub struct Variable {
     pub data: Vec<u8>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
 pub enum VariableType {
     /// RealObj
     Real = 0,
@@ -77,11 +79,29 @@ pub enum VariableType {
     Group = 0x17,
 }
 
+/// The value of a numeric variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Real(Real),
+    Complex(Complex),
+    List(Vec<Real>),
+    ComplexList(Vec<Complex>),
+    /// A matrix as a list of rows.
+    Matrix(Vec<Vec<Real>>),
+}
+
+const FILE_SIGNATURE: &[u8; 11] = b"**TI83F*\x1a\x0a\x00";
+
+/// The t2ByteTok, tAsmCmp tokens that begin an assembly program.
+pub const ASM_PROGRAM_SIGNATURE: &[u8] = b"\xbb\x6d";
+/// The t2ByteTok, tAsmPrgm tokens that begin an unsquished assembly program.
+pub const UNSQUISHED_PROGRAM_SIGNATURE: &[u8] = b"\xbb\x6c";
+
 impl File {
     pub fn read_from<R: Read>(mut src: R) -> Result<File> {
         let mut buf = [0u8; 11];
         src.read_exact(&mut buf)?;
-        if &buf != b"**TI83F*\x1a\x0a\x00" {
+        if &buf != FILE_SIGNATURE {
             return Err(Error::Invalid("Invalid signature"));
         }
 
@@ -112,6 +132,30 @@ impl File {
             var,
         })
     }
+
+    /// Create a file containing a variable, with a default comment.
+    pub fn new(var: Variable) -> File {
+        File {
+            comment: b"Created by tihle"[..].into(),
+            var,
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, mut dst: W) -> IoResult<()> {
+        let mut var = Vec::new();
+        self.var.write_to(&mut var)?;
+
+        let mut comment = [0u8; 42];
+        let comment_len = std::cmp::min(self.comment.len(), comment.len());
+        comment[..comment_len].copy_from_slice(&self.comment[..comment_len]);
+
+        dst.write_all(FILE_SIGNATURE)?;
+        dst.write_all(&comment)?;
+        dst.write_all(&(var.len() as u16).to_le_bytes())?;
+        dst.write_all(&var)?;
+        let sum = var.iter().fold(0u16, |a, &x| a.wrapping_add(x as u16));
+        dst.write_all(&sum.to_le_bytes())
+    }
 }
 
 impl Variable {
@@ -125,10 +169,16 @@ impl Variable {
 
         let mut raw_name = [0u8; 8];
         src.read_exact(&mut raw_name)?;
-        let name_len = raw_name
+        // Names of lists, matrices and the like are a two-byte token, the second
+        // byte of which may be zero (L₁ is 5D 00).
+        let start = match raw_name[0] {
+            0x5C..=0x5E | 0x60..=0x63 | 0xAA => 2,
+            _ => 0,
+        };
+        let name_len = raw_name[start..]
             .iter()
             .position(|&x| x == 0)
-            .unwrap_or(raw_name.len());
+            .map_or(raw_name.len(), |n| start + n);
         let name: Box<[u8]> = raw_name[..name_len].into();
 
         let (version, flags) = match hdr_len {
@@ -154,28 +204,214 @@ impl Variable {
         })
     }
 
+    fn write_to<W: Write>(&self, mut dst: W) -> IoResult<()> {
+        let hdr_len: u16 = match (self.version, self.flags) {
+            (None, None) => 11,
+            _ => 13,
+        };
+        let mut raw_name = [0u8; 8];
+        let name_len = std::cmp::min(self.name.len(), raw_name.len());
+        raw_name[..name_len].copy_from_slice(&self.name[..name_len]);
+
+        dst.write_all(&hdr_len.to_le_bytes())?;
+        dst.write_all(&(self.data.len() as u16).to_le_bytes())?;
+        dst.write_all(&[self.ty as u8])?;
+        dst.write_all(&raw_name)?;
+        if hdr_len == 13 {
+            dst.write_all(&[self.version.unwrap_or(0), self.flags.unwrap_or(0)])?;
+        }
+        dst.write_all(&(self.data.len() as u16).to_le_bytes())?;
+        dst.write_all(&self.data)
+    }
+
+    /// Get the number of bytes at the beginning of this variable's data that
+    /// specify its size, which is every type except reals and complex numbers.
+    /// The size is a length in bytes or elements except for matrices, which
+    /// have their number of columns and rows.
+    fn size_len(&self) -> usize {
+        match self.ty {
+            VariableType::Real | VariableType::Complex => 0,
+            _ => 2,
+        }
+    }
+
     /// Get the on-calculator contents of a variable.
     ///
     /// Many types use the first two bytes of data to indicate the size, and those
     /// bytes are included in the variable data here. This function gets only the bytes
     /// that are actual data, not the size bytes.
     pub fn calc_data(&self) -> &[u8] {
-        if self.ty != VariableType::Program || self.ty != VariableType::ProtectedProgram {
-            unimplemented!("Only programs are currently supported for data");
+        &self.data[self.size_len()..]
+    }
+
+    pub fn calc_data_mut(&mut self) -> &mut [u8] {
+        let start = self.size_len();
+        &mut self.data[start..]
+    }
+
+    /// Decode the value of a numeric variable.
+    pub fn value(&self) -> Result<Value> {
+        fn reals(data: &[u8]) -> Vec<Real> {
+            data.chunks_exact(9)
+                .map(|x| Real::from_bytes(x.try_into().unwrap()))
+                .collect()
         }
+        fn complexes(data: &[u8]) -> Vec<Complex> {
+            data.chunks_exact(18)
+                .map(|x| Complex::from_bytes(x.try_into().unwrap()))
+                .collect()
+        }
+        let count = || match self.data.get(..2) {
+            Some(&[lo, hi]) => Ok(u16::from_le_bytes([lo, hi]) as usize),
+            _ => Err(Error::Invalid("Variable data is truncated")),
+        };
+        let check_len = |expected: usize| {
+            if self.data.len() == expected {
+                Ok(())
+            } else {
+                Err(Error::Invalid("Variable data has the wrong length"))
+            }
+        };
 
-        &self.data[2..]
+        Ok(match self.ty {
+            VariableType::Real => {
+                check_len(9)?;
+                Value::Real(reals(&self.data)[0])
+            }
+            VariableType::Complex => {
+                check_len(18)?;
+                Value::Complex(complexes(&self.data)[0])
+            }
+            VariableType::List => {
+                check_len(2 + 9 * count()?)?;
+                Value::List(reals(&self.data[2..]))
+            }
+            VariableType::ComplexList => {
+                check_len(2 + 18 * count()?)?;
+                Value::ComplexList(complexes(&self.data[2..]))
+            }
+            VariableType::Matrix => {
+                count()?;
+                let (cols, rows) = (self.data[0] as usize, self.data[1] as usize);
+                check_len(2 + 9 * cols * rows)?;
+                let elements = reals(&self.data[2..]);
+                Value::Matrix(elements.chunks(cols.max(1)).map(Vec::from).collect())
+            }
+            _ => return Err(Error::Invalid("Variable is not numeric")),
+        })
     }
 
-    pub fn calc_data_mut(&mut self) -> &mut [u8] {
+    /// Create a variable holding a numeric value.
+    ///
+    /// The name must be valid for the type of value: a letter or θ for a number,
+    /// `5D xx` for a list and `5C xx` for a matrix, or Ans for any of them.
+    /// Matrix rows must all be the same length, and lists and matrices must be
+    /// within the calculator's size limits.
+    pub fn from_value(name: &[u8], value: &Value) -> Result<Variable> {
+        if !is_valid_name(value, name) {
+            return Err(Error::Invalid("Name is not valid for the type of value"));
+        }
+        let mut data = Vec::new();
+        let ty = match value {
+            Value::Real(x) => {
+                data.extend_from_slice(&x.to_bytes(REAL_TYPE));
+                VariableType::Real
+            }
+            Value::Complex(z) => {
+                data.extend_from_slice(&z.to_bytes());
+                VariableType::Complex
+            }
+            Value::List(xs) => {
+                if xs.is_empty() || xs.len() > 999 {
+                    return Err(Error::Invalid("Lists must have 1 to 999 elements"));
+                }
+                data.extend_from_slice(&(xs.len() as u16).to_le_bytes());
+                for x in xs {
+                    data.extend_from_slice(&x.to_bytes(REAL_TYPE));
+                }
+                VariableType::List
+            }
+            Value::ComplexList(zs) => {
+                if zs.is_empty() || zs.len() > 999 {
+                    return Err(Error::Invalid("Lists must have 1 to 999 elements"));
+                }
+                data.extend_from_slice(&(zs.len() as u16).to_le_bytes());
+                for z in zs {
+                    data.extend_from_slice(&z.to_bytes());
+                }
+                VariableType::ComplexList
+            }
+            Value::Matrix(rows) => {
+                let cols = rows.first().map_or(0, Vec::len);
+                if !(1..=99).contains(&rows.len()) || !(1..=99).contains(&cols) {
+                    return Err(Error::Invalid(
+                        "Matrices must have 1 to 99 rows and columns",
+                    ));
+                }
+                if rows.iter().any(|row| row.len() != cols) {
+                    return Err(Error::Invalid("Matrix rows differ in length"));
+                }
+                data.extend_from_slice(&[cols as u8, rows.len() as u8]);
+                for x in rows.iter().flatten() {
+                    data.extend_from_slice(&x.to_bytes(REAL_TYPE));
+                }
+                VariableType::Matrix
+            }
+        };
+
+        Ok(Variable {
+            name: name.into(),
+            ty,
+            version: Some(0),
+            flags: Some(0),
+            data,
+        })
+    }
+
+    /// If this variable is an unsquished assembly program, convert it to the
+    /// squished form that is executed and return whether it was unsquished.
+    ///
+    /// Unsquished programs begin with the AsmPrgm token, followed by machine code
+    /// as lines of hex digits. The code ends at an `End` token, so anything after
+    /// it such as the `0000` and `End` lines that TI-83 programs finish with is
+    /// ignored.
+    pub fn squish(&mut self) -> Result<bool> {
         if self.ty != VariableType::Program && self.ty != VariableType::ProtectedProgram {
-            unimplemented!(
-                "Only programs are currently supported for data, not {:?}",
-                self.ty
-            );
+            return Ok(false);
+        }
+        let text = match self.calc_data().strip_prefix(UNSQUISHED_PROGRAM_SIGNATURE) {
+            Some(text) => text,
+            None => return Ok(false),
+        };
+        let text = match text.iter().position(|&b| b == 0xD4) {
+            Some(end) => &text[..end],
+            None => text,
+        };
+
+        // Digit and letter tokens have the same values as in ASCII
+        let digits = text
+            .iter()
+            .filter(|&&b| b != 0x3F)
+            .map(|&b| match b {
+                b'0'..=b'9' => Ok(b - b'0'),
+                b'A'..=b'F' => Ok(b - b'A' + 10),
+                _ => Err(Error::Invalid(
+                    "Unsquished program contains tokens other than hex digits",
+                )),
+            })
+            .collect::<Result<Vec<u8>>>()?;
+        if digits.len() % 2 == 1 {
+            return Err(Error::Invalid(
+                "Unsquished program has an odd number of hex digits",
+            ));
         }
 
-        &mut self.data[2..]
+        let code_len = 2 + digits.len() / 2;
+        let mut data = (code_len as u16).to_le_bytes().to_vec();
+        data.extend_from_slice(ASM_PROGRAM_SIGNATURE);
+        data.extend(digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+        self.data = data;
+        Ok(true)
     }
 
     /// If this variable is an Ion program, patch it to execute as if it were nostub
@@ -240,6 +476,288 @@ impl Variable {
     }
 }
 
+/// Token of the Ans variable, which is also its name.
+const ANS_NAME: u8 = 0x72;
+
+/// Check whether a name can hold a numeric value.
+///
+/// Lists are L₁-L₆ (`5D 00`-`5D 05`) or user-named with up to five letters and
+/// digits after the `5D`, and matrices are [A]-[J] (`5C 00`-`5C 09`).
+fn is_valid_name(value: &Value, name: &[u8]) -> bool {
+    let is_letter = |c: &u8| (b'A'..=0x5B).contains(c);
+    match (value, name) {
+        (_, [ANS_NAME]) => true,
+        (Value::Real(_), [c]) | (Value::Complex(_), [c]) => is_letter(c),
+        (Value::List(_), [0x5D, rest @ ..]) | (Value::ComplexList(_), [0x5D, rest @ ..]) => {
+            match rest {
+                [n] if *n <= 5 => true,
+                [first, others @ ..] => {
+                    is_letter(first)
+                        && others.len() < 5
+                        && others.iter().all(|c| is_letter(c) || c.is_ascii_digit())
+                }
+                [] => false,
+            }
+        }
+        (Value::Matrix(_), [0x5C, n]) => *n <= 9,
+        _ => false,
+    }
+}
+
+/// A flash file (8xk, 8xu and similar), beginning with the `**TIFL**` signature.
+///
+/// The data in a flash file is Intel HEX text, where extended segment address
+/// records (type 02) select the flash page that following data records are for.
+/// The data is divided into sections by end of file records: OS upgrades have an
+/// OS header, the OS pages and a signature, while apps have only pages (which
+/// include the app's signature at the end) and possibly a separate signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlashFile {
+    /// Major and minor version numbers.
+    pub version: (u8, u8),
+    pub flags: u8,
+    pub object_type: u8,
+    /// Date as BCD day, month and (two-byte) year.
+    pub date: [u8; 4],
+    pub name: Box<[u8]>,
+    /// Target device ID; 0x73 for the 83+ and 84+.
+    pub device: u8,
+    pub ty: FlashType,
+    /// The header sent before an OS's pages, for OS upgrades.
+    pub os_header: Option<Vec<u8>>,
+    pub pages: Vec<FlashPage>,
+    pub signature: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum FlashType {
+    Os = 0x23,
+    App = 0x24,
+    Certificate = 0x25,
+    License = 0x3E,
+}
+
+/// The contents of a single flash page.
+///
+/// For apps page numbers are relative to the first page of the app, whereas for
+/// an OS they are the actual pages the data is written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlashPage {
+    pub page: u8,
+    /// Data beginning at the start of the page, not necessarily the entire page.
+    pub data: Vec<u8>,
+}
+
+const FLASH_SIGNATURE: &[u8; 8] = b"**TIFL**";
+/// Maximum number of data bytes written in a single HEX record.
+const HEX_RECORD_SIZE: usize = 0x20;
+
+impl FlashFile {
+    pub fn read_from<R: Read>(mut src: R) -> Result<FlashFile> {
+        let mut header = [0u8; 0x4E];
+        src.read_exact(&mut header)?;
+        if &header[..8] != FLASH_SIGNATURE {
+            return Err(Error::Invalid("Invalid signature"));
+        }
+
+        let name_len = std::cmp::min(header[0x10] as usize, 8);
+        let ty = match FlashType::from_u8(header[0x31]) {
+            None => return Err(Error::Invalid("Unrecognized flash data type")),
+            Some(t) => t,
+        };
+        let data_len = u32::from_le_bytes([header[0x4A], header[0x4B], header[0x4C], header[0x4D]]);
+
+        let mut hex = vec![0u8; data_len as usize];
+        src.read_exact(&mut hex)?;
+        let mut sections = read_hex(&hex)?.into_iter();
+
+        let os_header = if ty == FlashType::Os && sections.len() >= 3 {
+            sections.next().map(flatten_pages)
+        } else {
+            None
+        };
+        let pages = sections.next().unwrap_or_default();
+        let signature = sections.next().map(flatten_pages);
+        if sections.next().is_some() {
+            return Err(Error::Invalid("Too many sections in flash data"));
+        }
+
+        Ok(FlashFile {
+            version: (header[8], header[9]),
+            flags: header[0xA],
+            object_type: header[0xB],
+            date: [header[0xC], header[0xD], header[0xE], header[0xF]],
+            name: header[0x11..0x11 + name_len].into(),
+            device: header[0x30],
+            ty,
+            os_header,
+            pages,
+            signature,
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, mut dst: W) -> IoResult<()> {
+        let mut hex = Vec::new();
+        if let Some(ref os_header) = self.os_header {
+            write_hex_section(
+                &mut hex,
+                &[FlashPage {
+                    page: 0,
+                    data: os_header.clone(),
+                }],
+                None,
+            );
+        }
+        // OS page 0 is mapped at 0000, but everything else is in bank A.
+        let os_page0 = self.ty == FlashType::Os;
+        write_hex_section(&mut hex, &self.pages, Some(os_page0));
+        if let Some(ref signature) = self.signature {
+            write_hex_section(
+                &mut hex,
+                &[FlashPage {
+                    page: 0,
+                    data: signature.clone(),
+                }],
+                None,
+            );
+        }
+
+        let mut header = [0u8; 0x4E];
+        header[..8].copy_from_slice(FLASH_SIGNATURE);
+        header[8] = self.version.0;
+        header[9] = self.version.1;
+        header[0xA] = self.flags;
+        header[0xB] = self.object_type;
+        header[0xC..0x10].copy_from_slice(&self.date);
+        header[0x10] = self.name.len() as u8;
+        header[0x11..0x11 + self.name.len()].copy_from_slice(&self.name);
+        header[0x30] = self.device;
+        header[0x31] = self.ty as u8;
+        header[0x4A..].copy_from_slice(&(hex.len() as u32).to_le_bytes());
+
+        dst.write_all(&header)?;
+        dst.write_all(&hex)
+    }
+}
+
+/// Parse Intel HEX data, returning the pages in each section.
+fn read_hex(hex: &[u8]) -> Result<Vec<Vec<FlashPage>>> {
+    let mut sections = vec![];
+    let mut pages: Vec<FlashPage> = vec![];
+    let mut page = 0u8;
+
+    for line in hex.split(|&c| c == b'\n') {
+        let start = line
+            .iter()
+            .position(|c| !c.is_ascii_whitespace())
+            .unwrap_or(line.len());
+        let end = line
+            .iter()
+            .rposition(|c| !c.is_ascii_whitespace())
+            .map_or(start, |i| i + 1);
+        let line = &line[start..end];
+        if line.is_empty() {
+            continue;
+        }
+        if line[0] != b':' || line.len() % 2 != 1 {
+            return Err(Error::Invalid("Malformed HEX record"));
+        }
+        let record = line[1..]
+            .chunks(2)
+            .map(|digits| {
+                std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|s| u8::from_str_radix(s, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(Error::Invalid("Malformed HEX record"))?;
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(Error::Invalid("HEX record length mismatch"));
+        }
+        if record.iter().fold(0u8, |a, &x| a.wrapping_add(x)) != 0 {
+            return Err(Error::Invalid("Incorrect HEX record checksum"));
+        }
+
+        let address = u16::from_be_bytes([record[1], record[2]]) as usize;
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            // Data
+            0 => {
+                let offset = address % 0x4000;
+                if offset + data.len() > 0x4000 {
+                    return Err(Error::Invalid("HEX record extends past the end of a page"));
+                }
+                if pages.last().map(|p| p.page) != Some(page) {
+                    pages.push(FlashPage { page, data: vec![] });
+                }
+                let page_data = &mut pages.last_mut().unwrap().data;
+                if page_data.len() < offset + data.len() {
+                    page_data.resize(offset + data.len(), 0xFF);
+                }
+                page_data[offset..offset + data.len()].copy_from_slice(data);
+            }
+            // End of file
+            1 => {
+                sections.push(std::mem::take(&mut pages));
+                page = 0;
+            }
+            // Extended segment address, used as the page number
+            2 if data.len() == 2 => page = data[1],
+            _ => return Err(Error::Invalid("Unsupported HEX record")),
+        }
+    }
+
+    if !pages.is_empty() {
+        return Err(Error::Invalid("HEX data missing end of file record"));
+    }
+    Ok(sections)
+}
+
+/// Concatenate the data of pages, for sections that aren't really flash pages.
+fn flatten_pages(pages: Vec<FlashPage>) -> Vec<u8> {
+    pages.into_iter().flat_map(|p| p.data).collect()
+}
+
+/// Write a section of Intel HEX data.
+///
+/// If `os_page0` is `None` data is written as-is from address 0 without page
+/// numbers. Otherwise each page is preceded by its page number and written to
+/// bank A, except that page 0 is written at address 0 if `os_page0` is true.
+fn write_hex_section(out: &mut Vec<u8>, pages: &[FlashPage], os_page0: Option<bool>) {
+    fn record(out: &mut Vec<u8>, address: u16, ty: u8, data: &[u8]) {
+        let mut bytes = vec![data.len() as u8];
+        bytes.extend_from_slice(&address.to_be_bytes());
+        bytes.push(ty);
+        bytes.extend_from_slice(data);
+        let sum = bytes.iter().fold(0u8, |a, &x| a.wrapping_add(x));
+        bytes.push(sum.wrapping_neg());
+
+        out.push(b':');
+        for b in bytes {
+            out.extend_from_slice(format!("{:02X}", b).as_bytes());
+        }
+        out.extend_from_slice(b"\r\n");
+    }
+
+    for page in pages {
+        let base = match os_page0 {
+            None => 0,
+            Some(_) => {
+                record(out, 0, 2, &[0, page.page]);
+                if os_page0 == Some(true) && page.page == 0 {
+                    0
+                } else {
+                    0x4000
+                }
+            }
+        };
+        for (i, chunk) in page.data.chunks(HEX_RECORD_SIZE).enumerate() {
+            record(out, (base + i * HEX_RECORD_SIZE) as u16, 0, chunk);
+        }
+    }
+    record(out, 0, 1, &[]);
+}
+
 fn read_u8<R: Read>(mut src: R) -> IoResult<u8> {
     let mut buf = [0u8; 1];
     src.read_exact(&mut buf[..])?;
@@ -256,7 +774,8 @@ fn read_u16<R: Read>(mut src: R) -> IoResult<u16> {
 
 #[cfg(test)]
 mod tests {
-    use super::{File, Variable, VariableType};
+    use super::{Error, File, FlashFile, FlashPage, FlashType, Value, Variable, VariableType};
+    use crate::float::{Complex, Real};
 
     #[test]
     fn read_8xp() {
@@ -287,4 +806,220 @@ mod tests {
             }
         );
     }
+
+    fn real(s: &str) -> Real {
+        s.parse().unwrap()
+    }
+
+    /// Write a numeric variable to a file and read it back, returning the file.
+    fn value_roundtrip(name: &[u8], value: Value) -> Vec<u8> {
+        let var = Variable::from_value(name, &value).expect("Value should be valid");
+        let mut bytes = vec![];
+        File::new(var.clone()).write_to(&mut bytes).unwrap();
+
+        let file = File::read_from(&bytes[..]).expect("Should read back written file");
+        assert_eq!(file.var, var);
+        assert_eq!(file.var.value().unwrap(), value);
+        bytes
+    }
+
+    #[test]
+    fn numeric_roundtrip() {
+        let bytes = value_roundtrip(b"A", Value::Real(real("-1.25")));
+        assert_eq!(bytes[0x3B], 0);
+        assert_eq!(&bytes[0x48..0x51], b"\x80\x80\x12\x50\0\0\0\0\0");
+
+        let z = Complex {
+            re: real("1"),
+            im: real("-0.5"),
+        };
+        value_roundtrip(b"Z", Value::Complex(z));
+        value_roundtrip(
+            b"\x5D\x00",
+            Value::List(vec![real("1"), real("2"), real("3E50")]),
+        );
+        value_roundtrip(
+            b"\x5DLIST1",
+            Value::ComplexList(vec![z, Complex::default()]),
+        );
+
+        let bytes = value_roundtrip(
+            b"\x5C\x00",
+            Value::Matrix(vec![
+                vec![real("1"), real("2"), real("3")],
+                vec![real("4"), real("5"), real("6")],
+            ]),
+        );
+        // Three columns and two rows, stored by row
+        assert_eq!(&bytes[0x48..0x4A], &[3, 2]);
+        assert_eq!(bytes[0x4A + 9 + 2], 0x20);
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(Variable::from_value(b"\x5D\x00", &Value::List(vec![])).is_err());
+        assert!(Variable::from_value(
+            b"\x5C\x00",
+            &Value::Matrix(vec![vec![real("1")], vec![real("1"), real("2")]])
+        )
+        .is_err());
+
+        let one = || Value::List(vec![real("1")]);
+        // Lists need list names, and the name of a user list starts with a letter
+        assert!(Variable::from_value(b"A", &one()).is_err());
+        assert!(Variable::from_value(b"\x5D\x06", &one()).is_err());
+        assert!(Variable::from_value(b"\x5D1", &one()).is_err());
+        assert!(Variable::from_value(b"\x5DLONGER", &one()).is_err());
+        assert!(Variable::from_value(b"\x5C\x00", &one()).is_err());
+        assert!(Variable::from_value(b"\x5D\x00", &Value::Real(real("1"))).is_err());
+        assert!(Variable::from_value(b"\x72", &one()).is_ok());
+
+        let mut var = Variable::from_value(b"\x5D\x00", &one()).unwrap();
+        var.data.pop();
+        assert!(matches!(var.value(), Err(Error::Invalid(_))));
+        var.ty = VariableType::AppVar;
+        assert!(matches!(var.value(), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn calc_data() {
+        let var = Variable::from_value(b"A", &Value::Real(real("2"))).unwrap();
+        assert_eq!(var.calc_data(), &var.data[..]);
+        let var = Variable {
+            name: b"PROG"[..].into(),
+            ty: VariableType::Program,
+            version: None,
+            flags: None,
+            data: vec![1, 0, 0xC9],
+        };
+        assert_eq!(var.calc_data(), &[0xC9]);
+    }
+
+    #[test]
+    fn squish() {
+        let program = |text: &[u8]| {
+            let mut data = (text.len() as u16 + 2).to_le_bytes().to_vec();
+            data.extend_from_slice(b"\xbb\x6c");
+            data.extend_from_slice(text);
+            Variable {
+                name: b"HEX"[..].into(),
+                ty: VariableType::Program,
+                version: None,
+                flags: None,
+                data,
+            }
+        };
+
+        let mut var = program(b"3E2A\x3fC9\x3f\xd4\x3f0000\x3f\xd4");
+        assert!(var.squish().unwrap());
+        assert_eq!(var.data, [5, 0, 0xBB, 0x6D, 0x3E, 0x2A, 0xC9]);
+        // Already squished
+        assert!(!var.squish().unwrap());
+
+        assert!(matches!(program(b"3E2").squish(), Err(Error::Invalid(_))));
+        assert!(matches!(program(b"3G").squish(), Err(Error::Invalid(_))));
+    }
+
+    fn flash_file(ty: FlashType, os_header: Option<Vec<u8>>) -> FlashFile {
+        FlashFile {
+            version: (1, 2),
+            flags: 0,
+            object_type: 0x88,
+            date: [0x01, 0x02, 0x20, 0x20],
+            name: b"TESTAPP"[..].into(),
+            device: 0x73,
+            ty,
+            os_header,
+            pages: vec![
+                FlashPage {
+                    page: 0,
+                    data: (0..0x4000).map(|i| i as u8).collect(),
+                },
+                FlashPage {
+                    page: 1,
+                    data: vec![0xC9; 0x45],
+                },
+            ],
+            signature: Some(vec![0x02, 0x0D, 0x40, 0xA1, 0x6B]),
+        }
+    }
+
+    fn roundtrip(file: &FlashFile) -> Vec<u8> {
+        let mut bytes = vec![];
+        file.write_to(&mut bytes).expect("Writing should not fail");
+        assert_eq!(
+            &FlashFile::read_from(&bytes[..]).expect("Should read back written file"),
+            file
+        );
+        bytes
+    }
+
+    #[test]
+    fn flash_app_roundtrip() {
+        let bytes = roundtrip(&flash_file(FlashType::App, None));
+        assert_eq!(&bytes[..8], b"**TIFL**");
+        assert_eq!(bytes[0x31], 0x24);
+        // Page 0 of an app is written to bank A
+        assert_eq!(&bytes[0x4E..0x4E + 17], b":020000020000FC\r\n");
+        assert!(bytes[0x4E + 17..].starts_with(b":20400000000102"));
+    }
+
+    #[test]
+    fn flash_os_roundtrip() {
+        let bytes = roundtrip(&flash_file(
+            FlashType::Os,
+            Some(vec![0x80, 0x0F, 0, 0, 0, 0]),
+        ));
+        // OS header comes first at address 0, then OS page 0 at address 0
+        assert!(bytes[0x4E..].starts_with(b":06000000800F00000000"));
+        let page0 = b":020000020000FC\r\n:20000000000102";
+        assert!(bytes.windows(page0.len()).any(|w| w == page0));
+    }
+
+    #[test]
+    fn flash_bad_checksum() {
+        let mut bytes = vec![];
+        flash_file(FlashType::App, None)
+            .write_to(&mut bytes)
+            .unwrap();
+        // Corrupt the checksum of the page record
+        bytes[0x4E + 13] = b'0';
+        assert!(matches!(
+            FlashFile::read_from(&bytes[..]),
+            Err(Error::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn flash_record_past_end_of_page() {
+        // An app with one 32-byte data record in bank A
+        let file_with_record = |address: u16| {
+            let mut record = vec![0x20, (address >> 8) as u8, address as u8, 0];
+            record.extend_from_slice(&[0xC9; 0x20]);
+            record.push(record.iter().fold(0u8, |a, &x| a.wrapping_sub(x)));
+            let mut hex = b":020000020000FC\r\n:".to_vec();
+            hex.extend(
+                record
+                    .iter()
+                    .flat_map(|b| format!("{:02X}", b).into_bytes()),
+            );
+            hex.extend_from_slice(b"\r\n:00000001FF\r\n");
+
+            let file = FlashFile {
+                pages: vec![],
+                signature: None,
+                ..flash_file(FlashType::App, None)
+            };
+            let mut bytes = vec![];
+            file.write_to(&mut bytes).unwrap();
+            bytes.truncate(0x4E);
+            bytes[0x4A..0x4E].copy_from_slice(&(hex.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&hex);
+            FlashFile::read_from(&bytes[..])
+        };
+
+        let file = file_with_record(0x7FE0).expect("Record ending at the end of the page is valid");
+        assert_eq!(file.pages[0].data.len(), 0x4000);
+        assert!(matches!(file_with_record(0x7FF0), Err(Error::Invalid(_))));
+    }
 }
@@ -16,15 +16,122 @@ pub enum Trap {
     RomCall = 1,
     RomCallReturn = 2,
     OsInterrupt = 3,
+    /// Run a statement of the running BASIC program.
+    BasicStep = 4,
 
     DivHLBy10 = 0x400F,
+    Trunc = 0x4060,
+    Times2 = 0x4066,
+    Plus1 = 0x4069,
+    Minus1 = 0x406C,
+    FPSub = 0x406F,
+    FPAdd = 0x4072,
+    FPSquare = 0x4081,
+    FPMult = 0x4084,
+    InvOP1S = 0x408D,
+    InvOP2S = 0x4090,
+    Frac = 0x4093,
+    FPRecip = 0x4096,
+    FPDiv = 0x4099,
+    Int = 0x40A5,
+    CkOP1Real = 0x40FF,
+    CpOP1OP2 = 0x4111,
+    OP3ToOP4 = 0x4114,
+    OP1ToOP4 = 0x4117,
+    OP2ToOP4 = 0x411A,
+    OP4ToOP2 = 0x411D,
+    OP1ToOP3 = 0x4123,
+    OP5ToOP2 = 0x4126,
+    OP5ToOP6 = 0x4129,
+    OP5ToOP4 = 0x412C,
+    OP1ToOP2 = 0x412F,
+    OP6ToOP2 = 0x4132,
+    OP6ToOP1 = 0x4135,
+    OP4ToOP1 = 0x4138,
+    OP5ToOP1 = 0x413B,
+    OP3ToOP1 = 0x413E,
+    OP6ToOP5 = 0x4141,
+    OP4ToOP5 = 0x4144,
+    OP3ToOP5 = 0x4147,
+    OP2ToOP5 = 0x414A,
+    OP2ToOP6 = 0x414D,
+    OP1ToOP6 = 0x4150,
+    OP1ToOP5 = 0x4153,
+    OP2ToOP1 = 0x4156,
+    Mov11B = 0x4159,
+    Mov10B = 0x415C,
+    Mov9B = 0x415F,
+    OP2ToOP3 = 0x416E,
+    OP4ToOP3 = 0x4171,
+    OP5ToOP3 = 0x4174,
+    OP4ToOP6 = 0x4177,
+    Mov9ToOP1 = 0x417A,
+    Mov9ToOP2 = 0x4180,
+    MovFrOP1 = 0x4183,
+    OP4Set1 = 0x4186,
+    OP3Set1 = 0x4189,
+    OP2Set4 = 0x4195,
+    OP2Set3 = 0x4198,
+    OP1Set1 = 0x419B,
+    OP1Set4 = 0x419E,
+    OP1Set3 = 0x41A1,
+    OP3Set2 = 0x41A4,
+    OP1Set2 = 0x41A7,
+    OP2Set2 = 0x41AA,
+    OP2Set1 = 0x41AD,
+    OP5Set0 = 0x41B3,
+    OP4Set0 = 0x41B6,
+    OP3Set0 = 0x41B9,
+    OP2Set0 = 0x41BC,
+    OP1Set0 = 0x41BF,
+    ZeroOP1 = 0x41C5,
+    ZeroOP2 = 0x41C8,
+    ZeroOP3 = 0x41CB,
+    OP2ExOP6 = 0x4207,
+    OP5ExOP6 = 0x420A,
+    OP1ExOP5 = 0x420D,
+    OP1ExOP6 = 0x4210,
+    OP2ExOP4 = 0x4213,
+    OP2ExOP5 = 0x4216,
+    OP1ExOP3 = 0x4219,
+    OP1ExOP4 = 0x421C,
+    OP1ExOP2 = 0x421F,
+    CkOP1FP0 = 0x4228,
+    CkOP1Pos = 0x4258,
+    ChkFindSym = 0x42F1,
+    FindSym = 0x42F4,
+    PopOP5 = 0x4378,
+    PopOP3 = 0x437B,
+    PopOP1 = 0x437E,
+    PopRealO6 = 0x4381,
+    PopRealO5 = 0x4384,
+    PopRealO4 = 0x4387,
+    PopRealO3 = 0x438A,
+    PopRealO2 = 0x438D,
+    PopRealO1 = 0x4390,
+    PopReal = 0x4393,
+    PushRealO6 = 0x43AB,
+    PushRealO5 = 0x43AE,
+    PushRealO4 = 0x43B1,
+    PushRealO3 = 0x43B4,
+    PushRealO2 = 0x43B7,
+    PushRealO1 = 0x43BA,
+    PushReal = 0x43BD,
+    PushOP5 = 0x43C0,
+    PushOP3 = 0x43C3,
+    PushOP1 = 0x43C9,
     PutMap = 0x4501,
     PutC = 0x4504,
     DispHL = 0x4507,
     ClrLCDFull = 0x4540,
     HomeUp = 0x4558,
     VPutMap = 0x455e,
+    SetXXOP1 = 0x478C,
+    SetXXOP2 = 0x478F,
+    SetXXXXOP2 = 0x4792,
+    Mov18B = 0x47DA,
     GrBufCpy = 0x4860,
+    ConvOP1 = 0x4AEF,
     MemSet = 0x4C33,
 
     PrintCpuState = 0xFFFF,
@@ -47,7 +154,10 @@ impl Trap {
             OsInterrupt => {
                 // The OS interrupt does a few things which we don't implement right now:
                 //  * Run indicator
-                //  * Set onInterrupt,(onFlags) if ON is pressed
+                if emu.interrupt_controller.read_status_port() & 1 != 0 {
+                    // ON interrupt: request a break
+                    set_flag(emu, core, tios::onFlags, tios::onInterrupt);
+                }
                 if test_flag(emu, core, tios::indicFlags, tios::indicOnly) {
                     // Stop if only supposed to animate the run indicator
                     return 200;
@@ -63,14 +173,121 @@ impl Trap {
                 400 // :shrug:
             }
 
+            BasicStep => crate::basic::step(emu, core),
+
             DivHLBy10 => bcalls::util::DivHLBy10(core),
+            Trunc => bcalls::float::Trunc(emu),
+            Times2 => bcalls::float::Times2(emu),
+            Plus1 => bcalls::float::OP1PlusN(emu, 1),
+            Minus1 => bcalls::float::OP1PlusN(emu, -1),
+            FPSub => bcalls::float::FPSub(emu),
+            FPAdd => bcalls::float::FPAdd(emu),
+            FPSquare => bcalls::float::FPSquare(emu),
+            FPMult => bcalls::float::FPMult(emu),
+            InvOP1S => bcalls::float::InvOPxS(emu, 1),
+            InvOP2S => bcalls::float::InvOPxS(emu, 2),
+            Frac => bcalls::float::Frac(emu),
+            FPRecip => bcalls::float::FPRecip(emu),
+            FPDiv => bcalls::float::FPDiv(emu),
+            Int => bcalls::float::Int(emu),
+            CkOP1Real => bcalls::float::CkOP1Real(emu, core),
+            CpOP1OP2 => bcalls::float::CpOP1OP2(emu, core),
+            OP3ToOP4 => bcalls::float::OPxToOPy(emu, 3, 4),
+            OP1ToOP4 => bcalls::float::OPxToOPy(emu, 1, 4),
+            OP2ToOP4 => bcalls::float::OPxToOPy(emu, 2, 4),
+            OP4ToOP2 => bcalls::float::OPxToOPy(emu, 4, 2),
+            OP1ToOP3 => bcalls::float::OPxToOPy(emu, 1, 3),
+            OP5ToOP2 => bcalls::float::OPxToOPy(emu, 5, 2),
+            OP5ToOP6 => bcalls::float::OPxToOPy(emu, 5, 6),
+            OP5ToOP4 => bcalls::float::OPxToOPy(emu, 5, 4),
+            OP1ToOP2 => bcalls::float::OPxToOPy(emu, 1, 2),
+            OP6ToOP2 => bcalls::float::OPxToOPy(emu, 6, 2),
+            OP6ToOP1 => bcalls::float::OPxToOPy(emu, 6, 1),
+            OP4ToOP1 => bcalls::float::OPxToOPy(emu, 4, 1),
+            OP5ToOP1 => bcalls::float::OPxToOPy(emu, 5, 1),
+            OP3ToOP1 => bcalls::float::OPxToOPy(emu, 3, 1),
+            OP6ToOP5 => bcalls::float::OPxToOPy(emu, 6, 5),
+            OP4ToOP5 => bcalls::float::OPxToOPy(emu, 4, 5),
+            OP3ToOP5 => bcalls::float::OPxToOPy(emu, 3, 5),
+            OP2ToOP5 => bcalls::float::OPxToOPy(emu, 2, 5),
+            OP2ToOP6 => bcalls::float::OPxToOPy(emu, 2, 6),
+            OP1ToOP6 => bcalls::float::OPxToOPy(emu, 1, 6),
+            OP1ToOP5 => bcalls::float::OPxToOPy(emu, 1, 5),
+            OP2ToOP1 => bcalls::float::OPxToOPy(emu, 2, 1),
+            Mov11B => bcalls::float::MovNB(emu, core, 11),
+            Mov10B => bcalls::float::MovNB(emu, core, 10),
+            Mov9B => bcalls::float::MovNB(emu, core, 9),
+            OP2ToOP3 => bcalls::float::OPxToOPy(emu, 2, 3),
+            OP4ToOP3 => bcalls::float::OPxToOPy(emu, 4, 3),
+            OP5ToOP3 => bcalls::float::OPxToOPy(emu, 5, 3),
+            OP4ToOP6 => bcalls::float::OPxToOPy(emu, 4, 6),
+            Mov9ToOP1 => bcalls::float::Mov9ToOPx(emu, core, 1),
+            Mov9ToOP2 => bcalls::float::Mov9ToOPx(emu, core, 2),
+            MovFrOP1 => bcalls::float::MovFrOP1(emu, core),
+            OP4Set1 => bcalls::float::OPxSet(emu, 4, 1),
+            OP3Set1 => bcalls::float::OPxSet(emu, 3, 1),
+            OP2Set4 => bcalls::float::OPxSet(emu, 2, 4),
+            OP2Set3 => bcalls::float::OPxSet(emu, 2, 3),
+            OP1Set1 => bcalls::float::OPxSet(emu, 1, 1),
+            OP1Set4 => bcalls::float::OPxSet(emu, 1, 4),
+            OP1Set3 => bcalls::float::OPxSet(emu, 1, 3),
+            OP3Set2 => bcalls::float::OPxSet(emu, 3, 2),
+            OP1Set2 => bcalls::float::OPxSet(emu, 1, 2),
+            OP2Set2 => bcalls::float::OPxSet(emu, 2, 2),
+            OP2Set1 => bcalls::float::OPxSet(emu, 2, 1),
+            OP5Set0 => bcalls::float::OPxSet(emu, 5, 0),
+            OP4Set0 => bcalls::float::OPxSet(emu, 4, 0),
+            OP3Set0 => bcalls::float::OPxSet(emu, 3, 0),
+            OP2Set0 => bcalls::float::OPxSet(emu, 2, 0),
+            OP1Set0 => bcalls::float::OPxSet(emu, 1, 0),
+            ZeroOP1 => bcalls::float::ZeroOPx(emu, 1),
+            ZeroOP2 => bcalls::float::ZeroOPx(emu, 2),
+            ZeroOP3 => bcalls::float::ZeroOPx(emu, 3),
+            OP2ExOP6 => bcalls::float::OPxExOPy(emu, 2, 6),
+            OP5ExOP6 => bcalls::float::OPxExOPy(emu, 5, 6),
+            OP1ExOP5 => bcalls::float::OPxExOPy(emu, 1, 5),
+            OP1ExOP6 => bcalls::float::OPxExOPy(emu, 1, 6),
+            OP2ExOP4 => bcalls::float::OPxExOPy(emu, 2, 4),
+            OP2ExOP5 => bcalls::float::OPxExOPy(emu, 2, 5),
+            OP1ExOP3 => bcalls::float::OPxExOPy(emu, 1, 3),
+            OP1ExOP4 => bcalls::float::OPxExOPy(emu, 1, 4),
+            OP1ExOP2 => bcalls::float::OPxExOPy(emu, 1, 2),
+            CkOP1FP0 => bcalls::float::CkOP1FP0(emu, core),
+            CkOP1Pos => bcalls::float::CkOP1Pos(emu, core),
+            ChkFindSym => bcalls::symbols::ChkFindSym(emu, core),
+            FindSym => bcalls::symbols::FindSym(emu, core),
+            PopOP5 => bcalls::float::PopOPx(emu, 5),
+            PopOP3 => bcalls::float::PopOPx(emu, 3),
+            PopOP1 => bcalls::float::PopOPx(emu, 1),
+            PopRealO6 => bcalls::float::PopRealOx(emu, 6),
+            PopRealO5 => bcalls::float::PopRealOx(emu, 5),
+            PopRealO4 => bcalls::float::PopRealOx(emu, 4),
+            PopRealO3 => bcalls::float::PopRealOx(emu, 3),
+            PopRealO2 => bcalls::float::PopRealOx(emu, 2),
+            PopRealO1 => bcalls::float::PopRealOx(emu, 1),
+            PopReal => bcalls::float::PopReal(emu, core),
+            PushRealO6 => bcalls::float::PushRealOx(emu, 6),
+            PushRealO5 => bcalls::float::PushRealOx(emu, 5),
+            PushRealO4 => bcalls::float::PushRealOx(emu, 4),
+            PushRealO3 => bcalls::float::PushRealOx(emu, 3),
+            PushRealO2 => bcalls::float::PushRealOx(emu, 2),
+            PushRealO1 => bcalls::float::PushRealOx(emu, 1),
+            PushReal => bcalls::float::PushReal(emu, core),
+            PushOP5 => bcalls::float::PushOPx(emu, 5),
+            PushOP3 => bcalls::float::PushOPx(emu, 3),
+            PushOP1 => bcalls::float::PushOPx(emu, 1),
             PutMap => bcalls::display::PutMap(emu, core),
             PutC => bcalls::display::PutC(emu, core),
             DispHL => bcalls::display::DispHL(emu, core),
             ClrLCDFull => bcalls::display::ClrLCDFull(emu),
             HomeUp => bcalls::display::HomeUp(emu),
             VPutMap => bcalls::display::VPutMap(emu, core),
+            SetXXOP1 => bcalls::float::SetXXOPx(emu, core, 1),
+            SetXXOP2 => bcalls::float::SetXXOPx(emu, core, 2),
+            SetXXXXOP2 => bcalls::float::SetXXXXOP2(emu, core),
+            Mov18B => bcalls::float::MovNB(emu, core, 18),
             GrBufCpy => bcalls::display::GrBufCpy(emu),
+            ConvOP1 => bcalls::float::ConvOP1(emu, core),
             MemSet => bcalls::memory::MemSet(emu, core),
             PrintCpuState => {
                 info!("{:#?}", core.regs());
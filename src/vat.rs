@@ -0,0 +1,496 @@
+//! The variable allocation table (VAT), which TI-OS uses to track variables.
+//!
+//! Variable data is stored in user memory, beginning at userMem and growing
+//! upward, with the floating point stack immediately following it. The VAT begins
+//! at symTable and grows downward, with the operator stack immediately below it. The
+//! free RAM is the space between the two stacks.
+//!
+//! The VAT is divided in two: the symbol table from symTable down to progPtr holds
+//! variables with fixed names such as reals and lists, and the program table from
+//! progPtr down to pTemp holds programs, appvars and groups. Entries are stored at
+//! decreasing addresses from their first byte, which holds the variable type:
+//!
+//! | Offset | Contents                                  |
+//! |--------|-------------------------------------------|
+//! | 0      | Type (low 5 bits) and flags               |
+//! | -1     | Reserved                                  |
+//! | -2     | Version                                   |
+//! | -3     | Data pointer, low byte                    |
+//! | -4     | Data pointer, high byte                   |
+//! | -5     | Flash page if archived, otherwise 0       |
+//! | -6     | Name length (program table only)          |
+//! | -7     | Name, at decreasing addresses             |
+//!
+//! Names in the symbol table are always three bytes, starting at offset -6.
+//!
+//! See [WikiTI](https://wikiti.brandonw.net/index.php?title=83Plus:OS:Variable_Allocation_Table_(VAT))
+//! for more details.
+
+use crate::include::tios;
+use crate::tifiles::VariableType;
+use crate::Memory;
+use num_traits::FromPrimitive;
+
+/// An entry in the VAT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Address of the entry's first (highest) byte.
+    pub address: u16,
+    /// The type byte, including flags in the upper bits.
+    pub type_byte: u8,
+    pub version: u8,
+    /// Address of the variable's data.
+    pub data: u16,
+    /// Flash page holding the variable's data if archived, otherwise 0.
+    pub page: u8,
+    /// The variable's name. Names in the symbol table are padded with zeroes to
+    /// three bytes.
+    pub name: Box<[u8]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// There is not enough free RAM.
+    OutOfMemory,
+    /// A variable with the same name already exists.
+    Duplicate,
+    /// The name is the wrong length for the variable type.
+    InvalidName,
+}
+
+/// Size of the name of variables in the symbol table.
+const SYMBOL_NAME_LEN: usize = 3;
+/// Size of an entry, excluding its name.
+const ENTRY_HEADER_LEN: u16 = 6;
+
+impl Entry {
+    pub fn ty(&self) -> Option<VariableType> {
+        VariableType::from_u8(self.type_byte & 0x1F)
+    }
+
+    /// Return true if the variable's data is in RAM rather than archived.
+    pub fn in_ram(&self) -> bool {
+        self.page == 0
+    }
+
+    /// Get the size of this entry in the VAT.
+    fn len(&self) -> u16 {
+        let name_len = self.name.len() as u16;
+        if is_program_table_type(self.type_byte) {
+            ENTRY_HEADER_LEN + 1 + name_len
+        } else {
+            ENTRY_HEADER_LEN + name_len
+        }
+    }
+}
+
+/// Return true if variables of a type are stored in the program table.
+fn is_program_table_type(type_byte: u8) -> bool {
+    matches!(
+        VariableType::from_u8(type_byte & 0x1F),
+        Some(VariableType::Program)
+            | Some(VariableType::ProtectedProgram)
+            | Some(VariableType::AppVar)
+            | Some(VariableType::TemporaryProgram)
+            | Some(VariableType::Group)
+    )
+}
+
+/// Return true if variables of the given types are found by the same names, which
+/// is the case for programs regardless of protection.
+fn same_namespace(a: u8, b: u8) -> bool {
+    let is_program = |t: u8| matches!(t & 0x1F, 5 | 6 | 0x16);
+    match (is_program_table_type(a), is_program_table_type(b)) {
+        (true, true) => (a & 0x1F) == (b & 0x1F) || (is_program(a) && is_program(b)),
+        (false, false) => true,
+        _ => false,
+    }
+}
+
+/// Get the name of a symbol table variable as it is stored, padded with zeroes.
+fn symbol_name(name: &[u8]) -> [u8; SYMBOL_NAME_LEN] {
+    let mut out = [0; SYMBOL_NAME_LEN];
+    out[..name.len()].copy_from_slice(name);
+    out
+}
+
+/// Make the VAT empty and discard all variables.
+pub fn clear(mem: &mut Memory) {
+    mem.write_u16(tios::progPtr, tios::symTable);
+    mem.write_u16(tios::pTemp, tios::symTable);
+    mem.write_u16(tios::OPBase, tios::symTable);
+    mem.write_u16(tios::OPS, tios::symTable);
+    mem.write_u16(tios::newDataPtr, tios::userMem);
+    mem.write_u16(tios::fpBase, tios::userMem);
+    mem.write_u16(tios::FPS, tios::userMem);
+    // No program is running from userMem
+    mem.write_u16(tios::asm_prgm_size, 0);
+}
+
+/// Get the number of bytes of free RAM.
+///
+/// The pointers are in RAM where programs can change them, so if they are
+/// inconsistent there is no free RAM.
+pub fn free_ram(mem: &Memory) -> u16 {
+    mem.read_u16(tios::OPS)
+        .saturating_sub(mem.read_u16(tios::FPS))
+}
+
+/// Read the VAT entry beginning at the given address.
+///
+/// Addresses wrap around, so a corrupt VAT can't cause a panic.
+fn read_entry(mem: &Memory, address: u16, in_program_table: bool) -> Entry {
+    let at = |offset: u16| mem[address.wrapping_sub(offset)];
+    let name: Box<[u8]> = if in_program_table {
+        (0..at(6) as u16).map(|i| at(7 + i)).collect()
+    } else {
+        (0..SYMBOL_NAME_LEN as u16).map(|i| at(6 + i)).collect()
+    };
+
+    Entry {
+        address,
+        type_byte: at(0),
+        version: at(2),
+        data: at(3) as u16 | (at(4) as u16) << 8,
+        page: at(5),
+        name,
+    }
+}
+
+/// Get all of the entries in the VAT, in order from symTable downward.
+pub fn entries(mem: &Memory) -> Vec<Entry> {
+    let prog_ptr = mem.read_u16(tios::progPtr);
+    let p_temp = mem.read_u16(tios::pTemp);
+
+    let mut out = vec![];
+    let mut address = tios::symTable;
+    while address > p_temp {
+        let entry = read_entry(mem, address, address <= prog_ptr);
+        let len = if address <= prog_ptr {
+            ENTRY_HEADER_LEN + 1 + entry.name.len() as u16
+        } else {
+            ENTRY_HEADER_LEN + SYMBOL_NAME_LEN as u16
+        };
+        out.push(entry);
+        // pTemp is in RAM where programs can change it, so it might not stop the
+        // walk before the bottom of memory.
+        address = match address.checked_sub(len) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    out
+}
+
+/// Find a variable by type and name.
+///
+/// Programs are found regardless of whether they are protected, but other
+/// variables in the program table must match the type. Variables in the symbol
+/// table are identified only by their names, which need not be padded.
+pub fn find(mem: &Memory, ty: VariableType, name: &[u8]) -> Option<Entry> {
+    let padded;
+    let name = if is_program_table_type(ty as u8) || name.len() > SYMBOL_NAME_LEN {
+        name
+    } else {
+        padded = symbol_name(name);
+        &padded[..]
+    };
+
+    entries(mem)
+        .into_iter()
+        .find(|e| same_namespace(e.type_byte, ty as u8) && &e.name[..] == name)
+}
+
+/// Get the size of a variable's data, which depends on its type.
+pub fn data_size(mem: &Memory, entry: &Entry) -> u16 {
+    let read = |offset: u16| -> u8 {
+        if entry.in_ram() {
+            mem[entry.data + offset]
+        } else {
+            mem.read_paged(entry.page, entry.data + offset)
+        }
+    };
+    let word = || read(0) as u16 | (read(1) as u16) << 8;
+
+    match entry.ty() {
+        Some(VariableType::Real) => 9,
+        Some(VariableType::Complex) => 18,
+        Some(VariableType::List) => 2 + 9 * word(),
+        Some(VariableType::ComplexList) => 2 + 18 * word(),
+        Some(VariableType::Matrix) => 2 + 9 * read(0) as u16 * read(1) as u16,
+        _ => 2 + word(),
+    }
+}
+
+/// Move `len` bytes from `src` to `dst`, where the ranges may overlap.
+fn move_bytes(mem: &mut Memory, src: u16, dst: u16, len: u16) {
+    if dst < src {
+        for i in 0..len {
+            mem[dst + i] = mem[src + i];
+        }
+    } else {
+        for i in (0..len).rev() {
+            mem[dst + i] = mem[src + i];
+        }
+    }
+}
+
+/// Adjust the data pointers of variables in RAM at or after `at` by `delta`.
+fn adjust_data_pointers(mem: &mut Memory, at: u16, delta: i32) {
+    for entry in entries(mem) {
+        if entry.in_ram() && entry.data >= at {
+            let data = (entry.data as i32 + delta) as u16;
+            mem[entry.address - 3] = data as u8;
+            mem[entry.address - 4] = (data >> 8) as u8;
+        }
+    }
+    for &ptr in &[tios::newDataPtr, tios::fpBase, tios::FPS] {
+        let value = mem.read_u16(ptr);
+        if value >= at {
+            mem.write_u16(ptr, (value as i32 + delta) as u16);
+        }
+    }
+}
+
+/// Insert `count` bytes of space in user memory at `at`, like the InsertMem bcall.
+///
+/// Data following the insertion point moves up, and pointers to it are updated.
+/// The contents of the inserted space are unspecified.
+pub fn insert_mem(mem: &mut Memory, at: u16, count: u16) -> Result<(), Error> {
+    if free_ram(mem) < count {
+        return Err(Error::OutOfMemory);
+    }
+    let end = mem.read_u16(tios::FPS);
+    move_bytes(mem, at, at + count, end - at);
+    adjust_data_pointers(mem, at, count as i32);
+    Ok(())
+}
+
+/// Remove `count` bytes from user memory at `at`, like the DelMem bcall.
+pub fn delete_mem(mem: &mut Memory, at: u16, count: u16) {
+    let end = mem.read_u16(tios::FPS);
+    move_bytes(mem, at + count, at, end - at - count);
+    adjust_data_pointers(mem, at + count, -(count as i32));
+}
+
+/// Move the VAT entries (and operator stack) below `top` down by `count` bytes,
+/// making space for an entry that begins at `top`.
+fn grow_vat(mem: &mut Memory, top: u16, count: u16) {
+    let ops = mem.read_u16(tios::OPS);
+    move_bytes(mem, ops + 1, ops + 1 - count, top - ops);
+    for &ptr in &[tios::OPBase, tios::OPS, tios::pTemp] {
+        mem.write_u16(ptr, mem.read_u16(ptr) - count);
+    }
+}
+
+/// Create a variable with the given data, returning its VAT entry.
+///
+/// The data must be complete and in the format the OS expects, such as beginning
+/// with the size of a program.
+pub fn insert(
+    mem: &mut Memory,
+    ty: VariableType,
+    name: &[u8],
+    data: &[u8],
+) -> Result<Entry, Error> {
+    let in_program_table = is_program_table_type(ty as u8);
+    let max_name_len = if in_program_table { 8 } else { SYMBOL_NAME_LEN };
+    if name.is_empty() || name.len() > max_name_len {
+        return Err(Error::InvalidName);
+    }
+    if find(mem, ty, name).is_some() {
+        return Err(Error::Duplicate);
+    }
+
+    let entry_len = if in_program_table {
+        ENTRY_HEADER_LEN + 1 + name.len() as u16
+    } else {
+        ENTRY_HEADER_LEN + SYMBOL_NAME_LEN as u16
+    };
+    if (free_ram(mem) as usize) < data.len() + entry_len as usize {
+        return Err(Error::OutOfMemory);
+    }
+
+    // Data goes at the end of existing variables
+    let data_addr = mem.read_u16(tios::fpBase);
+    insert_mem(mem, data_addr, data.len() as u16)?;
+    for (i, &byte) in data.iter().enumerate() {
+        mem[data_addr + i as u16] = byte;
+    }
+
+    let address = if in_program_table {
+        mem.read_u16(tios::pTemp)
+    } else {
+        let prog_ptr = mem.read_u16(tios::progPtr);
+        mem.write_u16(tios::progPtr, prog_ptr - entry_len);
+        prog_ptr
+    };
+    grow_vat(mem, address, entry_len);
+
+    mem[address] = ty as u8;
+    mem[address - 1] = 0;
+    mem[address - 2] = 0;
+    mem[address - 3] = data_addr as u8;
+    mem[address - 4] = (data_addr >> 8) as u8;
+    mem[address - 5] = 0;
+    if in_program_table {
+        mem[address - 6] = name.len() as u8;
+        for (i, &c) in name.iter().enumerate() {
+            mem[address - 7 - i as u16] = c;
+        }
+    } else {
+        for (i, c) in symbol_name(name).iter().enumerate() {
+            mem[address - 6 - i as u16] = *c;
+        }
+    }
+
+    debug!(
+        "Created {:?} {:?} at {:04X} with data at {:04X}",
+        ty,
+        String::from_utf8_lossy(name),
+        address,
+        data_addr
+    );
+    Ok(read_entry(mem, address, in_program_table))
+}
+
+/// Change the size of a variable's data to `new_size` bytes, adding or removing
+/// bytes at the end.
+///
+/// Any size fields in the data are not updated, so the caller should update them
+/// to match.
+pub fn resize(mem: &mut Memory, entry: &Entry, new_size: u16) -> Result<(), Error> {
+    assert!(entry.in_ram(), "Archived variables cannot be resized");
+    let size = data_size(mem, entry);
+    if new_size > size {
+        insert_mem(mem, entry.data + size, new_size - size)
+    } else {
+        delete_mem(mem, entry.data + new_size, size - new_size);
+        Ok(())
+    }
+}
+
+/// Delete a variable, freeing its data if it is in RAM.
+///
+/// Entries previously returned by other functions may be invalidated, because
+/// variables may move.
+pub fn delete(mem: &mut Memory, entry: &Entry) {
+    if entry.in_ram() {
+        let size = data_size(mem, entry);
+        delete_mem(mem, entry.data, size);
+    }
+
+    // Close the gap in the VAT
+    let len = entry.len();
+    let ops = mem.read_u16(tios::OPS);
+    move_bytes(
+        mem,
+        ops + 1,
+        ops + 1 + len,
+        entry.address + 1 - len - (ops + 1),
+    );
+    for &ptr in &[tios::OPBase, tios::OPS, tios::pTemp] {
+        mem.write_u16(ptr, mem.read_u16(ptr) + len);
+    }
+    let prog_ptr = mem.read_u16(tios::progPtr);
+    if entry.address > prog_ptr {
+        mem.write_u16(tios::progPtr, prog_ptr + len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data_size, delete, entries, find, insert, resize, Error};
+    use crate::include::tios;
+    use crate::tifiles::VariableType;
+    use crate::{Memory, Model};
+
+    fn memory() -> Memory {
+        let flash: &[(u8, &[u8])] = &[];
+        let mut mem = Memory::new(Model::TI83Plus, flash).unwrap();
+        super::clear(&mut mem);
+        mem
+    }
+
+    #[test]
+    fn entry_layout() {
+        let mut mem = memory();
+        let prog = insert(
+            &mut mem,
+            VariableType::Program,
+            b"PROG",
+            &[2, 0, 0xC9, 0xC9],
+        )
+        .unwrap();
+        assert_eq!(prog.address, tios::symTable);
+        assert_eq!(prog.data, tios::userMem);
+        assert_eq!(
+            &mem[tios::symTable - 10..tios::symTable + 1],
+            &[b'G', b'O', b'R', b'P', 4, 0, 0x9D, 0x95, 0, 0, 5]
+        );
+        assert_eq!(mem.read_u16(tios::pTemp), tios::symTable - 11);
+
+        // Symbol table entries go above the program table
+        let real = insert(&mut mem, VariableType::Real, b"A", &[0; 9]).unwrap();
+        assert_eq!(real.address, tios::symTable);
+        assert_eq!(real.data, tios::userMem + 4);
+        assert_eq!(
+            &mem[tios::symTable - 8..tios::symTable + 1],
+            &[0, 0, b'A', 0, 0x9D, 0x99, 0, 0, 0]
+        );
+        assert_eq!(mem.read_u16(tios::progPtr), tios::symTable - 9);
+        assert_eq!(mem.read_u16(tios::FPS), tios::userMem + 13);
+
+        let entries = entries(&mem);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(&entries[1].name[..], b"PROG");
+        assert_eq!(entries[1].address, tios::symTable - 9);
+        assert_eq!(
+            find(&mem, VariableType::ProtectedProgram, b"PROG"),
+            Some(entries[1].clone())
+        );
+        assert_eq!(find(&mem, VariableType::AppVar, b"PROG"), None);
+        assert_eq!(
+            insert(&mut mem, VariableType::Program, b"PROG", &[0, 0]),
+            Err(Error::Duplicate)
+        );
+    }
+
+    #[test]
+    fn resize_and_delete() {
+        let mut mem = memory();
+        let a = insert(&mut mem, VariableType::AppVar, b"A", &[1, 0, 0xAA]).unwrap();
+        insert(&mut mem, VariableType::AppVar, b"B", &[1, 0, 0xBB]).unwrap();
+
+        resize(&mut mem, &a, 5).unwrap();
+        mem.write_u16(a.data, 3);
+        let b = find(&mem, VariableType::AppVar, b"B").unwrap();
+        assert_eq!(b.data, tios::userMem + 5);
+        assert_eq!(mem[b.data + 2], 0xBB);
+        assert_eq!(data_size(&mem, &a), 5);
+
+        delete(&mut mem, &a);
+        let b = find(&mem, VariableType::AppVar, b"B").unwrap();
+        assert_eq!(b.address, tios::symTable);
+        assert_eq!(b.data, tios::userMem);
+        assert_eq!(mem[b.data + 2], 0xBB);
+        assert_eq!(entries(&mem).len(), 1);
+        assert_eq!(mem.read_u16(tios::FPS), tios::userMem + 3);
+        assert_eq!(mem.read_u16(tios::pTemp), tios::symTable - 8);
+    }
+
+    #[test]
+    fn corrupt_pointers() {
+        let mut mem = memory();
+        insert(&mut mem, VariableType::AppVar, b"A", &[1, 0, 0xAA]).unwrap();
+
+        // Stacks that have collided leave no free RAM
+        mem.write_u16(tios::FPS, mem.read_u16(tios::OPS) + 1);
+        assert_eq!(super::free_ram(&mem), 0);
+
+        // The walk stops at the bottom of memory
+        mem.write_u16(tios::pTemp, 0);
+        mem.write_u16(tios::progPtr, tios::symTable);
+        assert!(entries(&mem).len() > 1);
+    }
+}
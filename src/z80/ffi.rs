@@ -40,8 +40,14 @@ pub struct State {
 }
 
 impl State {
+    /// Set the interrupt flip-flops, as if by EI or DI.
     pub fn set_interrupt_enable(&mut self, enable: bool) {
-        self.internal.ei = if enable { 1 } else { 0 };
+        self.internal.iff1 = enable as u8;
+        self.internal.iff2 = enable as u8;
+    }
+
+    pub fn interrupts_enabled(&self) -> bool {
+        self.internal.iff1 != 0
     }
 
     pub fn set_im(&mut self, mode: u8) {
@@ -78,7 +84,11 @@ impl std::fmt::Debug for State {
                 "PC  {:04X}    SP  {:04X}    Intr {:>3}\n",
                 self.pc,
                 self.sp,
-                if self.internal.ei != 0 { "ON" } else { "OFF" }
+                if self.interrupts_enabled() {
+                    "ON"
+                } else {
+                    "OFF"
+                }
             )?;
             write!(f, "Flags")?;
             for (mask, set, unset) in FLAGS {
@@ -108,6 +108,11 @@ impl Z80 {
         unsafe { ffi::z80_run(&mut self.z80 as *mut _, cycles) }
     }
 
+    /// Get the number of cycles executed so far in the current (or last) call to [run].
+    pub fn cycles_run(&self) -> usize {
+        self.z80.cycles
+    }
+
     pub fn regs_mut(&mut self) -> &mut ffi::State {
         &mut self.z80.regs
     }
@@ -126,6 +131,11 @@ impl Z80 {
         regs.af |= flags.bits() as u16;
     }
 
+    /// Reset the CPU, as if by its reset line.
+    pub fn reset(&mut self) {
+        unsafe { ffi::z80_reset(&mut self.z80 as *mut _) }
+    }
+
     pub fn set_irq(&mut self, pending: bool) {
         unsafe { ffi::z80_int(&mut self.z80 as *mut _, pending as u8) }
     }
@@ -139,3 +149,38 @@ impl Z80 {
         self.z80.yield_requested = true as u8;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Z80;
+    use crate::{Emulator, Model};
+
+    #[test]
+    fn ei_yields() {
+        let mut emu = Emulator::new();
+        let mut cpu = Z80::new();
+        // ei \ nop \ ei \ jr $
+        for (i, &b) in [0xFB, 0x00, 0xFB, 0x18, 0xFE].iter().enumerate() {
+            emu.mem[0x8000 + i as u16] = b;
+        }
+        cpu.regs_mut().pc = 0x8000;
+
+        assert_eq!(cpu.run(1000, &mut emu), 4);
+        assert!(cpu.regs().interrupts_enabled());
+        // EI with interrupts already enabled doesn't yield
+        assert!(cpu.run(1000, &mut emu) >= 1000);
+    }
+
+    #[test]
+    fn user_os_ignores_traps() {
+        // Trap 0000 (reset)
+        let page: &[u8] = &[0xED, 0x25, 0x00, 0x00];
+        let mut emu = Emulator::with_flash_pages(Model::TI83Plus, &[(0, page)]).unwrap();
+        let mut cpu = Z80::new();
+        assert!(emu.is_running());
+
+        // Executes as an 8-cycle illegal instruction, then the trap number as nops
+        assert_eq!(cpu.run(16, &mut emu), 16);
+        assert_eq!(cpu.regs().pc, 4);
+    }
+}
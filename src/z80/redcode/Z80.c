@@ -1037,7 +1037,8 @@ INSTRUCTION(V_vXYOFFSET)   {zuint16 a = (zuint16)(XY + READ_OFFSET((PC += 3) - 1
 INSTRUCTION(nop)  {PC++;			     return 4;}
 INSTRUCTION(halt) {HALT = 1; SET_HALT;		     return 4;}
 INSTRUCTION(di)	  {PC++; IFF1 = IFF2 = 0; EI = TRUE; return 4;}
-INSTRUCTION(ei)	  {PC++; IFF1 = IFF2 = 1; EI = TRUE; return 4;}
+/* tihle: enabling interrupts yields so the emulator can schedule any that are pending. */
+INSTRUCTION(ei)	  {PC++; if (!IFF1) object->yield_requested = TRUE; IFF1 = IFF2 = 1; EI = TRUE; return 4;}
 INSTRUCTION(im_0) {PC += 2; IM = 0;		     return 8;}
 INSTRUCTION(im_1) {PC += 2; IM = 1;		     return 8;}
 INSTRUCTION(im_2) {PC += 2; IM = 2;		     return 8;}
@@ -64,7 +64,8 @@ typedef struct {
 	 *
 	 * This field is tested after each instruction, and if nonzero
 	 * the core will yield back to the caller after clearing it
-	 * back to zero.
+	 * back to zero. The core sets it when an EI instruction enables
+	 * interrupts.
 	 */
   zuint8 yield_requested;
 